@@ -0,0 +1,189 @@
+use std::{
+    f32::consts::{FRAC_PI_4, SQRT_2},
+    path::Path,
+};
+#[cfg(feature = "audio")]
+use std::{fs, io::Cursor, sync::Arc};
+
+#[cfg(feature = "audio")]
+use ahash::HashMap;
+#[cfg(feature = "audio")]
+use rand::seq::SliceRandom;
+#[cfg(feature = "audio")]
+use rodio::{source::ChannelVolume, OutputStream, OutputStreamHandle, Source};
+
+use crate::{camera::Camera, world::SoundGroup};
+
+/// What happened to a block to make a sound.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SoundKind {
+    Break,
+    Place,
+    Step,
+}
+
+impl SoundKind {
+    /// Start of the names of the sound's files.
+    #[cfg_attr(not(feature = "audio"), allow(dead_code))]
+    fn file_prefix(self) -> &'static str {
+        match self {
+            Self::Break => "break",
+            Self::Place => "place",
+            Self::Step => "step",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SoundId {
+    pub group: SoundGroup,
+    pub kind: SoundKind,
+}
+
+impl SoundId {
+    pub fn new(group: SoundGroup, kind: SoundKind) -> Self {
+        Self { group, kind }
+    }
+}
+
+/// Directory of a group's sounds, `None` for silent blocks.
+#[cfg_attr(not(feature = "audio"), allow(dead_code))]
+fn group_dir(group: SoundGroup) -> Option<&'static str> {
+    match group {
+        SoundGroup::None => None,
+        SoundGroup::Stone => Some("stone"),
+        SoundGroup::Gravel => Some("gravel"),
+        SoundGroup::Grass => Some("grass"),
+    }
+}
+
+/// Plays block sounds, fading and panning the ones in the world by where they are relative to
+/// the camera. Without the `audio` feature, or without an audio device, nothing is played.
+pub struct Audio {
+    /// Multiplier of the volume of every sound.
+    pub volume: f32,
+    /// Distance in blocks at which sounds in the world fade out completely.
+    pub max_distance: f32,
+    listener_pos: glam::Vec3,
+    /// Direction of the listener's right ear.
+    listener_right: glam::Vec3,
+
+    /// Has to be kept alive for the handle to play anything.
+    #[cfg(feature = "audio")]
+    output: Option<(OutputStream, OutputStreamHandle)>,
+    /// Encoded variants of each sound, one of them picked at random whenever it's played.
+    #[cfg(feature = "audio")]
+    sounds: HashMap<SoundId, Vec<Arc<[u8]>>>,
+}
+
+impl Audio {
+    /// Opens the default audio device and loads the sounds in `dir`, named like
+    /// `stone/break1.ogg` after their group and kind. Missing sounds are skipped.
+    pub fn new(dir: &Path) -> Self {
+        #[cfg(not(feature = "audio"))]
+        let _ = dir;
+        Self {
+            volume: 1.0,
+            max_distance: 16.0,
+            listener_pos: glam::Vec3::ZERO,
+            listener_right: glam::Vec3::X,
+
+            #[cfg(feature = "audio")]
+            output: (OutputStream::try_default())
+                .inspect_err(|err| tracing::warn!("No audio output, sounds are muted: {err}"))
+                .ok(),
+            #[cfg(feature = "audio")]
+            sounds: Self::load_sounds(dir),
+        }
+    }
+
+    #[cfg(feature = "audio")]
+    fn load_sounds(dir: &Path) -> HashMap<SoundId, Vec<Arc<[u8]>>> {
+        let mut sounds = HashMap::<_, Vec<_>>::default();
+        let groups = [SoundGroup::Stone, SoundGroup::Gravel, SoundGroup::Grass];
+        for group in groups {
+            let Some(entries) = group_dir(group).and_then(|name| fs::read_dir(dir.join(name)).ok())
+            else {
+                continue;
+            };
+            let mut paths: Vec<_> = entries.filter_map(|e| e.ok().map(|e| e.path())).collect();
+            paths.sort();
+            for path in paths {
+                let is_audio = (path.extension()).is_some_and(|ext| ext == "ogg" || ext == "wav");
+                let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+                    continue;
+                };
+                let kind = [SoundKind::Break, SoundKind::Place, SoundKind::Step]
+                    .into_iter()
+                    .find(|kind| name.starts_with(kind.file_prefix()));
+                let (true, Some(kind)) = (is_audio, kind) else {
+                    continue;
+                };
+                match fs::read(&path) {
+                    Ok(bytes) => (sounds.entry(SoundId::new(group, kind)).or_default())
+                        .push(Arc::from(bytes)),
+                    Err(err) => tracing::warn!("Failed to read {}: {err}", path.display()),
+                }
+            }
+        }
+        if sounds.is_empty() {
+            tracing::warn!("No sounds found in {}", dir.display());
+        }
+        sounds
+    }
+
+    /// Moves the ear sounds in the world are heard from to the camera.
+    pub fn set_listener(&mut self, cam: &Camera) {
+        self.listener_pos = cam.pos;
+        self.listener_right = cam.forward_xz().cross(glam::Vec3::Y);
+    }
+
+    /// Plays `id` at `volume` without placing it anywhere, like sounds of the player.
+    pub fn play(&self, id: SoundId, volume: f32) {
+        self.play_panned(id, volume, [1.0, 1.0]);
+    }
+
+    /// Plays `id` coming from `pos`, relative to the origin like the camera. It's quieter the
+    /// further away it is and louder in the ear facing it.
+    pub fn play_at(&self, id: SoundId, volume: f32, pos: glam::Vec3) {
+        let offset = pos - self.listener_pos;
+        let fade = (1.0 - offset.length() / self.max_distance).max(0.0);
+        if fade == 0.0 {
+            return;
+        }
+        // constant power panning, both ears at full volume when it's straight ahead or behind
+        let pan = offset.normalize_or_zero().dot(self.listener_right);
+        let angle = (pan + 1.0) * FRAC_PI_4;
+        let ears = [angle.cos(), angle.sin()].map(|ear| ear * SQRT_2);
+        self.play_panned(id, volume * fade * fade, ears);
+    }
+
+    /// Plays a random variant of `id` with the volume of the left and right channels.
+    fn play_panned(&self, id: SoundId, volume: f32, ears: [f32; 2]) {
+        #[cfg(not(feature = "audio"))]
+        let _ = (id, volume, ears);
+        #[cfg(feature = "audio")]
+        {
+            let Some((_, handle)) = &self.output else {
+                return;
+            };
+            let Some(bytes) =
+                (self.sounds.get(&id)).and_then(|v| v.choose(&mut rand::thread_rng()))
+            else {
+                return;
+            };
+            let source = match rodio::Decoder::new(Cursor::new(bytes.clone())) {
+                Ok(source) => source,
+                Err(err) => {
+                    tracing::warn!("Failed to decode {id:?}: {err}");
+                    return;
+                }
+            };
+            let volumes = ears.map(|ear| ear * volume * self.volume).to_vec();
+            let source = ChannelVolume::new(source, volumes).convert_samples();
+            if let Err(err) = handle.play_raw(source) {
+                tracing::warn!("Failed to play {id:?}: {err}");
+            }
+        }
+    }
+}
@@ -1,29 +1,109 @@
+use std::f32::consts::TAU;
+
+use ahash::HashMap;
 use winit::{
     event::*,
     keyboard::{KeyCode, PhysicalKey},
 };
 
+use crate::world::{BlockFace, World};
+
 #[derive(Debug)]
 pub struct Camera {
     pub pos: glam::Vec3,
     pub rot: glam::Vec2,
     pub aspect: f32,
+    /// Vertical field of view in degrees, see `set_fov`.
     pub fovy: f32,
     pub znear: f32,
     pub zfar: f32,
+    /// Transient offset of the eye from `pos`, like the view bob. Only moves the rendered view,
+    /// not `pos` or `dir` used for targeting.
+    pub view_offset: glam::Vec3,
+    /// Transient widening of `fovy` in degrees, like the sprint zoom.
+    pub fov_offset: f32,
 }
 
 impl Camera {
+    /// Unit vector the camera looks along, the same one the view matrix is built from. It's the
+    /// negated `(cos x sin y, sin x, cos x cos y)` of `rot`, so `rot == 0` looks down -Z.
     pub fn dir(&self) -> glam::Vec3 {
         let (sin_x, cos_x) = self.rot.x.to_radians().sin_cos();
         let (sin_y, cos_y) = self.rot.y.to_radians().sin_cos();
         -glam::vec3(cos_x * sin_y, sin_x, cos_x * cos_y)
     }
+    /// `dir` flattened onto the horizontal plane, for walking. Only depends on the yaw so it
+    /// stays well defined when looking straight up or down.
+    pub fn forward_xz(&self) -> glam::Vec3 {
+        let (sin_y, cos_y) = self.rot.y.to_radians().sin_cos();
+        -glam::vec3(sin_y, 0.0, cos_y)
+    }
+    /// Face of a block the camera looks towards: the vertical one when looking more than 45°
+    /// up or down, else the horizontal one nearest the yaw.
+    pub fn facing(&self) -> BlockFace {
+        match self.rot.x.abs() {
+            pitch if 45.0 < pitch => BlockFace::nearest(self.dir() * glam::Vec3::Y),
+            _ => self.horizontal_facing(),
+        }
+    }
+    /// The horizontal face nearest the direction the camera looks, ignoring its pitch.
+    pub fn horizontal_facing(&self) -> BlockFace {
+        BlockFace::nearest(self.forward_xz())
+    }
+    /// Sets the vertical field of view in degrees, clamped to what the projection can handle.
+    pub fn set_fov(&mut self, degrees: f32) {
+        self.fovy = degrees.clamp(Self::MIN_FOV, Self::MAX_FOV);
+    }
+    const MIN_FOV: f32 = 10.0;
+    const MAX_FOV: f32 = 160.0;
+    /// Position the scene is rendered from.
+    pub fn eye(&self) -> glam::Vec3 {
+        self.pos + self.view_offset
+    }
     fn build_view_projection_matrix(&self) -> glam::Mat4 {
-        let view = glam::Mat4::look_to_rh(self.pos, self.dir(), glam::Vec3::Y);
-        let proj = glam::Mat4::perspective_rh(self.fovy, self.aspect, self.znear, self.zfar);
+        let view = glam::Mat4::look_to_rh(self.eye(), self.dir(), glam::Vec3::Y);
+        // reversed-Z, with depth going from 1 at the near plane to 0 at the far one, which spreads
+        // the float precision of the depth buffer more evenly over the distance
+        let fovy = (self.fovy + self.fov_offset).clamp(Self::MIN_FOV, Self::MAX_FOV);
+        let proj =
+            glam::Mat4::perspective_rh(fovy.to_radians(), self.aspect, self.zfar, self.znear);
         proj * view
     }
+    /// The volume the camera sees, in the same space as `pos`.
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_view_proj(self.build_view_projection_matrix())
+    }
+}
+
+/// Six planes bounding what a camera sees, each pointing inwards. A plane `p` keeps the points
+/// where `p.xyz · point + p.w >= 0`.
+#[derive(Debug, Copy, Clone)]
+pub struct Frustum {
+    /// Left, right, bottom, top, and the planes at depth 0 and 1, which are the far and near
+    /// planes with reversed-Z.
+    pub planes: [glam::Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a view projection matrix with wgpu's clip space, where depth goes
+    /// from 0 to 1.
+    pub fn from_view_proj(view_proj: glam::Mat4) -> Self {
+        let [x, y, z, w] = [0, 1, 2, 3].map(|i| view_proj.row(i));
+        let planes =
+            [w + x, w - x, w + y, w - y, z, w - z].map(|plane| plane / plane.truncate().length());
+        Self { planes }
+    }
+
+    /// Whether any part of the box from `min` to `max` may be inside. Boxes near the corners
+    /// can pass without being seen, but boxes that are seen never fail.
+    pub fn intersects_aabb(&self, min: glam::Vec3, max: glam::Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            // the corner furthest along the plane's normal
+            let normal = plane.truncate();
+            let corner = glam::Vec3::select(normal.cmpge(glam::Vec3::ZERO), max, min);
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
 }
 
 #[repr(C)]
@@ -32,6 +112,7 @@ pub struct CameraUniform {
     pub view_proj: glam::Mat4,
     pub view_pos: glam::Vec3,
     pub _pad1: u32,
+    pub inv_view_proj: glam::Mat4,
 }
 
 impl CameraUniform {
@@ -40,12 +121,188 @@ impl CameraUniform {
             view_proj: glam::Mat4::IDENTITY,
             view_pos: glam::Vec3::ZERO,
             _pad1: 0,
+            inv_view_proj: glam::Mat4::IDENTITY,
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera) {
         self.view_proj = camera.build_view_projection_matrix();
-        self.view_pos = camera.pos;
+        self.view_pos = camera.eye();
+        self.inv_view_proj = self.view_proj.inverse();
+    }
+}
+
+/// Sway of the view while moving horizontally.
+#[derive(Debug, Copy, Clone)]
+pub struct ViewBob {
+    /// Turn off for motion sensitive players.
+    pub enabled: bool,
+    /// Vertical sway in blocks at full speed, the sideways sway is half of it.
+    pub amplitude: f32,
+    /// Steps per block travelled, each step is one vertical bob.
+    pub frequency: f32,
+}
+
+impl Default for ViewBob {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            amplitude: 0.05,
+            frequency: 0.3,
+        }
+    }
+}
+
+/// Running faster while the sprint key is held and moving forward, widening the view to sell
+/// the speed.
+#[derive(Debug, Copy, Clone)]
+pub struct Sprint {
+    /// Factor the movement speed is scaled by at full sprint.
+    pub speed_multiplier: f32,
+    /// Degrees added to the field of view at full sprint.
+    pub fov_increase: f32,
+}
+
+impl Default for Sprint {
+    fn default() -> Self {
+        Self {
+            speed_multiplier: 1.3,
+            fov_increase: 10.0,
+        }
+    }
+}
+
+/// Logical movement a key can be bound to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MoveAction {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Up,
+    Down,
+    Sprint,
+    /// Switches between flying through blocks and walking with collisions.
+    ToggleNoclip,
+}
+
+/// Keys bound to each movement, so players on other layouts can remap them.
+#[derive(Debug, Clone)]
+pub struct KeyBindings {
+    keys: HashMap<KeyCode, MoveAction>,
+}
+
+impl Default for KeyBindings {
+    /// WASD and the arrow keys, space to go up, left shift to go down, left control to sprint
+    /// and V to toggle noclip.
+    fn default() -> Self {
+        let keys = [
+            (KeyCode::KeyW, MoveAction::Forward),
+            (KeyCode::ArrowUp, MoveAction::Forward),
+            (KeyCode::KeyS, MoveAction::Back),
+            (KeyCode::ArrowDown, MoveAction::Back),
+            (KeyCode::KeyA, MoveAction::Left),
+            (KeyCode::ArrowLeft, MoveAction::Left),
+            (KeyCode::KeyD, MoveAction::Right),
+            (KeyCode::ArrowRight, MoveAction::Right),
+            (KeyCode::Space, MoveAction::Up),
+            (KeyCode::ShiftLeft, MoveAction::Down),
+            (KeyCode::ControlLeft, MoveAction::Sprint),
+            (KeyCode::KeyV, MoveAction::ToggleNoclip),
+        ];
+        Self {
+            keys: keys.into_iter().collect(),
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn action(&self, key: KeyCode) -> Option<MoveAction> {
+        self.keys.get(&key).copied()
+    }
+
+    /// Binds `key` to `action`, replacing the keys bound to `action` and whatever `key` was
+    /// bound to.
+    pub fn set(&mut self, action: MoveAction, key: KeyCode) {
+        self.keys.retain(|_, bound| *bound != action);
+        self.keys.insert(key, action);
+    }
+}
+
+/// How gamepad sticks move and turn the camera.
+#[derive(Debug, Copy, Clone)]
+pub struct GamepadSettings {
+    /// Stick deflection ignored around the center, so stick drift doesn't creep.
+    pub deadzone: f32,
+    /// Power curve applied to the deflection past the deadzone, for finer control near it.
+    pub exponent: f32,
+    /// Turning speed at full deflection of the right stick, in degrees per second.
+    pub look_speed: f32,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        Self {
+            deadzone: 0.15,
+            exponent: 2.0,
+            look_speed: 180.0,
+        }
+    }
+}
+
+impl GamepadSettings {
+    /// Rescales a stick's deflection from the edge of the deadzone to the rim.
+    pub fn scale(&self, stick: glam::Vec2) -> glam::Vec2 {
+        let len = stick.length();
+        if len <= self.deadzone {
+            return glam::Vec2::ZERO;
+        }
+        let scaled = ((len - self.deadzone) / (1.0 - self.deadzone)).min(1.0);
+        stick / len * scaled.powf(self.exponent)
+    }
+}
+
+/// Walking with collisions and gravity instead of flying through blocks.
+#[derive(Debug, Copy, Clone)]
+pub struct Physics {
+    pub enabled: bool,
+    /// Size of the player's box, standing on its bottom face.
+    pub size: glam::Vec3,
+    /// Height of the camera above the bottom of the box.
+    pub eye_height: f32,
+    /// Horizontal speed in blocks per second.
+    pub walk_speed: f32,
+    /// Downward acceleration in blocks per second squared.
+    pub gravity: f32,
+    /// Upward speed a jump starts with, in blocks per second.
+    pub jump_speed: f32,
+    /// Fastest the player can fall, in blocks per second.
+    pub terminal_speed: f32,
+    /// Distance in blocks walked on the ground between footstep sounds.
+    pub step_length: f32,
+}
+
+impl Default for Physics {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            size: glam::vec3(0.6, 1.8, 0.6),
+            eye_height: 1.62,
+            walk_speed: 4.3,
+            gravity: 32.0,
+            jump_speed: 9.0,
+            terminal_speed: 78.4,
+            step_length: 1.6,
+        }
+    }
+}
+
+impl Physics {
+    /// Lowest and highest corners of the player's box with the camera at `eye`.
+    pub fn aabb(&self, eye: glam::Vec3) -> (glam::Vec3, glam::Vec3) {
+        let feet = eye - self.eye_height * glam::Vec3::Y;
+        let min = feet - glam::vec3(self.size.x / 2.0, 0.0, self.size.z / 2.0);
+        (min, min + self.size)
     }
 }
 
@@ -55,7 +312,47 @@ pub struct CameraController {
     vel: glam::Vec3,
     im_vel: glam::Vec3,
 
+    /// Rotation still to be applied, in hundredths of a degree.
     ang_vel: glam::Vec2,
+    /// Time constant of the mouse look in seconds, the camera turning about two thirds of the
+    /// way to where the mouse points in this time. 0 turns instantly.
+    pub rotation_smoothing: f32,
+
+    /// Number of mouse motion events discarded after the cursor is (re)grabbed, as the first
+    /// delta often contains the cursor warping to the center of the window.
+    pub ignored_motion_events: u32,
+    pending_ignored_motion: u32,
+
+    pub view_bob: ViewBob,
+    /// Progress through a pair of steps, from 0 to 1.
+    bob_phase: f32,
+    /// Strength of the bob, easing towards the horizontal speed so it doesn't stop abruptly.
+    bob_strength: f32,
+
+    pub sprint: Sprint,
+    sprinting: bool,
+    /// How far into the sprint the camera is, easing from 0 to 1 and back.
+    sprint_amount: f32,
+
+    pub key_bindings: KeyBindings,
+
+    pub gamepad: GamepadSettings,
+    /// Raw deflection of the left and right sticks.
+    #[cfg(feature = "gamepad")]
+    move_stick: glam::Vec2,
+    look_stick: glam::Vec2,
+    /// How far the gamepad's up and down inputs are pressed, from 0 to 1.
+    #[cfg(feature = "gamepad")]
+    gamepad_up: f32,
+    #[cfg(feature = "gamepad")]
+    gamepad_down: f32,
+
+    pub physics: Physics,
+    /// Vertical speed while physics is enabled, in blocks per second.
+    fall_speed: f32,
+    on_ground: bool,
+    /// Distance walked on the ground since the last footstep.
+    step_distance: f32,
 }
 
 impl CameraController {
@@ -67,11 +364,75 @@ impl CameraController {
             im_vel: glam::Vec3::ZERO,
 
             ang_vel: glam::Vec2::ZERO,
+            rotation_smoothing: 0.0,
+
+            ignored_motion_events: 1,
+            pending_ignored_motion: 1,
+
+            view_bob: ViewBob::default(),
+            bob_phase: 0.0,
+            bob_strength: 0.0,
+
+            sprint: Sprint::default(),
+            sprinting: false,
+            sprint_amount: 0.0,
+
+            key_bindings: KeyBindings::default(),
+
+            gamepad: GamepadSettings::default(),
+            #[cfg(feature = "gamepad")]
+            move_stick: glam::Vec2::ZERO,
+            look_stick: glam::Vec2::ZERO,
+            #[cfg(feature = "gamepad")]
+            gamepad_up: 0.0,
+            #[cfg(feature = "gamepad")]
+            gamepad_down: 0.0,
+
+            physics: Physics::default(),
+            fall_speed: 0.0,
+            on_ground: false,
+            step_distance: 0.0,
         }
     }
 
+    /// Sets how far the view turns per unit of mouse motion.
+    pub fn set_sensitivity(&mut self, sensitivity: f32) {
+        self.sensitivity = sensitivity;
+    }
+
+    /// The player's box around `camera`, `None` when flying through blocks without physics.
+    pub fn player_aabb(&self, camera: &Camera) -> Option<(glam::Vec3, glam::Vec3)> {
+        (self.physics.enabled).then(|| self.physics.aabb(camera.pos))
+    }
+
+    /// Switches between flying through blocks and walking with collisions, keeping the camera
+    /// where it is. Walking starts out falling, and flying starts out hovering.
+    pub fn toggle_noclip(&mut self) {
+        self.physics.enabled = !self.physics.enabled;
+        self.fall_speed = 0.0;
+        self.on_ground = false;
+        self.step_distance = 0.0;
+        self.vel.y = 0.0;
+        tracing::info!("Physics enabled: {}", self.physics.enabled);
+    }
+
+    /// Binds `key` to `action`, see [`KeyBindings::set`].
+    pub fn set_binding(&mut self, action: MoveAction, key: KeyCode) {
+        self.key_bindings.set(action, key);
+    }
+
+    /// Call after grabbing the cursor so the next mouse deltas don't snap the camera.
+    pub fn cursor_grabbed(&mut self) {
+        self.pending_ignored_motion = self.ignored_motion_events;
+        self.ang_vel = glam::Vec2::ZERO;
+    }
+
     pub fn device_event(&mut self, event: &DeviceEvent) -> bool {
         match event {
+            DeviceEvent::MouseMotion { .. } if 0 < self.pending_ignored_motion => {
+                self.pending_ignored_motion -= 1;
+                true
+            }
             DeviceEvent::MouseMotion { delta } => {
                 self.ang_vel.x += delta.1 as f32 * self.sensitivity;
                 self.ang_vel.y -= delta.0 as f32 * self.sensitivity;
@@ -82,8 +443,62 @@ impl CameraController {
         }
     }
 
+    /// Left stick moves, right stick turns, the right trigger and bumper go up and the left ones
+    /// go down.
+    #[cfg(feature = "gamepad")]
+    pub fn gamepad_event(&mut self, event: &gilrs::Event) -> bool {
+        use gilrs::{Axis, Button, EventType};
+
+        match event.event {
+            EventType::AxisChanged(axis, value, _) => {
+                match axis {
+                    Axis::LeftStickX => self.move_stick.x = value,
+                    Axis::LeftStickY => self.move_stick.y = value,
+                    Axis::RightStickX => self.look_stick.x = value,
+                    Axis::RightStickY => self.look_stick.y = value,
+                    _ => return false,
+                }
+                if matches!(axis, Axis::LeftStickX | Axis::LeftStickY) {
+                    let stick = self.gamepad.scale(self.move_stick);
+                    self.im_vel.x = stick.x;
+                    self.im_vel.z = -stick.y;
+                }
+                true
+            }
+            EventType::ButtonChanged(button, value, _) => {
+                match button {
+                    Button::RightTrigger2 | Button::RightTrigger => self.gamepad_up = value,
+                    Button::LeftTrigger2 | Button::LeftTrigger => self.gamepad_down = value,
+                    _ => return false,
+                }
+                self.im_vel.y = (self.gamepad_up - self.gamepad_down).clamp(-1.0, 1.0);
+                true
+            }
+            EventType::Disconnected => {
+                self.move_stick = glam::Vec2::ZERO;
+                self.look_stick = glam::Vec2::ZERO;
+                self.gamepad_up = 0.0;
+                self.gamepad_down = 0.0;
+                self.im_vel = glam::Vec3::ZERO;
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn window_event(&mut self, event: &WindowEvent) -> bool {
         match event {
+            WindowEvent::Focused(true) => {
+                self.cursor_grabbed();
+                false
+            }
+            WindowEvent::Focused(false) => {
+                // keys released while unfocused never arrive, so stop instead of moving forever
+                self.im_vel = glam::Vec3::ZERO;
+                self.ang_vel = glam::Vec2::ZERO;
+                self.sprinting = false;
+                false
+            }
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
@@ -94,43 +509,49 @@ impl CameraController {
                     },
                 ..
             } => {
-                let offset = match state {
-                    ElementState::Pressed => 1.0,
-                    ElementState::Released => -1.0,
+                let PhysicalKey::Code(key) = *physical_key else {
+                    return false;
                 };
-                match physical_key {
-                    PhysicalKey::Code(KeyCode::KeyW | KeyCode::ArrowUp) => {
-                        self.im_vel.z = (self.im_vel.z - offset).clamp(-1.0, 1.0);
-                        true
-                    }
-                    PhysicalKey::Code(KeyCode::KeyS | KeyCode::ArrowDown) => {
-                        self.im_vel.z = (self.im_vel.z + offset).clamp(-1.0, 1.0);
-                        true
-                    }
-                    PhysicalKey::Code(KeyCode::KeyA | KeyCode::ArrowLeft) => {
-                        self.im_vel.x = (self.im_vel.x - offset).clamp(-1.0, 1.0);
-                        true
-                    }
-                    PhysicalKey::Code(KeyCode::KeyD | KeyCode::ArrowRight) => {
-                        self.im_vel.x = (self.im_vel.x + offset).clamp(-1.0, 1.0);
-                        true
-                    }
-                    PhysicalKey::Code(KeyCode::Space) => {
-                        self.im_vel.y = (self.im_vel.y + offset).clamp(-1.0, 1.0);
-                        true
-                    }
-                    PhysicalKey::Code(KeyCode::ShiftLeft) => {
-                        self.im_vel.y = (self.im_vel.y - offset).clamp(-1.0, 1.0);
-                        true
-                    }
-                    _ => false,
-                }
+                self.key_input(key, *state)
             }
             _ => false,
         }
     }
 
-    pub fn update_camera(&mut self, delta_time: f32, camera: &mut Camera) {
+    /// Handles a key press or release through the key bindings, returns whether `key` is bound.
+    fn key_input(&mut self, key: KeyCode, state: ElementState) -> bool {
+        let offset = match state {
+            ElementState::Pressed => 1.0,
+            ElementState::Released => -1.0,
+        };
+        let Some(action) = self.key_bindings.action(key) else {
+            return false;
+        };
+        let (axis, sign) = match action {
+            MoveAction::Sprint => {
+                self.sprinting = state == ElementState::Pressed;
+                return true;
+            }
+            MoveAction::ToggleNoclip => {
+                if state == ElementState::Pressed {
+                    self.toggle_noclip();
+                }
+                return true;
+            }
+            MoveAction::Forward => (2, -1.0),
+            MoveAction::Back => (2, 1.0),
+            MoveAction::Left => (0, -1.0),
+            MoveAction::Right => (0, 1.0),
+            MoveAction::Up => (1, 1.0),
+            MoveAction::Down => (1, -1.0),
+        };
+        self.im_vel[axis] = (self.im_vel[axis] + sign * offset).clamp(-1.0, 1.0);
+        true
+    }
+
+    /// Moves and turns the camera. With physics enabled it walks through `world`, colliding with
+    /// its blocks, falling and jumping instead of flying.
+    pub fn update_camera(&mut self, delta_time: f32, camera: &mut Camera, world: &World) {
         let dvel = self.im_vel - self.vel;
         let dvel_len = dvel.length();
         if 0.1 < dvel_len {
@@ -138,13 +559,280 @@ impl CameraController {
         } else {
             self.vel = self.im_vel;
         }
-        let rot_vel = glam::Quat::from_rotation_y(camera.rot.y.to_radians()) * self.vel;
-        camera.pos += delta_time * self.speed * rot_vel;
+        // only sprinting forwards, easing in and out like the view bob
+        let target = match self.sprinting && self.im_vel.z < 0.0 {
+            true => 1.0,
+            false => 0.0,
+        };
+        self.sprint_amount += (target - self.sprint_amount) * (delta_time * 8.0).min(1.0);
+        let speed_scale = 1.0 + (self.sprint.speed_multiplier - 1.0) * self.sprint_amount;
+        camera.fov_offset = self.sprint.fov_increase * self.sprint_amount;
 
-        camera.rot += self.ang_vel / 100.0;
-        self.ang_vel = glam::Vec2::ZERO;
+        let forward = camera.forward_xz();
+        let right = forward.cross(glam::Vec3::Y);
+        if self.physics.enabled {
+            self.walk(
+                delta_time,
+                camera,
+                world,
+                speed_scale * (self.vel.x * right - self.vel.z * forward),
+            );
+        } else {
+            let rot_vel = self.vel.x * right + self.vel.y * glam::Vec3::Y - self.vel.z * forward;
+            camera.pos += delta_time * self.speed * speed_scale * rot_vel;
+        }
+
+        // the right stick turns continuously, in the same units as the mouse
+        let look = self.gamepad.scale(self.look_stick);
+        self.ang_vel -= 100.0 * delta_time * self.gamepad.look_speed * glam::vec2(look.y, look.x);
+        let turn = match 0.0 < self.rotation_smoothing {
+            true => (1.0 - (-delta_time / self.rotation_smoothing).exp()) * self.ang_vel,
+            false => self.ang_vel,
+        };
+        camera.rot += turn / 100.0;
+        self.ang_vel -= turn;
 
         camera.rot.y %= 360.0;
-        camera.rot.x = camera.rot.x.clamp(-89.0, 89.0);
+        let pitch = camera.rot.x.clamp(-89.0, 89.0);
+        if pitch != camera.rot.x {
+            // don't keep turning past the clamp once the mouse stops
+            self.ang_vel.x = 0.0;
+        }
+        camera.rot.x = pitch;
+
+        self.update_view_bob(delta_time, camera);
+    }
+
+    /// Moves the player's box along `horizontal`, jumping off the ground while up is held.
+    fn walk(
+        &mut self,
+        delta_time: f32,
+        camera: &mut Camera,
+        world: &World,
+        horizontal: glam::Vec3,
+    ) {
+        let physics = self.physics;
+        if self.on_ground && 0.0 < self.im_vel.y {
+            self.fall_speed = physics.jump_speed;
+        }
+        self.fall_speed =
+            (self.fall_speed - delta_time * physics.gravity).max(-physics.terminal_speed);
+
+        let feet = camera.pos - physics.eye_height * glam::Vec3::Y;
+        let velocity =
+            delta_time * (physics.walk_speed * horizontal + self.fall_speed * glam::Vec3::Y);
+        let resolved = world.move_aabb(feet, physics.size, velocity);
+
+        // stopped short vertically, either landing or bumping a ceiling
+        let blocked = (resolved.y - feet.y - velocity.y).abs() > 1e-5;
+        self.on_ground = blocked && velocity.y < 0.0;
+        if blocked {
+            self.fall_speed = 0.0;
+        }
+        camera.pos = resolved + physics.eye_height * glam::Vec3::Y;
+
+        if self.on_ground {
+            let moved = resolved - feet;
+            self.step_distance += glam::vec2(moved.x, moved.z).length();
+            if physics.step_length <= self.step_distance {
+                self.step_distance %= physics.step_length;
+                world.play_step(resolved);
+            }
+        }
+    }
+
+    fn update_view_bob(&mut self, delta_time: f32, camera: &mut Camera) {
+        let horizontal = glam::vec2(self.vel.x, self.vel.z).length().min(1.0);
+        let target = if self.view_bob.enabled {
+            horizontal
+        } else {
+            0.0
+        };
+        self.bob_strength += (target - self.bob_strength) * (delta_time * 8.0).min(1.0);
+        if self.bob_strength < 1e-3 {
+            self.bob_strength = 0.0;
+            self.bob_phase = 0.0;
+            camera.view_offset = glam::Vec3::ZERO;
+            return;
+        }
+
+        let steps = delta_time * self.speed * horizontal * self.view_bob.frequency;
+        self.bob_phase = (self.bob_phase + steps / 2.0).fract();
+
+        let amplitude = self.view_bob.amplitude * self.bob_strength;
+        let right = camera.forward_xz().cross(glam::Vec3::Y);
+        camera.view_offset = amplitude
+            * (0.5 * (TAU * self.bob_phase).sin() * right
+                + (TAU * self.bob_phase).sin().abs() * glam::Vec3::Y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A camera at the origin turned by `rot`, with a square view.
+    fn camera(rot: glam::Vec2) -> Camera {
+        Camera {
+            pos: glam::Vec3::ZERO,
+            rot,
+            aspect: 1.0,
+            fovy: 70.0,
+            znear: 0.1,
+            zfar: 100.0,
+            view_offset: glam::Vec3::ZERO,
+            fov_offset: 0.0,
+        }
+    }
+
+    #[test]
+    fn dir_is_the_view_matrix_forward() {
+        for rot in [
+            glam::vec2(0.0, 0.0),
+            glam::vec2(30.0, 45.0),
+            glam::vec2(-60.0, 200.0),
+            glam::vec2(10.0, -90.0),
+            glam::vec2(89.0, 123.0),
+            glam::vec2(-89.0, -17.0),
+        ] {
+            let cam = camera(rot);
+            let dir = cam.dir();
+            assert!((dir.length() - 1.0).abs() < 1e-5, "{rot}");
+            // view space looks down -Z
+            let view = glam::Mat4::look_to_rh(cam.eye(), dir, glam::Vec3::Y);
+            let forward = view.inverse().transform_vector3(glam::Vec3::NEG_Z);
+            assert!(forward.abs_diff_eq(dir, 1e-5), "{rot}: {forward} != {dir}");
+
+            let flat = glam::vec3(dir.x, 0.0, dir.z).normalize();
+            assert!(cam.forward_xz().abs_diff_eq(flat, 1e-4), "{rot}");
+        }
+
+        assert!(camera(glam::Vec2::ZERO)
+            .dir()
+            .abs_diff_eq(glam::Vec3::NEG_Z, 1e-6));
+        // the poles look straight down and up, while walking still follows the yaw
+        for (pitch, vertical) in [(90.0, glam::Vec3::NEG_Y), (-90.0, glam::Vec3::Y)] {
+            let cam = camera(glam::vec2(pitch, 90.0));
+            assert!(cam.dir().abs_diff_eq(vertical, 1e-6), "{pitch}");
+            assert!(
+                cam.forward_xz().abs_diff_eq(glam::Vec3::NEG_X, 1e-6),
+                "{pitch}"
+            );
+        }
+    }
+
+    #[test]
+    fn rebound_keys_move_the_camera() {
+        let mut controller = CameraController::new(4.0, 20.0);
+        controller.set_binding(MoveAction::Forward, KeyCode::KeyK);
+
+        // the old keys no longer move it
+        assert!(!controller.key_input(KeyCode::KeyW, ElementState::Pressed));
+        assert!(!controller.key_input(KeyCode::ArrowUp, ElementState::Pressed));
+        assert_eq!(controller.im_vel, glam::Vec3::ZERO);
+
+        assert!(controller.key_input(KeyCode::KeyK, ElementState::Pressed));
+        assert_eq!(controller.im_vel.z, -1.0);
+        assert!(controller.key_input(KeyCode::KeyK, ElementState::Released));
+        assert_eq!(controller.im_vel.z, 0.0);
+    }
+
+    #[test]
+    fn noclip_toggle_is_bindable_and_resets_falling() {
+        let mut controller = CameraController::new(4.0, 20.0);
+        assert_eq!(
+            controller.key_bindings.action(KeyCode::KeyV),
+            Some(MoveAction::ToggleNoclip)
+        );
+        controller.set_binding(MoveAction::ToggleNoclip, KeyCode::KeyN);
+        assert_eq!(controller.key_bindings.action(KeyCode::KeyV), None);
+        assert_eq!(
+            controller.key_bindings.action(KeyCode::KeyN),
+            Some(MoveAction::ToggleNoclip)
+        );
+
+        controller.vel = glam::vec3(0.5, 1.0, -1.0);
+        controller.fall_speed = -20.0;
+        controller.on_ground = true;
+        controller.toggle_noclip();
+        assert!(controller.physics.enabled);
+        assert_eq!(controller.fall_speed, 0.0);
+        assert!(!controller.on_ground);
+        assert_eq!(controller.vel, glam::vec3(0.5, 0.0, -1.0));
+
+        controller.toggle_noclip();
+        assert!(!controller.physics.enabled);
+    }
+
+    #[test]
+    fn frustum_planes_bound_the_view() {
+        let mut cam = camera(glam::Vec2::ZERO);
+        cam.fovy = 90.0;
+        let frustum = cam.frustum();
+
+        // with a 90° square view the side planes are at 45° to the view direction
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        let expected = [
+            glam::vec4(s, 0.0, -s, 0.0),
+            glam::vec4(-s, 0.0, -s, 0.0),
+            glam::vec4(0.0, s, -s, 0.0),
+            glam::vec4(0.0, -s, -s, 0.0),
+            // far and near with reversed-Z
+            glam::vec4(0.0, 0.0, 1.0, cam.zfar),
+            glam::vec4(0.0, 0.0, -1.0, -cam.znear),
+        ];
+        for (plane, expected) in frustum.planes.iter().zip(expected) {
+            assert!(plane.abs_diff_eq(expected, 1e-3), "{plane} != {expected}");
+        }
+
+        let point = |p: glam::Vec3| frustum.intersects_aabb(p, p);
+        assert!(point(glam::vec3(0.0, 0.0, -10.0)));
+        assert!(point(glam::vec3(9.0, -9.0, -10.0)));
+        assert!(!point(glam::vec3(11.0, 0.0, -10.0)));
+        assert!(!point(glam::vec3(0.0, 11.0, -10.0)));
+        assert!(!point(glam::vec3(0.0, 0.0, 10.0)));
+        assert!(!point(glam::vec3(0.0, 0.0, -0.05)));
+        assert!(!point(glam::vec3(0.0, 0.0, -101.0)));
+
+        // a box is seen if any part of it is
+        assert!(frustum.intersects_aabb(glam::vec3(5.0, 0.0, -10.0), glam::vec3(15.0, 1.0, -9.0)));
+        assert!(!frustum.intersects_aabb(glam::vec3(11.0, 0.0, -10.0), glam::vec3(15.0, 1.0, -9.0)));
+    }
+
+    #[test]
+    fn projection_has_the_configured_vertical_fov() {
+        // at the origin facing -Z the view is the identity, leaving the projection's own scale
+        let implied_fovy = |cam: &Camera| {
+            let vp = cam.build_view_projection_matrix();
+            (2.0 * vp.y_axis.y.recip().atan()).to_degrees()
+        };
+        for fovy in [30.0, 45.0, 70.0, 110.0] {
+            let mut cam = camera(glam::Vec2::ZERO);
+            cam.fovy = fovy;
+            assert!((implied_fovy(&cam) - fovy).abs() < 1e-3, "{fovy}");
+
+            // the width follows from the aspect ratio
+            cam.aspect = 16.0 / 9.0;
+            let vp = cam.build_view_projection_matrix();
+            assert!(
+                (vp.x_axis.x * cam.aspect - vp.y_axis.y).abs() < 1e-4,
+                "{fovy}"
+            );
+
+            // sprinting widens the view by the offset
+            cam.fov_offset = 10.0;
+            assert!((implied_fovy(&cam) - (fovy + 10.0)).abs() < 1e-3, "{fovy}");
+        }
+    }
+
+    #[test]
+    fn depth_is_reversed_from_the_near_plane_to_the_far_one() {
+        let cam = camera(glam::vec2(20.0, 30.0));
+        let vp = cam.build_view_projection_matrix();
+        let depth = |distance: f32| vp.project_point3(cam.eye() + cam.dir() * distance).z;
+        assert!((depth(cam.znear) - 1.0).abs() < 1e-5);
+        assert!(depth(cam.zfar).abs() < 1e-5);
+        // and shrinks in between
+        assert!(depth(20.0) < depth(10.0));
     }
 }
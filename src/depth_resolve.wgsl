@@ -0,0 +1,21 @@
+// A single triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2(f32(vertex_index & 1u), f32(vertex_index >> 1u));
+    return vec4(4.0 * uv - 1.0, 0.0, 1.0);
+}
+
+@group(0) @binding(0)
+var t_depth: texture_depth_multisampled_2d;
+
+// Keeps the closest sample, so edges resolve to the geometry in front
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @builtin(frag_depth) f32 {
+    let coords = vec2<i32>(position.xy);
+    var depth = 0.0;
+    for (var i = 0u; i < textureNumSamples(t_depth); i++) {
+        // reversed-Z, closer is larger
+        depth = max(depth, textureLoad(t_depth, coords, i32(i)));
+    }
+    return depth;
+}
@@ -9,6 +9,59 @@ pub trait VertexBuffer: Sized {
     const DESC: wgpu::VertexBufferLayout<'static>;
 }
 
+/// Optional features used when the adapter supports them, check `Device::features` before use.
+pub const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::POLYGON_MODE_LINE
+    .union(wgpu::Features::MULTI_DRAW_INDIRECT)
+    .union(wgpu::Features::TIMESTAMP_QUERY)
+    .union(wgpu::Features::TEXTURE_BINDING_ARRAY);
+
+/// Returns the subset of the `requested` features the adapter supports.
+pub fn supported_features(adapter: &wgpu::Adapter, requested: wgpu::Features) -> wgpu::Features {
+    let features = requested & adapter.features();
+    tracing::info!("Device features: requested {requested:?}, using {features:?}");
+    if features != requested {
+        tracing::info!("Unsupported features: {:?}", requested - features);
+    }
+    features
+}
+
+/// Default limits, raised to what the adapter supports for texture sizes and layer counts.
+pub fn supported_limits(adapter: &wgpu::Adapter) -> wgpu::Limits {
+    let adapter_limits = adapter.limits();
+    let limits = wgpu::Limits {
+        max_texture_array_layers: adapter_limits.max_texture_array_layers,
+        ..wgpu::Limits::default().using_resolution(adapter_limits)
+    };
+    tracing::info!(
+        "Device limits: max texture size {}, max texture array layers {}",
+        limits.max_texture_dimension_2d,
+        limits.max_texture_array_layers,
+    );
+    limits
+}
+
+/// Returns the highest power of two sample count up to `requested` that both the color
+/// `format` and the depth format support.
+pub fn supported_sample_count(
+    adapter: &wgpu::Adapter,
+    format: wgpu::TextureFormat,
+    requested: u32,
+) -> u32 {
+    let color_flags = adapter.get_texture_format_features(format).flags;
+    let depth_flags = adapter
+        .get_texture_format_features(Texture::DEPTH_FORMAT)
+        .flags;
+    let mut count = 1 << requested.max(1).ilog2();
+    while 1 < count
+        && !(color_flags.sample_count_supported(count) && depth_flags.sample_count_supported(count))
+    {
+        count /= 2;
+    }
+    tracing::info!("MSAA: requested {requested}x, using {count}x");
+    count
+}
+
+/// Pipeline for the scene passes, depth tested against the scene depth.
 pub fn create_render_pipeline(
     device: &wgpu::Device,
     config: &wgpu::SurfaceConfiguration,
@@ -16,6 +69,189 @@ pub fn create_render_pipeline(
     layout: &wgpu::PipelineLayout,
     buffers: &[wgpu::VertexBufferLayout],
     module: &wgpu::ShaderModule,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    let depth_stencil = wgpu::DepthStencilState {
+        format: Texture::DEPTH_FORMAT,
+        depth_write_enabled: true,
+        depth_compare: wgpu::CompareFunction::Greater,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+    };
+    build_render_pipeline(
+        device,
+        config,
+        label,
+        layout,
+        buffers,
+        module,
+        Some(depth_stencil),
+        sample_count,
+    )
+}
+
+/// Pipeline for the scene pass after a depth prepass drew the same geometry. Only the fragments
+/// left in the scene depth by the prepass are shaded, each pixel once however much is behind it.
+pub fn create_prepassed_render_pipeline(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    buffers: &[wgpu::VertexBufferLayout],
+    module: &wgpu::ShaderModule,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    let depth_stencil = wgpu::DepthStencilState {
+        format: Texture::DEPTH_FORMAT,
+        depth_write_enabled: false,
+        depth_compare: wgpu::CompareFunction::Equal,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+    };
+    build_render_pipeline(
+        device,
+        config,
+        label,
+        layout,
+        buffers,
+        module,
+        Some(depth_stencil),
+        sample_count,
+    )
+}
+
+/// Depth-only pipeline for the depth prepass, drawing with the vertex shader `entry_point`. It
+/// has to compute exactly the positions the scene pass does, for their depths to compare equal.
+pub fn create_depth_prepass_render_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    buffers: &[wgpu::VertexBufferLayout],
+    module: &wgpu::ShaderModule,
+    entry_point: &str,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point,
+            buffers,
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: None,
+        primitive: wgpu::PrimitiveState {
+            cull_mode: Some(wgpu::Face::Back),
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Greater,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    })
+}
+
+/// Pipeline for see-through geometry drawn after the rest of the scene. It's depth tested against
+/// the scene depth without writing to it, so it has to be drawn back to front.
+pub fn create_translucent_render_pipeline(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    buffers: &[wgpu::VertexBufferLayout],
+    module: &wgpu::ShaderModule,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    let depth_stencil = wgpu::DepthStencilState {
+        format: Texture::DEPTH_FORMAT,
+        depth_write_enabled: false,
+        depth_compare: wgpu::CompareFunction::Greater,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+    };
+    build_render_pipeline(
+        device,
+        config,
+        label,
+        layout,
+        buffers,
+        module,
+        Some(depth_stencil),
+        sample_count,
+    )
+}
+
+/// Depth-only pipeline for the shadow pass, drawing with the vertex shader `entry_point`. Both
+/// sides of each face cast shadows, as chunk meshes leave out the faces nobody can see.
+pub fn create_shadow_render_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    buffers: &[wgpu::VertexBufferLayout],
+    module: &wgpu::ShaderModule,
+    entry_point: &str,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point,
+            buffers,
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: None,
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Greater,
+            stencil: wgpu::StencilState::default(),
+            // pushes the casters away from the sun, reversed-Z making that negative, so lit faces
+            // don't shadow themselves
+            bias: wgpu::DepthBiasState {
+                constant: -2,
+                slope_scale: -2.0,
+                clamp: 0.0,
+            },
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Pipeline for the overlay pass, which has no depth attachment so the GUI always ends up on
+/// top of the scene.
+pub fn create_overlay_render_pipeline(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    buffers: &[wgpu::VertexBufferLayout],
+    module: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    build_render_pipeline(device, config, label, layout, buffers, module, None, 1)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_render_pipeline(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    label: &str,
+    layout: &wgpu::PipelineLayout,
+    buffers: &[wgpu::VertexBufferLayout],
+    module: &wgpu::ShaderModule,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+    sample_count: u32,
 ) -> wgpu::RenderPipeline {
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: Some(label),
@@ -45,18 +281,325 @@ pub fn create_render_pipeline(
             unclipped_depth: false,
             conservative: false,
         },
-        depth_stencil: Some(wgpu::DepthStencilState {
-            format: Texture::DEPTH_FORMAT,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::Less,
-            stencil: wgpu::StencilState::default(),
-            bias: wgpu::DepthBiasState::default(),
-        }),
+        depth_stencil,
         multisample: wgpu::MultisampleState {
-            count: 1,
+            count: sample_count,
             mask: !0,
             alpha_to_coverage_enabled: false,
         },
         multiview: None,
     })
 }
+
+/// Renders the scene into an offscreen target at a fraction of the window resolution,
+/// which is then stretched over the whole window.
+///
+/// With MSAA the scene is rendered into multisampled targets instead, whose color is resolved
+/// into `color` by the scene pass and whose depth is resolved into `depth` by `resolve_depth`,
+/// so post-processing passes can always sample single-sampled targets.
+pub struct Upscaler {
+    render_scale: f32,
+    sample_count: u32,
+    pub color: Texture,
+    pub depth: Texture,
+    msaa: Option<MsaaTargets>,
+
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    pub render_pipeline: wgpu::RenderPipeline,
+
+    depth_resolve_bind_group_layout: wgpu::BindGroupLayout,
+    depth_resolve_pipeline: wgpu::RenderPipeline,
+}
+
+struct MsaaTargets {
+    color: Texture,
+    depth: Texture,
+    depth_resolve_bind_group: wgpu::BindGroup,
+}
+
+impl Upscaler {
+    pub const MIN_RENDER_SCALE: f32 = 0.25;
+    pub const MAX_RENDER_SCALE: f32 = 1.0;
+
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        render_scale: f32,
+        sample_count: u32,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Upscaler Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let module = device.create_shader_module(wgpu::include_wgsl!("upscale.wgsl"));
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Upscaler Render Pipeline"),
+            layout: Some(
+                &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Upscaler Render Pipeline Layout"),
+                    bind_group_layouts: &[&bind_group_layout],
+                    push_constant_ranges: &[],
+                }),
+            ),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let depth_resolve_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Depth Resolve Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: true,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                }],
+            });
+        let module = device.create_shader_module(wgpu::include_wgsl!("depth_resolve.wgsl"));
+        let depth_resolve_pipeline =
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Depth Resolve Render Pipeline"),
+                layout: Some(
+                    &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: Some("Depth Resolve Render Pipeline Layout"),
+                        bind_group_layouts: &[&depth_resolve_bind_group_layout],
+                        push_constant_ranges: &[],
+                    }),
+                ),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point: "fs_main",
+                    targets: &[],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Texture::DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            });
+
+        let render_scale = render_scale.clamp(Self::MIN_RENDER_SCALE, Self::MAX_RENDER_SCALE);
+        let (color, depth, bind_group, msaa) = Self::create_targets(
+            device,
+            config,
+            &bind_group_layout,
+            &depth_resolve_bind_group_layout,
+            render_scale,
+            sample_count,
+        );
+        Self {
+            render_scale,
+            sample_count,
+            color,
+            depth,
+            msaa,
+
+            bind_group_layout,
+            bind_group,
+            render_pipeline,
+
+            depth_resolve_bind_group_layout,
+            depth_resolve_pipeline,
+        }
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        depth_resolve_bind_group_layout: &wgpu::BindGroupLayout,
+        render_scale: f32,
+        sample_count: u32,
+    ) -> (Texture, Texture, wgpu::BindGroup, Option<MsaaTargets>) {
+        let width = ((config.width as f32 * render_scale).round() as u32).max(1);
+        let height = ((config.height as f32 * render_scale).round() as u32).max(1);
+        let config = wgpu::SurfaceConfiguration {
+            width,
+            height,
+            ..config.clone()
+        };
+
+        let color =
+            Texture::create_render_target(device, width, height, config.format, 1, "Scene Color");
+        let depth = Texture::create_depth_texture(device, &config, "Scene Depth");
+
+        let msaa = (1 < sample_count).then(|| {
+            let color = Texture::create_render_target(
+                device,
+                width,
+                height,
+                config.format,
+                sample_count,
+                "Multisampled Scene Color",
+            );
+            let depth = Texture::create_multisampled_depth_texture(
+                device,
+                &config,
+                sample_count,
+                "Multisampled Scene Depth",
+            );
+            let depth_resolve_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Depth Resolve Bind Group"),
+                layout: depth_resolve_bind_group_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&depth.view),
+                }],
+            });
+            MsaaTargets {
+                color,
+                depth,
+                depth_resolve_bind_group,
+            }
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Upscaler Bind Group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&color.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&color.sampler),
+                },
+            ],
+        });
+        (color, depth, bind_group, msaa)
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// The view the scene pass should render color into and the target to resolve it into.
+    pub fn color_attachment(&self) -> (&wgpu::TextureView, Option<&wgpu::TextureView>) {
+        match &self.msaa {
+            Some(msaa) => (&msaa.color.view, Some(&self.color.view)),
+            None => (&self.color.view, None),
+        }
+    }
+
+    /// The depth view the scene pass should render into.
+    pub fn depth_attachment(&self) -> &wgpu::TextureView {
+        match &self.msaa {
+            Some(msaa) => &msaa.depth.view,
+            None => &self.depth.view,
+        }
+    }
+
+    /// Resolves the multisampled scene depth into `depth`, call after the scene pass.
+    pub fn resolve_depth(&self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(msaa) = &self.msaa else {
+            return;
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Depth Resolve Render Pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.depth_resolve_pipeline);
+        render_pass.set_bind_group(0, &msaa.depth_resolve_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Recreates the offscreen targets, call after the surface is resized.
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+        (self.color, self.depth, self.bind_group, self.msaa) = Self::create_targets(
+            device,
+            config,
+            &self.bind_group_layout,
+            &self.depth_resolve_bind_group_layout,
+            self.render_scale,
+            self.sample_count,
+        );
+    }
+
+    pub fn set_render_scale(
+        &mut self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        render_scale: f32,
+    ) {
+        let render_scale = render_scale.clamp(Self::MIN_RENDER_SCALE, Self::MAX_RENDER_SCALE);
+        if render_scale == self.render_scale {
+            return;
+        }
+        tracing::info!("Render scale set to {render_scale}");
+        self.render_scale = render_scale;
+        self.resize(device, config);
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,77 @@
+use anyhow::Result;
+
+use super::{NineSlice, Rect};
+use crate::texture::Texture;
+
+/// How a button looks, following the pointer.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ButtonState {
+    #[default]
+    Idle,
+    Hovered,
+    /// The left mouse button went down over the button and hasn't been released yet.
+    Pressed,
+}
+
+/// A button added to the GUI, see `Gui::add_button`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ButtonId(pub(super) usize);
+
+/// Rectangle clicked with the pointer while the cursor is free, drawn with a panel for each of
+/// its states.
+pub struct Button {
+    pub rect: Rect,
+    /// Text written in the middle of the button.
+    pub label: String,
+    /// Hidden buttons aren't drawn and ignore the pointer.
+    pub visible: bool,
+    pub state: ButtonState,
+    /// Panels of the idle, hovered and pressed states, in that order.
+    pub panels: [NineSlice; 3],
+    /// Width of the panels' borders in texels, see `NineSlice::set_rect`.
+    pub border: f32,
+    /// Called when the button is clicked, before `Gui::handle_pointer` returns it.
+    pub on_click: Option<Box<dyn FnMut()>>,
+}
+
+impl Button {
+    pub fn new(label: impl Into<String>, panels: [NineSlice; 3], border: f32) -> Self {
+        Self {
+            rect: Rect::new(glam::Vec2::ZERO, glam::Vec2::ZERO),
+            label: label.into(),
+            visible: true,
+            state: ButtonState::Idle,
+            panels,
+            border,
+            on_click: None,
+        }
+    }
+
+    /// Panel of the button's current state.
+    pub fn panel(&self) -> &NineSlice {
+        let i = match self.state {
+            ButtonState::Idle => 0,
+            ButtonState::Hovered => 1,
+            ButtonState::Pressed => 2,
+        };
+        &self.panels[i]
+    }
+
+    /// Square texture `size` texels wide filled with `fill` inside a frame of `edge`, `border`
+    /// texels thick. For panels of buttons without images of their own.
+    pub fn framed_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: u32,
+        border: u32,
+        fill: [u8; 4],
+        edge: [u8; 4],
+    ) -> Result<Texture> {
+        let image = image::RgbaImage::from_fn(size, size, |x, y| {
+            let distance = x.min(y).min(size - 1 - x).min(size - 1 - y);
+            image::Rgba(if distance < border { edge } else { fill })
+        });
+        let image = image::DynamicImage::ImageRgba8(image);
+        Texture::from_image(device, queue, &image, false, "Button Texture")
+    }
+}
@@ -0,0 +1,204 @@
+use std::{mem, time::Instant};
+
+use winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::Key,
+};
+
+/// Debug panels drawn with egui over everything else. It only gets input while the cursor is
+/// free, so it doesn't fight with the mouse look.
+pub struct DebugUi {
+    pub context: egui::Context,
+    renderer: egui_wgpu::Renderer,
+    /// Input gathered since the last `run`.
+    input: egui::RawInput,
+    modifiers: egui::Modifiers,
+    /// Last position of the cursor, in egui's points.
+    pointer_pos: egui::Pos2,
+    pixels_per_point: f32,
+    start: Instant,
+
+    paint_jobs: Vec<egui::ClippedPrimitive>,
+    textures_delta: egui::TexturesDelta,
+    /// Textures freed by the last frame, dropped once it's been drawn.
+    freed_textures: Vec<egui::TextureId>,
+    size: PhysicalSize<u32>,
+}
+
+impl DebugUi {
+    /// Panels drawn into targets of `config.format`, without depth or multisampling.
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        Self {
+            context: egui::Context::default(),
+            renderer: egui_wgpu::Renderer::new(device, config.format, None, 1),
+            input: egui::RawInput::default(),
+            modifiers: egui::Modifiers::default(),
+            pointer_pos: egui::Pos2::ZERO,
+            pixels_per_point: 1.0,
+            start: Instant::now(),
+
+            paint_jobs: Vec::new(),
+            textures_delta: egui::TexturesDelta::default(),
+            freed_textures: Vec::new(),
+            size: PhysicalSize::new(config.width, config.height),
+        }
+    }
+
+    /// Passes `event` on to egui, returning whether egui used it and the game should ignore it.
+    pub fn window_event(&mut self, event: &WindowEvent) -> bool {
+        let modifiers = self.modifiers;
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.pointer_pos = egui::pos2(
+                    position.x as f32 / self.pixels_per_point,
+                    position.y as f32 / self.pixels_per_point,
+                );
+                self.push(egui::Event::PointerMoved(self.pointer_pos));
+                self.context.wants_pointer_input()
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.push(egui::Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left => egui::PointerButton::Primary,
+                    MouseButton::Right => egui::PointerButton::Secondary,
+                    MouseButton::Middle => egui::PointerButton::Middle,
+                    MouseButton::Back => egui::PointerButton::Extra1,
+                    MouseButton::Forward => egui::PointerButton::Extra2,
+                    MouseButton::Other(_) => return false,
+                };
+                self.push(egui::Event::PointerButton {
+                    pos: self.pointer_pos,
+                    button,
+                    pressed: *state == ElementState::Pressed,
+                    modifiers,
+                });
+                self.context.wants_pointer_input() || self.context.is_pointer_over_area()
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (unit, delta) = match *delta {
+                    MouseScrollDelta::LineDelta(x, y) => {
+                        (egui::MouseWheelUnit::Line, egui::vec2(x, y))
+                    }
+                    MouseScrollDelta::PixelDelta(delta) => (
+                        egui::MouseWheelUnit::Point,
+                        egui::vec2(delta.x as f32, delta.y as f32) / self.pixels_per_point,
+                    ),
+                };
+                self.push(egui::Event::MouseWheel {
+                    unit,
+                    delta,
+                    modifiers,
+                });
+                self.context.is_pointer_over_area()
+            }
+            WindowEvent::ModifiersChanged(state) => {
+                let state = state.state();
+                self.modifiers = egui::Modifiers {
+                    alt: state.alt_key(),
+                    ctrl: state.control_key(),
+                    shift: state.shift_key(),
+                    mac_cmd: false,
+                    command: state.control_key(),
+                };
+                false
+            }
+            WindowEvent::KeyboardInput { event, .. } => {
+                let pressed = event.state == ElementState::Pressed;
+                let name = match &event.logical_key {
+                    Key::Named(key) => Some(format!("{key:?}")),
+                    Key::Character(c) => Some(c.to_string()),
+                    _ => None,
+                };
+                if let Some(key) = name.as_deref().and_then(egui::Key::from_name) {
+                    self.push(egui::Event::Key {
+                        key,
+                        physical_key: None,
+                        pressed,
+                        repeat: event.repeat,
+                        modifiers,
+                    });
+                }
+                let text =
+                    (event.text.as_ref()).filter(|text| text.chars().all(|c| !c.is_control()));
+                if let Some(text) = text.filter(|_| pressed) {
+                    self.push(egui::Event::Text(text.to_string()));
+                }
+                self.context.wants_keyboard_input()
+            }
+            _ => false,
+        }
+    }
+
+    fn push(&mut self, event: egui::Event) {
+        self.input.events.push(event);
+    }
+
+    /// Lays out the panels `build` adds for a window of `size` pixels, with the input since the
+    /// last call.
+    pub fn run(
+        &mut self,
+        size: PhysicalSize<u32>,
+        pixels_per_point: f32,
+        build: impl FnOnce(&egui::Context),
+    ) {
+        self.size = size;
+        self.pixels_per_point = pixels_per_point;
+        let mut input = mem::take(&mut self.input);
+        let points = egui::vec2(size.width as f32, size.height as f32) / self.pixels_per_point;
+        input.screen_rect = Some(egui::Rect::from_min_size(egui::Pos2::ZERO, points));
+        input.time = Some(self.start.elapsed().as_secs_f64());
+        input.modifiers = self.modifiers;
+        input.focused = true;
+        (input.viewports.entry(egui::ViewportId::ROOT).or_default()).native_pixels_per_point =
+            Some(self.pixels_per_point);
+
+        let output = self.context.run(input, build);
+        self.paint_jobs = (self.context).tessellate(output.shapes, output.pixels_per_point);
+        self.textures_delta.append(output.textures_delta);
+    }
+
+    fn screen_descriptor(&self) -> egui_wgpu::ScreenDescriptor {
+        egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [self.size.width, self.size.height],
+            pixels_per_point: self.pixels_per_point,
+        }
+    }
+
+    /// Uploads the textures and meshes of the last `run`, call before starting the pass it's
+    /// drawn in.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        for id in self.freed_textures.drain(..) {
+            self.renderer.free_texture(&id);
+        }
+        let delta = mem::take(&mut self.textures_delta);
+        for (id, image_delta) in &delta.set {
+            self.renderer
+                .update_texture(device, queue, *id, image_delta);
+        }
+        self.freed_textures = delta.free;
+
+        let screen_descriptor = self.screen_descriptor();
+        // only paint callbacks add command buffers, and the panels don't use any
+        let _ = (self.renderer).update_buffers(
+            device,
+            queue,
+            encoder,
+            &self.paint_jobs,
+            &screen_descriptor,
+        );
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let screen_descriptor = self.screen_descriptor();
+        (self.renderer).render(render_pass, &self.paint_jobs, &screen_descriptor);
+    }
+}
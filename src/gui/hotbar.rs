@@ -0,0 +1,108 @@
+use anyhow::Result;
+
+use super::{Gui, Instance, SpriteBatch, TextureHandle};
+use crate::texture::Texture;
+
+/// Row of block slots at the bottom of the screen, with the selected one lit up.
+pub struct Hotbar {
+    /// Width and height of a slot in pixels, before `Gui::ui_scale`.
+    pub slot_size: f32,
+    sprites: SpriteBatch,
+    background: TextureHandle,
+    selection: TextureHandle,
+    /// Icon of the block in each slot, `None` for blocks without a texture.
+    icons: Vec<Option<TextureHandle>>,
+    /// Resolution and selected slot the sprites were last laid out for.
+    layout: Option<(glam::Vec2, usize)>,
+}
+
+impl Hotbar {
+    /// A hotbar with a slot for each of `icons`, the images of the blocks in them.
+    pub fn new<'a>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        gui: &Gui,
+        icons: impl IntoIterator<Item = Option<&'a image::RgbaImage>>,
+    ) -> Result<Self> {
+        let icons = icons.into_iter();
+        let mut sprites = SpriteBatch::new(device, 2 + icons.size_hint().0);
+        // pixel art, stays crisp when scaled up
+        let filter = wgpu::FilterMode::Nearest;
+        let mut square = |color: [u8; 4]| {
+            let texture = Texture::dummy(device, queue, color.into());
+            sprites.add_texture(device, gui, texture, filter)
+        };
+        let background = square([0, 0, 0, 128]);
+        let selection = square([255, 255, 255, 160]);
+        let icons = icons
+            .map(|icon| {
+                let Some(icon) = icon else {
+                    return Ok(None);
+                };
+                let image = image::DynamicImage::ImageRgba8(icon.clone());
+                let texture = Texture::from_image(device, queue, &image, false, "Hotbar Icon")?;
+                Ok(Some(sprites.add_texture(device, gui, texture, filter)))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            slot_size: 40.0,
+            sprites,
+            background,
+            selection,
+            icons,
+            layout: None,
+        })
+    }
+
+    /// Lays the slots out along the bottom of the screen, lighting up `selected`. Only writes
+    /// the sprites when the resolution or the selection changed.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        gui: &Gui,
+        selected: usize,
+    ) {
+        let resolution = gui.uniform.resolution;
+        if self.layout == Some((resolution, selected)) {
+            return;
+        }
+        self.layout = Some((resolution, selected));
+
+        let slot = self.slot_size * gui.ui_scale;
+        // pixels from the middle of the screen to clip space
+        let to_clip =
+            |x: f32, y: f32| glam::vec3(2.0 * x / resolution.x, 2.0 * y / resolution.y, 0.0);
+        let y = -0.5 * resolution.y + 0.5 * slot + 8.0 * gui.ui_scale;
+        let len = self.icons.len() as f32;
+        let slot_x = |i: usize| (i as f32 - 0.5 * (len - 1.0)) * slot;
+
+        let instance = |x: f32, scale: glam::Vec2| Instance {
+            position: to_clip(x, y),
+            scale,
+            angle: 0.0,
+        };
+
+        self.sprites.clear();
+        (self.sprites).push(instance(0.0, glam::vec2(slot * len, slot)), self.background);
+        (self.sprites).push(
+            instance(slot_x(selected), glam::Vec2::splat(slot)),
+            self.selection,
+        );
+        for (i, icon) in self.icons.iter().enumerate() {
+            if let &Some(icon) = icon {
+                let scale = glam::Vec2::splat(0.75 * slot);
+                self.sprites.push(instance(slot_x(i), scale), icon);
+            }
+        }
+        self.sprites.upload(device, queue);
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, gui: &'a Gui) {
+        if self.layout.is_none() {
+            return;
+        }
+        self.sprites.draw(render_pass, gui);
+    }
+}
@@ -0,0 +1,573 @@
+use std::{mem, ops::Range};
+
+use wgpu::util::DeviceExt;
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    graphics::{self, VertexBuffer},
+    texture,
+};
+
+pub mod button;
+#[cfg(feature = "debug_ui")]
+pub mod debug_ui;
+pub mod hotbar;
+pub mod nine_slice;
+pub mod text;
+
+pub use button::{Button, ButtonId, ButtonState};
+#[cfg(feature = "debug_ui")]
+pub use debug_ui::DebugUi;
+pub use hotbar::Hotbar;
+pub use nine_slice::{NineSlice, Rect};
+pub use text::{Font, FontKind, TextRenderer};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    pub position: glam::Vec2,
+    pub tex_coords: glam::Vec2,
+    pub color: [u8; 4],
+}
+
+impl VertexBuffer for Vertex {
+    type Raw = Self;
+    fn to_raw(&self) -> Self {
+        *self
+    }
+    const DESC: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: mem::size_of::<Vertex>() as _,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &wgpu::vertex_attr_array![
+            0 => Float32x2, // position
+            1 => Float32x2, // tex_coords
+            2 => Unorm8x4, // color
+        ],
+    };
+}
+
+#[repr(C, packed(4))]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceRaw {
+    pub model: glam::Mat2,
+    pub position: glam::Vec3,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Instance {
+    pub position: glam::Vec3,
+    pub scale: glam::Vec2,
+    pub angle: f32,
+}
+
+impl VertexBuffer for Instance {
+    type Raw = InstanceRaw;
+    fn to_raw(&self) -> InstanceRaw {
+        InstanceRaw {
+            model: glam::Mat2::from_scale_angle(self.scale, self.angle),
+            position: self.position,
+        }
+    }
+    const DESC: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: mem::size_of::<InstanceRaw>() as _,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &wgpu::vertex_attr_array![
+            3 => Float32x2, // model1
+            4 => Float32x2, // model2
+            5 => Float32x3, // position
+        ],
+    };
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GuiUniform {
+    pub resolution: glam::Vec2,
+    pub _pad1: u64,
+}
+
+pub struct Gui {
+    pub square_vertices: wgpu::Buffer,
+    pub square_indices: wgpu::Buffer,
+
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub render_pipeline: wgpu::RenderPipeline,
+
+    pub uniform: GuiUniform,
+    pub uniform_buffer: wgpu::Buffer,
+    pub uniform_bind_group_layout: wgpu::BindGroupLayout,
+    pub uniform_bind_group: wgpu::BindGroup,
+
+    /// Samplers shared by GUI elements, nearest for pixel art and linear for smooth images.
+    pub nearest_sampler: wgpu::Sampler,
+    pub linear_sampler: wgpu::Sampler,
+
+    /// Multiplier applied to pixel sizes of HUD elements.
+    pub ui_scale: f32,
+
+    /// Sprite drawn in the middle of the screen, see `Gui::draw_crosshair`.
+    pub crosshair: Option<Sprite>,
+    pub show_crosshair: bool,
+
+    /// Buttons added with `add_button`, indexed by their `ButtonId`.
+    pub buttons: Vec<Button>,
+}
+
+impl Gui {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        resolution: PhysicalSize<u32>,
+    ) -> Self {
+        let color = [255; 4];
+        let square_vertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Square Buffer"),
+            contents: bytemuck::cast_slice(&[
+                Vertex {
+                    position: glam::Vec2::new(-1.0, -1.0),
+                    tex_coords: glam::Vec2::new(0.0, 0.0),
+                    color,
+                },
+                Vertex {
+                    position: glam::Vec2::new(1.0, -1.0),
+                    tex_coords: glam::Vec2::new(1.0, 0.0),
+                    color,
+                },
+                Vertex {
+                    position: glam::Vec2::new(1.0, 1.0),
+                    tex_coords: glam::Vec2::new(1.0, 1.0),
+                    color,
+                },
+                Vertex {
+                    position: glam::Vec2::new(-1.0, 1.0),
+                    tex_coords: glam::Vec2::new(0.0, 1.0),
+                    color,
+                },
+            ]),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let square_indices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Square Indices"),
+            contents: bytemuck::cast_slice(&[0u16, 1, 2, 0, 2, 3]),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let bind_group_layout = Self::create_bind_group_layout(device);
+
+        let uniform = GuiUniform {
+            resolution: glam::vec2(resolution.width as _, resolution.height as _),
+            _pad1: 0,
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("GUI Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("GUI Uniform Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("GUI Uniform Bind Group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let create_sampler = |filter, label| {
+            device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some(label),
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: filter,
+                min_filter: filter,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            })
+        };
+        let nearest_sampler = create_sampler(wgpu::FilterMode::Nearest, "GUI Nearest Sampler");
+        let linear_sampler = create_sampler(wgpu::FilterMode::Linear, "GUI Linear Sampler");
+
+        Self {
+            square_vertices,
+            square_indices,
+            render_pipeline: Self::create_render_pipeline(
+                device,
+                config,
+                &bind_group_layout,
+                &uniform_bind_group_layout,
+            ),
+            bind_group_layout,
+
+            uniform,
+            uniform_buffer,
+            uniform_bind_group_layout,
+            uniform_bind_group,
+
+            nearest_sampler,
+            linear_sampler,
+
+            ui_scale: 1.0,
+
+            crosshair: None,
+            show_crosshair: true,
+
+            buttons: Vec::new(),
+        }
+    }
+
+    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("GUI Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    pub fn create_render_pipeline(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        gui_bind_group_layout: &wgpu::BindGroupLayout,
+        gui_uniform_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::RenderPipeline {
+        graphics::create_overlay_render_pipeline(
+            device,
+            config,
+            "GUI Render Pipeline",
+            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("GUI Render Pipeline Layout"),
+                bind_group_layouts: &[gui_bind_group_layout, gui_uniform_bind_group_layout],
+                push_constant_ranges: &[],
+            }),
+            &[Vertex::DESC, Instance::DESC],
+            &device.create_shader_module(wgpu::include_wgsl!("gui.wgsl")),
+        )
+    }
+
+    pub fn sampler(&self, filter: wgpu::FilterMode) -> &wgpu::Sampler {
+        match filter {
+            wgpu::FilterMode::Nearest => &self.nearest_sampler,
+            wgpu::FilterMode::Linear => &self.linear_sampler,
+        }
+    }
+
+    pub fn resize(&mut self, queue: &wgpu::Queue, resolution: PhysicalSize<u32>) {
+        self.uniform.resolution = glam::vec2(resolution.width as _, resolution.height as _);
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform]),
+        );
+    }
+
+    pub fn draw_sprite<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.draw_squares(render_pass, 0..1);
+    }
+
+    /// Draws a textured square for each of `instances` in the bound instance buffer, with the
+    /// texture of the bound bind group.
+    pub fn draw_squares<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        instances: Range<u32>,
+    ) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.square_vertices.slice(..));
+        render_pass.set_index_buffer(self.square_indices.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..6, 0, instances);
+    }
+
+    /// Draws `panel` over the rect of its last `NineSlice::set_rect`, nothing before that.
+    pub fn draw_nine_slice<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        panel: &'a NineSlice,
+    ) {
+        if !panel.is_laid_out() {
+            return;
+        }
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &panel.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, panel.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, panel.instance_buffer.slice(..));
+        render_pass.set_index_buffer(panel.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..54, 0, 0..1);
+    }
+
+    pub fn add_button(&mut self, button: Button) -> ButtonId {
+        self.buttons.push(button);
+        ButtonId(self.buttons.len() - 1)
+    }
+
+    pub fn button_mut(&mut self, id: ButtonId) -> &mut Button {
+        &mut self.buttons[id.0]
+    }
+
+    /// Moves the pointer to `cursor_pos`, in pixels from the top-left corner of the window, with
+    /// the left mouse button `pressed` or not. Returns the button clicked, one pressed and then
+    /// released with the pointer over it, after calling its `on_click`.
+    pub fn handle_pointer(&mut self, cursor_pos: glam::Vec2, pressed: bool) -> Option<ButtonId> {
+        let mut clicked = None;
+        for (i, button) in self.buttons.iter_mut().enumerate() {
+            let over = button.visible && button.rect.contains(cursor_pos);
+            let state = match (over, pressed, button.state) {
+                (false, _, _) => ButtonState::Idle,
+                (true, false, _) => ButtonState::Hovered,
+                // only presses starting over the button count, not ones dragged onto it
+                (true, true, ButtonState::Idle) => ButtonState::Idle,
+                (true, true, _) => ButtonState::Pressed,
+            };
+            if button.state == ButtonState::Pressed && state == ButtonState::Hovered {
+                if let Some(on_click) = &mut button.on_click {
+                    on_click();
+                }
+                clicked = Some(ButtonId(i));
+            }
+            button.state = state;
+        }
+        clicked
+    }
+
+    /// Lays the panels of the visible buttons out over their rects.
+    pub fn update_buttons(&mut self, queue: &wgpu::Queue) {
+        let mut buttons = mem::take(&mut self.buttons);
+        for button in buttons.iter_mut().filter(|button| button.visible) {
+            for panel in &mut button.panels {
+                panel.set_rect(queue, self, button.rect, button.border);
+            }
+        }
+        self.buttons = buttons;
+    }
+
+    /// Queues the labels of the visible buttons, centered on them.
+    pub fn queue_button_labels(&self, text: &mut TextRenderer, scale: f32, color: [u8; 4]) {
+        for button in self.buttons.iter().filter(|button| button.visible) {
+            let size = text.measure(self, &button.label, scale);
+            let center = 0.5 * (button.rect.min + button.rect.max);
+            text.queue_text(self, &button.label, center - 0.5 * size, scale, color);
+        }
+    }
+
+    pub fn draw_buttons<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        for button in self.buttons.iter().filter(|button| button.visible) {
+            self.draw_nine_slice(render_pass, button.panel());
+        }
+    }
+
+    /// Draws the crosshair, if there is one and it's shown. Its position and size are in pixels
+    /// from the middle of the screen, so it stays centered and keeps its size across resizes.
+    pub fn draw_crosshair<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if let Some(crosshair) = self.crosshair.as_ref().filter(|_| self.show_crosshair) {
+            crosshair.draw(render_pass, self);
+        }
+    }
+}
+
+/// Creates the bind group sampling `texture` with `filter` for the GUI pipeline.
+fn create_texture_bind_group(
+    device: &wgpu::Device,
+    gui: &Gui,
+    texture: &texture::Texture,
+    filter: wgpu::FilterMode,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Sprite Bind Group"),
+        layout: &gui.bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(gui.sampler(filter)),
+            },
+        ],
+    })
+}
+
+pub struct Sprite {
+    pub instance: Instance,
+    pub instance_buffer: wgpu::Buffer,
+
+    pub texture: texture::Texture,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Sprite {
+    pub fn new(
+        device: &wgpu::Device,
+        gui: &Gui,
+        texture: texture::Texture,
+        instance: Instance,
+        filter: wgpu::FilterMode,
+    ) -> Self {
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sprite Instance Buffer"),
+            contents: bytemuck::cast_slice(&[instance.to_raw()]),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+        Self {
+            instance,
+            instance_buffer,
+
+            bind_group: create_texture_bind_group(device, gui, &texture, filter),
+            texture,
+        }
+    }
+
+    pub fn update_instance(&mut self, queue: &wgpu::Queue) {
+        queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&[self.instance.to_raw()]),
+        );
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, gui: &'a Gui) {
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        gui.draw_sprite(render_pass);
+    }
+}
+
+/// Texture added to a `SpriteBatch`, see `SpriteBatch::add_texture`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TextureHandle(usize);
+
+/// Sprites sharing one instance buffer, drawn with an instanced draw call per run of
+/// consecutive sprites with the same texture. Sprites are pushed each time the layout changes,
+/// then uploaded and drawn in the order they were pushed.
+pub struct SpriteBatch {
+    textures: Vec<(texture::Texture, wgpu::BindGroup)>,
+    /// Sprites pushed since the last `clear`.
+    sprites: Vec<(Instance, TextureHandle)>,
+
+    instance_buffer: wgpu::Buffer,
+    /// Number of instances `instance_buffer` has room for.
+    instance_capacity: usize,
+    /// Texture and instances of each draw call, from the last `upload`.
+    runs: Vec<(TextureHandle, Range<u32>)>,
+}
+
+impl SpriteBatch {
+    /// An empty batch, with room for `capacity` sprites before its buffer has to grow.
+    pub fn new(device: &wgpu::Device, capacity: usize) -> Self {
+        Self {
+            textures: Vec::new(),
+            sprites: Vec::new(),
+
+            instance_buffer: Self::create_instance_buffer(device, capacity),
+            instance_capacity: capacity,
+            runs: Vec::new(),
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sprite Batch Instance Buffer"),
+            size: (capacity.max(1) * mem::size_of::<InstanceRaw>()) as _,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Makes `texture` available to the batch's sprites, sampled with `filter`.
+    pub fn add_texture(
+        &mut self,
+        device: &wgpu::Device,
+        gui: &Gui,
+        texture: texture::Texture,
+        filter: wgpu::FilterMode,
+    ) -> TextureHandle {
+        let bind_group = create_texture_bind_group(device, gui, &texture, filter);
+        self.textures.push((texture, bind_group));
+        TextureHandle(self.textures.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sprites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sprites.is_empty()
+    }
+
+    /// Removes the pushed sprites. The last upload is still drawn until the next one.
+    pub fn clear(&mut self) {
+        self.sprites.clear();
+    }
+
+    /// Adds a sprite drawn over the ones pushed before it.
+    pub fn push(&mut self, instance: Instance, texture: TextureHandle) {
+        debug_assert!(
+            texture.0 < self.textures.len(),
+            "unknown texture {texture:?}"
+        );
+        self.sprites.push((instance, texture));
+    }
+
+    /// Writes the pushed sprites to the instance buffer, growing it if they don't fit.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.instance_capacity < self.sprites.len() {
+            self.instance_capacity = self.sprites.len().next_power_of_two();
+            self.instance_buffer = Self::create_instance_buffer(device, self.instance_capacity);
+        }
+        let instances: Vec<_> = self.sprites.iter().map(|(i, _)| i.to_raw()).collect();
+        if !instances.is_empty() {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+        }
+
+        self.runs.clear();
+        for (i, &(_, texture)) in self.sprites.iter().enumerate() {
+            let i = i as u32;
+            match self.runs.last_mut() {
+                Some((last, range)) if *last == texture => range.end = i + 1,
+                _ => self.runs.push((texture, i..i + 1)),
+            }
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, gui: &'a Gui) {
+        if self.runs.is_empty() {
+            return;
+        }
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        for (texture, instances) in &self.runs {
+            render_pass.set_bind_group(0, &self.textures[texture.0].1, &[]);
+            gui.draw_squares(render_pass, instances.clone());
+        }
+    }
+}
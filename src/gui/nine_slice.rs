@@ -0,0 +1,128 @@
+use wgpu::util::DeviceExt;
+
+use super::{Gui, Instance, Vertex};
+use crate::{graphics::VertexBuffer, texture::Texture};
+
+/// Rectangle in pixels, from the top-left corner of the screen with y pointing down.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rect {
+    pub min: glam::Vec2,
+    pub max: glam::Vec2,
+}
+
+impl Rect {
+    pub fn new(min: glam::Vec2, size: glam::Vec2) -> Self {
+        Self {
+            min,
+            max: min + size,
+        }
+    }
+
+    pub fn size(&self) -> glam::Vec2 {
+        self.max - self.min
+    }
+
+    pub fn contains(&self, point: glam::Vec2) -> bool {
+        self.min.cmple(point).all() && point.cmplt(self.max).all()
+    }
+}
+
+/// Panel drawn from a texture cut into a 3x3 grid. The corners keep their size, the edges
+/// stretch along the panel's sides and the center stretches both ways, so borders stay crisp at
+/// any panel size. See `Gui::draw_nine_slice`.
+pub struct NineSlice {
+    pub texture: Texture,
+    pub bind_group: wgpu::BindGroup,
+    /// The 4x4 grid of corners of the nine quads.
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    /// Maps pixels from the top-left corner of the screen to clip space.
+    pub instance_buffer: wgpu::Buffer,
+    /// Rect, border and UI scale the vertices were last laid out for, `None` until `set_rect`.
+    layout: Option<(Rect, f32, f32)>,
+}
+
+impl NineSlice {
+    pub fn new(
+        device: &wgpu::Device,
+        gui: &Gui,
+        texture: Texture,
+        filter: wgpu::FilterMode,
+    ) -> Self {
+        let indices: Vec<u16> = (0..3)
+            .flat_map(|y| (0..3).map(move |x| 4 * y + x))
+            .flat_map(|i| [i, i + 4, i + 5, i, i + 5, i + 1])
+            .collect();
+        // from the shader's pixels around the middle of the screen, y up, to pixels from the
+        // top-left corner, y down
+        let instance = Instance {
+            position: glam::vec3(-1.0, 1.0, 0.0),
+            scale: glam::vec2(2.0, -2.0),
+            angle: 0.0,
+        };
+        Self {
+            bind_group: super::create_texture_bind_group(device, gui, &texture, filter),
+            texture,
+            vertex_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Nine Slice Vertex Buffer"),
+                size: (16 * std::mem::size_of::<Vertex>()) as _,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Nine Slice Index Buffer"),
+                contents: bytemuck::cast_slice(&indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }),
+            instance_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Nine Slice Instance Buffer"),
+                contents: bytemuck::cast_slice(&[instance.to_raw()]),
+                usage: wgpu::BufferUsages::VERTEX,
+            }),
+            layout: None,
+        }
+    }
+
+    /// Stretches the panel over `rect`. `border` is the width of the texture's border in texels,
+    /// drawn `Gui::ui_scale` pixels per texel and shrunk to fit rects less than two borders wide
+    /// or tall. Only writes the vertices when the layout changed.
+    pub fn set_rect(&mut self, queue: &wgpu::Queue, gui: &Gui, rect: Rect, border: f32) {
+        let layout = (rect, border, gui.ui_scale);
+        if self.layout == Some(layout) {
+            return;
+        }
+        self.layout = Some(layout);
+
+        let size = self.texture.texture.size();
+        let uv_border = border / glam::vec2(size.width as _, size.height as _);
+        let border = glam::Vec2::splat(border * gui.ui_scale).min(0.5 * rect.size().abs());
+        let xs = [
+            rect.min.x,
+            rect.min.x + border.x,
+            rect.max.x - border.x,
+            rect.max.x,
+        ];
+        let ys = [
+            rect.min.y,
+            rect.min.y + border.y,
+            rect.max.y - border.y,
+            rect.max.y,
+        ];
+        let us = [0.0, uv_border.x, 1.0 - uv_border.x, 1.0];
+        let vs = [0.0, uv_border.y, 1.0 - uv_border.y, 1.0];
+
+        let vertices: Vec<_> = (0..4)
+            .flat_map(|y| (0..4).map(move |x| (x, y)))
+            .map(|(x, y)| Vertex {
+                position: glam::vec2(xs[x], ys[y]),
+                tex_coords: glam::vec2(us[x], vs[y]),
+                color: [255; 4],
+            })
+            .collect();
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+
+    pub fn is_laid_out(&self) -> bool {
+        self.layout.is_some()
+    }
+}
@@ -0,0 +1,497 @@
+use std::{fs, mem, path::Path};
+
+use anyhow::{ensure, Context, Result};
+use font8x8::legacy::BASIC_LEGACY;
+use wgpu::util::DeviceExt;
+
+use super::{Gui, Vertex};
+use crate::{
+    graphics::{self, VertexBuffer},
+    texture::Texture,
+};
+
+/// Size of a glyph of the built-in font, in font pixels.
+const GLYPH_SIZE: u32 = 8;
+const ATLAS_COLUMNS: u32 = 16;
+const ATLAS_ROWS: u32 = 8;
+
+/// Prebuilt SDF atlas of the built-in font, along with its metrics in a `.toml` of the same name.
+pub const SDF_FONT_PATH: &str = "res/fonts/sdf_font.png";
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum FontKind {
+    /// Coverage atlas sampled with nearest filtering, blocky when scaled by non-integers.
+    #[default]
+    Bitmap,
+    /// Signed distance field atlas, stays crisp at any scale.
+    Sdf,
+}
+
+/// Layout of a font atlas, read from the file next to a prebuilt atlas.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct FontMetrics {
+    pub columns: u32,
+    pub rows: u32,
+    /// Size of an atlas cell, in texels.
+    pub cell_size: u32,
+    /// Empty border around each glyph inside its cell, in texels.
+    pub padding: u32,
+    /// Atlas texels per font pixel.
+    pub texel_scale: f32,
+    /// Distance from the outline in font pixels at which the distance field saturates, 0 for
+    /// coverage atlases.
+    pub spread: f32,
+}
+
+impl FontMetrics {
+    /// Metrics of the coverage atlas built from the 8x8 font.
+    pub const BITMAP: Self = Self {
+        columns: ATLAS_COLUMNS,
+        rows: ATLAS_ROWS,
+        cell_size: GLYPH_SIZE,
+        padding: 0,
+        texel_scale: 1.0,
+        spread: 0.0,
+    };
+
+    /// Reads the metrics of the atlas at `atlas_path` from the `.toml` next to it.
+    pub fn load(atlas_path: &Path) -> Result<Self> {
+        let path = atlas_path.with_extension("toml");
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct FontUniform {
+    pub sdf: u32,
+    /// Atlas value of the outline's outer edge, the outline is drawn where the glyph's value is
+    /// between this and 0.5.
+    pub outline_edge: f32,
+    pub _pad1: [u32; 2],
+    pub outline_color: [f32; 4],
+}
+
+#[derive(Debug)]
+pub struct Font {
+    pub kind: FontKind,
+    pub texture: Texture,
+    pub uniform_buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+    pub metrics: FontMetrics,
+}
+
+impl Font {
+    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Font Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    /// Builds a coverage atlas of the 128 ASCII glyphs of the built-in 8x8 font.
+    pub fn bitmap(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let metrics = FontMetrics::BITMAP;
+        let mut atlas = image::GrayImage::new(
+            metrics.columns * metrics.cell_size,
+            metrics.rows * metrics.cell_size,
+        );
+        for (c, glyph) in BASIC_LEGACY.iter().enumerate() {
+            let origin = metrics.cell_size
+                * glam::uvec2(c as u32 % metrics.columns, c as u32 / metrics.columns);
+            for y in 0..GLYPH_SIZE {
+                for x in 0..GLYPH_SIZE {
+                    let value = 255 * glyph_pixel(glyph, x as _, y as _) as u8;
+                    atlas.put_pixel(origin.x + x, origin.y + y, image::Luma([value]));
+                }
+            }
+        }
+        Self::from_atlas(device, queue, layout, FontKind::Bitmap, atlas, metrics)
+    }
+
+    /// Loads the prebuilt SDF atlas at `path` and its metrics.
+    pub fn load_sdf(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        path: &Path,
+    ) -> Result<Self> {
+        let metrics = FontMetrics::load(path)?;
+        let atlas = image::open(path)
+            .with_context(|| format!("Failed to load {}", path.display()))?
+            .into_luma8();
+        ensure!(
+            atlas.dimensions()
+                == (
+                    metrics.columns * metrics.cell_size,
+                    metrics.rows * metrics.cell_size
+                ),
+            "{} doesn't match its metrics",
+            path.display()
+        );
+        ensure!(
+            metrics.columns * metrics.rows >= 128,
+            "{} doesn't have all 128 ASCII glyphs",
+            path.display()
+        );
+        Self::from_atlas(device, queue, layout, FontKind::Sdf, atlas, metrics)
+    }
+
+    fn from_atlas(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        kind: FontKind,
+        atlas: image::GrayImage,
+        metrics: FontMetrics,
+    ) -> Result<Self> {
+        let label = match kind {
+            FontKind::Bitmap => "Bitmap Font",
+            FontKind::Sdf => "SDF Font",
+        };
+        let mut texture = Texture::from_image(device, queue, &atlas.into(), true, label)?;
+        let filter = match kind {
+            FontKind::Bitmap => wgpu::FilterMode::Nearest,
+            FontKind::Sdf => wgpu::FilterMode::Linear,
+        };
+        texture.sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(&format!("{label} / Sampler")),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{label} / Uniform Buffer")),
+            contents: bytemuck::cast_slice(&[FontUniform {
+                sdf: (kind == FontKind::Sdf) as _,
+                outline_edge: 0.5,
+                _pad1: [0; 2],
+                outline_color: [0.0; 4],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{label} / Bind Group")),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Ok(Self {
+            kind,
+            texture,
+            uniform_buffer,
+            bind_group,
+            metrics,
+        })
+    }
+
+    /// Atlas texels per font pixel.
+    pub fn texel_scale(&self) -> f32 {
+        self.metrics.texel_scale
+    }
+
+    /// Width in font pixels of the widest outline the glyphs' quads have room for.
+    pub fn max_outline_width(&self) -> f32 {
+        match self.kind {
+            FontKind::Bitmap => 0.0,
+            FontKind::Sdf => {
+                (self.metrics.padding as f32 / self.texel_scale()).min(self.metrics.spread)
+            }
+        }
+    }
+
+    /// Sets the outline drawn around SDF glyphs, `width` font pixels wide. The bitmap font has no
+    /// distances to draw it with, so it ignores the outline.
+    pub fn set_outline(&self, queue: &wgpu::Queue, color: [u8; 4], width: f32) {
+        let width = width.clamp(0.0, self.max_outline_width());
+        let uniform = FontUniform {
+            sdf: (self.kind == FontKind::Sdf) as _,
+            outline_edge: match self.kind {
+                FontKind::Bitmap => 0.5,
+                FontKind::Sdf => 0.5 - 0.5 * width / self.metrics.spread,
+            },
+            _pad1: [0; 2],
+            outline_color: color.map(|c| c as f32 / 255.0),
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Returns the top-left and bottom-right texture coordinates of `c`'s cell.
+    pub fn glyph_tex_coords(&self, c: char) -> (glam::Vec2, glam::Vec2) {
+        let i = match c {
+            '\0'..='\x7f' => c as u32,
+            _ => '?' as u32,
+        };
+        let FontMetrics { columns, rows, .. } = self.metrics;
+        let atlas_size = glam::uvec2(columns, rows).as_vec2();
+        let cell = glam::uvec2(i % columns, i / columns).as_vec2();
+        (cell / atlas_size, (cell + 1.0) / atlas_size)
+    }
+}
+
+fn glyph_pixel(glyph: &[u8; 8], x: i32, y: i32) -> bool {
+    (0..8).contains(&x) && (0..8).contains(&y) && glyph[y as usize] >> x & 1 != 0
+}
+
+pub struct TextRenderer {
+    pub font_bind_group_layout: wgpu::BindGroupLayout,
+    pub render_pipeline: wgpu::RenderPipeline,
+
+    pub bitmap_font: Font,
+    /// `None` when the SDF atlas couldn't be made, text then falls back to the bitmap font.
+    pub sdf_font: Option<Font>,
+    /// The font queued text is laid out and rendered with.
+    pub font_kind: FontKind,
+    /// Color of the outline around SDF glyphs, keeping text readable over bright terrain.
+    pub outline_color: [u8; 4],
+    /// Width of the outline in font pixels, 0 for none. Limited by `Font::max_outline_width`.
+    pub outline_width: f32,
+
+    vertices: Vec<Vertex>,
+    vertex_buffer: wgpu::Buffer,
+    num_vertices: u32,
+}
+
+impl TextRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        gui: &Gui,
+    ) -> Result<Self> {
+        let font_bind_group_layout = Font::create_bind_group_layout(device);
+        let render_pipeline = graphics::create_overlay_render_pipeline(
+            device,
+            config,
+            "Text Render Pipeline",
+            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Text Render Pipeline Layout"),
+                bind_group_layouts: &[&font_bind_group_layout, &gui.uniform_bind_group_layout],
+                push_constant_ranges: &[],
+            }),
+            &[Vertex::DESC],
+            &device.create_shader_module(wgpu::include_wgsl!("text.wgsl")),
+        );
+
+        Ok(Self {
+            bitmap_font: Font::bitmap(device, queue, &font_bind_group_layout)?,
+            sdf_font: Font::load_sdf(
+                device,
+                queue,
+                &font_bind_group_layout,
+                SDF_FONT_PATH.as_ref(),
+            )
+            .inspect_err(|err| tracing::warn!("Failed to load the SDF font: {err:#}"))
+            .ok(),
+            font_kind: FontKind::Sdf,
+            outline_color: [0, 0, 0, 192],
+            outline_width: 0.5,
+
+            vertices: Vec::new(),
+            vertex_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Text Vertex Buffer"),
+                size: 0,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            num_vertices: 0,
+
+            font_bind_group_layout,
+            render_pipeline,
+        })
+    }
+
+    /// The font of `font_kind`, or the bitmap font when there's no SDF font.
+    pub fn font(&self) -> &Font {
+        match (self.font_kind, &self.sdf_font) {
+            (FontKind::Sdf, Some(sdf_font)) => sdf_font,
+            _ => &self.bitmap_font,
+        }
+    }
+
+    /// Width and height in pixels of `text` queued at `scale`, see `queue_text`.
+    pub fn measure(&self, gui: &Gui, text: &str, scale: f32) -> glam::Vec2 {
+        let lines = text.split('\n');
+        let columns = lines
+            .clone()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+        let advance = GLYPH_SIZE as f32 * scale * gui.ui_scale;
+        advance * glam::vec2(columns as f32, lines.count() as f32)
+    }
+
+    /// Lays out `text` with its top-left corner at `pos` (in pixels, y pointing down).
+    /// `scale` is the size of a font pixel in screen pixels, before `Gui::ui_scale`.
+    pub fn queue_text(
+        &mut self,
+        gui: &Gui,
+        text: &str,
+        pos: glam::Vec2,
+        scale: f32,
+        color: [u8; 4],
+    ) {
+        let font = match (self.font_kind, &self.sdf_font) {
+            (FontKind::Sdf, Some(sdf_font)) => sdf_font,
+            _ => &self.bitmap_font,
+        };
+        let scale = scale * gui.ui_scale;
+        let advance = GLYPH_SIZE as f32 * scale;
+        let padding = font.metrics.padding as f32 / font.texel_scale() * scale;
+
+        let mut pen = pos.round();
+        for c in text.chars() {
+            if c == '\n' {
+                pen = glam::vec2(pos.round().x, pen.y + advance);
+                continue;
+            }
+
+            let (uv_min, uv_max) = font.glyph_tex_coords(c);
+            let min = pen - padding;
+            let max = pen + advance + padding;
+            let vertex = |position: glam::Vec2, tex_coords: glam::Vec2| Vertex {
+                position,
+                tex_coords,
+                color,
+            };
+            let top_left = vertex(min, uv_min);
+            let bottom_left = vertex(glam::vec2(min.x, max.y), glam::vec2(uv_min.x, uv_max.y));
+            let bottom_right = vertex(max, uv_max);
+            let top_right = vertex(glam::vec2(max.x, min.y), glam::vec2(uv_max.x, uv_min.y));
+            self.vertices.extend([
+                top_left,
+                bottom_left,
+                bottom_right,
+                top_left,
+                bottom_right,
+                top_right,
+            ]);
+
+            pen.x += advance;
+        }
+    }
+
+    /// Uploads the text queued since the last call, and the outline.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.font()
+            .set_outline(queue, self.outline_color, self.outline_width);
+
+        let size = (mem::size_of::<Vertex>() * self.vertices.len()) as wgpu::BufferAddress;
+        if self.vertex_buffer.size() < size {
+            self.vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Text Vertex Buffer"),
+                size: size.next_power_of_two(),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+
+        self.num_vertices = self.vertices.len() as _;
+        self.vertices.clear();
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, gui: &'a Gui) {
+        if self.num_vertices == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.font().bind_group, &[]);
+        render_pass.set_bind_group(1, &gui.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.num_vertices, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sdf_atlas_matches_the_bitmap_font() {
+        let path = Path::new(SDF_FONT_PATH);
+        let metrics = FontMetrics::load(path).unwrap();
+        let atlas = image::open(path).unwrap().into_luma8();
+        assert_eq!(
+            atlas.dimensions(),
+            (
+                metrics.columns * metrics.cell_size,
+                metrics.rows * metrics.cell_size
+            ),
+        );
+        assert_eq!(
+            metrics.cell_size as f32,
+            GLYPH_SIZE as f32 * metrics.texel_scale + 2.0 * metrics.padding as f32,
+        );
+
+        // the center of every font pixel is inside the outline exactly when the pixel is set
+        for (c, glyph) in BASIC_LEGACY.iter().enumerate() {
+            let origin = metrics.cell_size
+                * glam::uvec2(c as u32 % metrics.columns, c as u32 / metrics.columns);
+            for y in 0..GLYPH_SIZE {
+                for x in 0..GLYPH_SIZE {
+                    let texel = origin
+                        + metrics.padding
+                        + ((glam::uvec2(x, y).as_vec2() + 0.5) * metrics.texel_scale).as_uvec2();
+                    let inside = atlas.get_pixel(texel.x, texel.y).0[0] > 127;
+                    assert_eq!(
+                        inside,
+                        glyph_pixel(glyph, x as _, y as _),
+                        "glyph {c} at ({x}, {y})",
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,68 @@
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+struct GuiUniform {
+    resolution: vec2<f32>,
+};
+
+@group(1) @binding(0)
+var<uniform> gui_uniform: GuiUniform;
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    // pixel coordinates have their origin at the top-left corner and y pointing down
+    let ndc = 2.0 * model.position / gui_uniform.resolution - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4(ndc.x, -ndc.y, 0.0, 1.0);
+    out.tex_coords = model.tex_coords;
+    out.color = model.color;
+
+    return out;
+}
+
+// Fragment shader
+@group(0) @binding(0)
+var t_font: texture_2d<f32>;
+@group(0) @binding(1)
+var s_font: sampler;
+
+struct FontUniform {
+    sdf: u32,
+    // the outline covers atlas values from outline_edge up to the glyph's edge at 0.5
+    outline_edge: f32,
+    outline_color: vec4<f32>,
+};
+
+@group(0) @binding(2)
+var<uniform> font: FontUniform;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let value = textureSample(t_font, s_font, in.tex_coords).r;
+    // the outline sits at 0.5, smooth over roughly one screen pixel
+    let width = max(fwidth(value), 1e-4);
+
+    if font.sdf == 0u {
+        return vec4(in.color.rgb, in.color.a * value);
+    }
+    let fill = smoothstep(0.5 - width, 0.5 + width, value);
+    var color = vec4(in.color.rgb, in.color.a * fill);
+    if font.outline_edge < 0.5 {
+        let outline = smoothstep(font.outline_edge - width, font.outline_edge + width, value);
+        let alpha = mix(font.outline_color.a * outline, in.color.a, fill);
+        color = vec4(mix(font.outline_color.rgb, in.color.rgb, fill), alpha);
+    }
+    return color;
+}
@@ -1,24 +1,34 @@
-use std::{iter, sync::Arc, time::Instant};
+use std::{
+    iter,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use wgpu::util::DeviceExt;
 use winit::{
     application::ApplicationHandler,
     event::*,
-    event_loop::{ActiveEventLoop, EventLoop},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
     keyboard::{KeyCode, PhysicalKey},
     window::{Window, WindowId},
 };
 
 use camera::{Camera, CameraController, CameraUniform};
 use graphics::VertexBuffer;
-use texture::Texture;
-use world::World;
+use settings::{Settings, SETTINGS_FILE};
+use world::{BlockTextureBackend, World};
 
+pub mod audio;
 pub mod camera;
 pub mod graphics;
 pub mod gui;
 pub mod model;
+pub mod settings;
+pub mod shadow;
+pub mod sky;
+pub mod ssao;
 pub mod texture;
 pub mod world;
 
@@ -29,19 +39,103 @@ struct LightUniform {
     _pad1: u32,
     color: glam::Vec3,
     _pad2: u32,
+    /// Ambient light on faces pointing up, blended towards `ground_color` on faces pointing down.
+    sky_color: glam::Vec3,
+    ambient_intensity: f32,
+    ground_color: glam::Vec3,
+    /// Scales the sky light reaching the blocks, 0 at night and 1 during the day.
+    daylight: f32,
+}
+
+/// Buttons to resume or quit, shown in the middle of the screen while the cursor is released.
+struct PauseMenu {
+    resume: gui::ButtonId,
+    quit: gui::ButtonId,
+}
+
+impl PauseMenu {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue, gui: &mut gui::Gui) -> Result<Self> {
+        let mut button = |label: &str| -> Result<gui::ButtonId> {
+            // idle, hovered and pressed
+            let [idle, hovered, pressed] = [
+                ([40, 40, 40, 200], [16, 16, 16, 255]),
+                ([72, 72, 72, 220], [220, 220, 220, 255]),
+                ([24, 24, 24, 220], [220, 220, 220, 255]),
+            ]
+            .map(|(fill, edge)| {
+                let texture = gui::Button::framed_texture(device, queue, 8, 2, fill, edge)?;
+                let filter = wgpu::FilterMode::Nearest;
+                Ok::<_, anyhow::Error>(gui::NineSlice::new(device, gui, texture, filter))
+            });
+            let mut button = gui::Button::new(label, [idle?, hovered?, pressed?], 2.0);
+            button.visible = false;
+            Ok(gui.add_button(button))
+        };
+        Ok(Self {
+            resume: button("Resume")?,
+            quit: button("Quit")?,
+        })
+    }
+
+    /// Shows or hides the buttons, stacking them in the middle of the screen.
+    fn layout(&self, gui: &mut gui::Gui, visible: bool) {
+        let size = glam::vec2(200.0, 40.0) * gui.ui_scale;
+        let middle = 0.5 * gui.uniform.resolution;
+        for (i, id) in [self.resume, self.quit].into_iter().enumerate() {
+            let center = middle + glam::vec2(0.0, (i as f32 - 0.5) * 1.25 * size.y);
+            let button = gui.button_mut(id);
+            button.visible = visible;
+            button.rect = gui::Rect::new(center - 0.5 * size, size);
+        }
+    }
+}
+
+/// Where `GraphicsState` presents its frames.
+enum Output {
+    Window {
+        window: Arc<Window>,
+        surface: wgpu::Surface<'static>,
+    },
+    /// A texture of a fixed size to render into without a window, read back with
+    /// `GraphicsState::read_pixels`.
+    Offscreen(wgpu::Texture),
+}
+
+/// What `GraphicsState::new` renders to.
+enum OutputTarget {
+    Window(Arc<Window>),
+    Offscreen { width: u32, height: u32 },
 }
 
 struct GraphicsState {
     size: winit::dpi::PhysicalSize<u32>,
-    window: Arc<Window>,
-    surface: wgpu::Surface<'static>,
+    output: Output,
+    /// Whether the output can be rendered to, false while the window has no area.
+    surface_configured: bool,
+    /// Kept to create surfaces for new windows with the device's adapter.
+    instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
+    adapter_info: wgpu::AdapterInfo,
     device: wgpu::Device,
     queue: wgpu::Queue,
+    /// Format of the frames rendered to, an sRGB view of the surface's format.
     config: wgpu::SurfaceConfiguration,
+    /// Format the surface is configured with, differing from `config.format` when the surface
+    /// has no sRGB format.
+    surface_format: wgpu::TextureFormat,
+    /// Present modes the surface supports besides the automatic ones.
+    present_modes: Vec<wgpu::PresentMode>,
     render_pipeline: wgpu::RenderPipeline,
+    /// Draws the models after the depth prepass, see `depth_prepass`.
+    prepassed_render_pipeline: wgpu::RenderPipeline,
+    model_depth_prepass_render_pipeline: wgpu::RenderPipeline,
     light_render_pipeline: wgpu::RenderPipeline,
+    /// Draw the opaque chunks and models into the scene depth in a pass of their own first, so
+    /// the scene pass shades each pixel once. Only a win when rendering is bound by fragments.
+    depth_prepass: bool,
 
-    depth_texture: Texture,
+    upscaler: graphics::Upscaler,
+    staging_belt: wgpu::util::StagingBelt,
 
     camera: Camera,
     camera_uniform: CameraUniform,
@@ -50,69 +144,138 @@ struct GraphicsState {
 
     light_uniform: LightUniform,
     light_buffer: wgpu::Buffer,
+    light_bind_group_layout: wgpu::BindGroupLayout,
     light_bind_group: wgpu::BindGroup,
+    /// Strength of the ambient light from the sky, keeping faces turned away from the sun visible.
+    ambient_intensity: f32,
+    /// Color of the sunlight at noon.
+    sun_color: glam::Vec3,
+    /// Real seconds a whole day and night take.
+    day_length: f32,
+    /// Degrees the sun's path is tilted away from straight overhead.
+    sun_tilt: f32,
 
     model: model::Model,
-    instance_buffer: wgpu::Buffer,
-    num_instances: u32,
+    instances: model::Instances,
+    model_shadow_render_pipeline: wgpu::RenderPipeline,
 
     world: World,
+    sky: sky::Sky,
+    ssao: ssao::Ssao,
+    shadow: shadow::Shadow,
 
     gui: gui::Gui,
-    sprite: gui::Sprite,
+    hotbar: gui::Hotbar,
+    pause_menu: PauseMenu,
+    /// Show the pause menu, while the cursor is released.
+    paused: bool,
+    text: gui::TextRenderer,
+    /// Show the frame time, position and loaded chunks in the top-left corner.
+    show_debug_text: bool,
+    /// Frame time in seconds, averaged over the last few frames so the text is readable.
+    frame_time: f32,
+    /// Settings in effect, changed through `apply_settings`.
+    settings: Settings,
+    #[cfg(feature = "debug_ui")]
+    debug_ui: gui::DebugUi,
 }
 
 impl GraphicsState {
-    pub async fn new(window: Arc<Window>) -> Self {
-        let size = window.inner_size();
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY,
-            ..Default::default()
-        });
-
-        let surface = instance.create_surface(window.clone()).unwrap();
-
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
+    pub async fn new(
+        target: OutputTarget,
+        requested_features: wgpu::Features,
+        requested_anisotropy: u16,
+        requested_msaa_samples: u32,
+        block_texture_backend: BlockTextureBackend,
+        settings: Settings,
+    ) -> Result<Self> {
+        let size = match &target {
+            OutputTarget::Window(window) => window.inner_size(),
+            &OutputTarget::Offscreen { width, height } => {
+                winit::dpi::PhysicalSize::new(width, height)
+            }
+        };
+        let window = match &target {
+            OutputTarget::Window(window) => Some(window),
+            OutputTarget::Offscreen { .. } => None,
+        };
+        let (instance, surface, adapter) = request_adapter(window).await?;
+        let adapter_info = adapter.get_info();
+        tracing::info!("Using adapter {adapter_info:?}");
 
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    required_features: wgpu::Features::empty(),
-                    required_limits: wgpu::Limits::default(),
+                    required_features: graphics::supported_features(&adapter, requested_features),
+                    required_limits: graphics::supported_limits(&adapter),
                     label: None,
                 },
                 None,
             )
             .await
-            .unwrap();
-
-        let surface_caps = surface.get_capabilities(&adapter);
+            .context("Failed to create the graphics device")?;
+
+        let anisotropy = texture::supported_anisotropy(&adapter, requested_anisotropy);
+
+        // offscreen frames are plain sRGB textures, which are never presented
+        let surface_caps = match &surface {
+            Some(surface) => surface.get_capabilities(&adapter),
+            None => wgpu::SurfaceCapabilities {
+                formats: vec![wgpu::TextureFormat::Rgba8UnormSrgb],
+                present_modes: vec![wgpu::PresentMode::Fifo],
+                alpha_modes: vec![wgpu::CompositeAlphaMode::Opaque],
+                usages: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            },
+        };
 
-        let surface_format = surface_caps
-            .formats
-            .iter()
+        let surface_format = (surface_caps.formats.iter())
             .find(|f| f.is_srgb())
+            .or(surface_caps.formats.first())
             .copied()
-            .unwrap_or(surface_caps.formats[0]);
+            .context("The surface isn't compatible with the graphics adapter")?;
+        // the shaders output linear colors, without an sRGB surface format they're written
+        // through an sRGB view of it
+        let format = surface_format.add_srgb_suffix();
+        if format != surface_format {
+            tracing::info!("No sRGB surface format, rendering through {format:?} views");
+        }
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
+            format,
             width: size.width,
             height: size.height,
             present_mode: wgpu::PresentMode::AutoVsync,
             alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
+            view_formats: vec![format],
             desired_maximum_frame_latency: 2,
         };
-        surface.configure(&device, &config);
+        let output = match (target, surface) {
+            (OutputTarget::Window(window), Some(surface)) => {
+                let config = wgpu::SurfaceConfiguration {
+                    format: surface_format,
+                    ..config.clone()
+                };
+                surface.configure(&device, &config);
+                Output::Window { window, surface }
+            }
+            _ => Output::Offscreen(device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Offscreen Output"),
+                size: wgpu::Extent3d {
+                    width: size.width,
+                    height: size.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: config.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            })),
+        };
+        let sample_count =
+            graphics::supported_sample_count(&adapter, config.format, requested_msaa_samples);
 
         let camera = Camera {
             // position the camera 1 unit up and 2 units back
@@ -120,9 +283,11 @@ impl GraphicsState {
             pos: glam::vec3(0.0, 0.0, 2.0),
             rot: glam::vec2(0.0, 0.0),
             aspect: config.width as f32 / config.height as f32,
-            fovy: 45.0,
+            fovy: 70.0,
             znear: 0.1,
             zfar: 100.0,
+            view_offset: glam::Vec3::ZERO,
+            fov_offset: 0.0,
         };
         let mut camera_uniform = CameraUniform::new();
         camera_uniform.update_view_proj(&camera);
@@ -162,42 +327,45 @@ impl GraphicsState {
             color: glam::vec3(1.0, 1.0, 1.0),
             _pad1: 0,
             _pad2: 0,
+            sky_color: glam::Vec3::ONE,
+            ambient_intensity: 0.0,
+            ground_color: glam::Vec3::ZERO,
+            daylight: 1.0,
         };
         let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Light Buffer"),
             contents: bytemuck::cast_slice(&[light_uniform]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
+        let shadow = shadow::Shadow::new(&device);
+        let [shadow_uniform_entry, shadow_map_entry, shadow_sampler_entry] =
+            shadow::Shadow::light_layout_entries();
         let light_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("Light Bind Group Layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
                     },
-                    count: None,
-                }],
+                    shadow_uniform_entry,
+                    shadow_map_entry,
+                    shadow_sampler_entry,
+                ],
             });
-        let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Light Bind Group"),
-            layout: &light_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: light_buffer.as_entire_binding(),
-            }],
-        });
+        let light_bind_group =
+            create_light_bind_group(&device, &light_bind_group_layout, &light_buffer, &shadow);
 
-        let depth_texture = Texture::create_depth_texture(&device, &config, "Depth Texture");
+        let upscaler = graphics::Upscaler::new(&device, &config, 1.0, sample_count);
 
-        let render_pipeline = graphics::create_render_pipeline(
-            &device,
-            &config,
-            "Render Pipeline",
-            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
                 bind_group_layouts: &[
                     &material_layout,
@@ -205,9 +373,34 @@ impl GraphicsState {
                     &light_bind_group_layout,
                 ],
                 push_constant_ranges: &[],
-            }),
+            });
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
+        let render_pipeline = graphics::create_render_pipeline(
+            &device,
+            &config,
+            "Render Pipeline",
+            &render_pipeline_layout,
             &[model::Vertex::DESC, model::Instance::DESC],
-            &device.create_shader_module(wgpu::include_wgsl!("shader.wgsl")),
+            &shader,
+            sample_count,
+        );
+        let prepassed_render_pipeline = graphics::create_prepassed_render_pipeline(
+            &device,
+            &config,
+            "Prepassed Render Pipeline",
+            &render_pipeline_layout,
+            &[model::Vertex::DESC, model::Instance::DESC],
+            &shader,
+            sample_count,
+        );
+        let model_depth_prepass_render_pipeline = graphics::create_depth_prepass_render_pipeline(
+            &device,
+            "Model Depth Prepass Render Pipeline",
+            &render_pipeline_layout,
+            &[model::Vertex::DESC, model::Instance::DESC],
+            &shader,
+            "vs_depth",
+            sample_count,
         );
         let light_render_pipeline = graphics::create_render_pipeline(
             &device,
@@ -220,17 +413,43 @@ impl GraphicsState {
             }),
             &[model::Vertex::DESC, model::Instance::DESC],
             &device.create_shader_module(wgpu::include_wgsl!("light.wgsl")),
+            sample_count,
+        );
+        let model_shadow_render_pipeline = graphics::create_shadow_render_pipeline(
+            &device,
+            "Model Shadow Render Pipeline",
+            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Model Shadow Render Pipeline Layout"),
+                bind_group_layouts: &[&shadow.bind_group_layout],
+                push_constant_ranges: &[],
+            }),
+            &[model::Vertex::DESC, model::Instance::DESC],
+            &device.create_shader_module(wgpu::include_wgsl!("shadow.wgsl")),
+            "vs_model",
         );
 
-        let model = model::Model::load("res/models/monkey.obj", &device, &queue, &material_layout)
-            .await
-            .unwrap();
+        let model = model::Model::load(
+            "res/models/monkey.obj",
+            &device,
+            &queue,
+            &material_layout,
+            anisotropy,
+        )
+        .await
+        .context("Failed to load the monkey model")?;
 
         const NUM_INSTANCES_PER_ROW: u32 = 10;
         const SPACE_BETWEEN: f32 = 3.0;
         let instances = (0..NUM_INSTANCES_PER_ROW)
             .flat_map(|z| {
                 (0..NUM_INSTANCES_PER_ROW).map(move |x| {
+                    // a red to blue gradient across the grid, greener further back
+                    let tint = [
+                        (255 * x / (NUM_INSTANCES_PER_ROW - 1)) as u8,
+                        (255 * z / (NUM_INSTANCES_PER_ROW - 1)) as u8,
+                        (255 - 255 * x / (NUM_INSTANCES_PER_ROW - 1)) as u8,
+                        255,
+                    ];
                     let x = SPACE_BETWEEN * (x as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
                     let z = SPACE_BETWEEN * (z as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
 
@@ -245,52 +464,75 @@ impl GraphicsState {
                         position,
                         rotation,
                         scale: glam::Vec3::ONE,
+                        tint,
                     }
                 })
             })
-            .map(VertexBuffer::into_raw)
             .collect::<Vec<_>>();
-        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Instance Buffer"),
-            contents: bytemuck::cast_slice(&instances),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
+        let instances = model::Instances::new(&device, "Monkeys".to_owned(), instances);
 
+        let seed = 0;
         let world = World::new(
             &device,
             &queue,
             &config,
             &camera_bind_group_layout,
             &light_bind_group_layout,
+            &shadow.bind_group_layout,
+            block_texture_backend,
+            anisotropy,
+            sample_count,
+            seed,
+            Box::new(world::NoiseTerrain::new(seed)),
         )
         .await
-        .unwrap();
+        .context("Failed to load the world")?;
+        let sky = sky::Sky::new(&device, &config, &camera_bind_group_layout, sample_count);
+        let ssao = ssao::Ssao::new(&device, &config, &camera_bind_group_layout, &upscaler.depth);
 
-        let gui = gui::Gui::new(&device, &config, window.inner_size());
-        let sprite = gui::Sprite::new(
+        let mut gui = gui::Gui::new(&device, &config, size);
+        gui.crosshair = Some(gui::Sprite::new(
             &device,
             &gui,
             texture::Texture::load(&device, &queue, "res/images/cross.png", false, "Cross")
                 .await
-                .unwrap(),
+                .context("Failed to load the crosshair")?,
             gui::Instance {
                 position: glam::Vec3::new(0.0, 0.0, 0.0),
-                scale: 32. * glam::Vec2::ONE,
+                scale: 32. * gui.ui_scale * glam::Vec2::ONE,
                 angle: 0.0,
             },
-        );
-
-        Self {
+            // pixel art, stays crisp when scaled up
+            wgpu::FilterMode::Nearest,
+        ));
+        let icons = (world.hotbar.iter()).map(|&id| world.registry.icons[id as usize].as_ref());
+        let hotbar = gui::Hotbar::new(&device, &queue, &gui, icons)?;
+        let pause_menu = PauseMenu::new(&device, &queue, &mut gui)?;
+        let text = gui::TextRenderer::new(&device, &queue, &config, &gui)?;
+        #[cfg(feature = "debug_ui")]
+        let debug_ui = gui::DebugUi::new(&device, &config);
+
+        let mut graphics = Self {
             size,
-            window,
-            surface,
+            output,
+            surface_configured: true,
+            instance,
+            adapter,
+            adapter_info,
             device,
             queue,
             config,
+            surface_format,
+            present_modes: surface_caps.present_modes,
             render_pipeline,
+            prepassed_render_pipeline,
+            model_depth_prepass_render_pipeline,
             light_render_pipeline,
+            depth_prepass: false,
 
-            depth_texture,
+            upscaler,
+            // large enough for a few freshly meshed chunks, which upload their whole mesh
+            staging_belt: wgpu::util::StagingBelt::new(1 << 20),
 
             camera,
             camera_uniform,
@@ -299,47 +541,155 @@ impl GraphicsState {
 
             light_uniform,
             light_buffer,
+            light_bind_group_layout,
             light_bind_group,
+            ambient_intensity: 0.35,
+            sun_color: light_uniform.color,
+            day_length: 1200.0,
+            sun_tilt: 20.0,
 
             model,
-            instance_buffer,
-            num_instances: instances.len() as _,
+            instances,
+            model_shadow_render_pipeline,
 
             world,
+            sky,
+            ssao,
+            shadow,
             gui,
-            sprite,
+            hotbar,
+            pause_menu,
+            paused: false,
+            text,
+            show_debug_text: true,
+            frame_time: 0.0,
+            settings: Settings::default(),
+            #[cfg(feature = "debug_ui")]
+            debug_ui,
+        };
+        graphics.apply_settings(settings);
+        Ok(graphics)
+    }
+
+    /// Presents to `window` from now on. Its surface has to support a format with the same sRGB
+    /// view as the old one, as the pipelines are built for it.
+    pub fn update_window(&mut self, window: Arc<Window>) -> Result<()> {
+        let surface = (self.instance)
+            .create_surface(window.clone())
+            .context("Failed to create a surface for the window")?;
+        let caps = surface.get_capabilities(&self.adapter);
+        self.surface_format = (caps.formats.iter())
+            .find(|f| f.add_srgb_suffix() == self.config.format)
+            .copied()
+            .with_context(|| {
+                format!(
+                    "The new window doesn't support {:?}, only {:?}",
+                    self.config.format, caps.formats,
+                )
+            })?;
+        if !caps.alpha_modes.contains(&self.config.alpha_mode) {
+            self.config.alpha_mode = caps.alpha_modes[0];
         }
+        self.present_modes = caps.present_modes;
+        let present_mode = self.config.present_mode;
+        let automatic = matches!(
+            present_mode,
+            wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync
+        );
+        if !automatic && !self.present_modes.contains(&present_mode) {
+            self.config.present_mode = wgpu::PresentMode::AutoVsync;
+        }
+
+        let size = window.inner_size();
+        self.output = Output::Window { window, surface };
+        self.resize(size);
+        Ok(())
     }
 
-    pub fn update_window(&mut self, window: Arc<Window>) {
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY,
-            ..Default::default()
-        });
-        self.window = window;
-        self.surface = instance.create_surface(self.window.clone()).unwrap();
-        self.resize(self.window.inner_size());
+    /// The window frames are presented to, `None` when rendering offscreen.
+    pub fn window(&self) -> Option<&Arc<Window>> {
+        match &self.output {
+            Output::Window { window, .. } => Some(window),
+            Output::Offscreen(_) => None,
+        }
+    }
+
+    fn configure_surface(&self) {
+        if let Output::Window { surface, .. } = &self.output {
+            let config = wgpu::SurfaceConfiguration {
+                format: self.surface_format,
+                ..self.config.clone()
+            };
+            surface.configure(&self.device, &config);
+        }
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width == 0 || new_size.height == 0 {
-            tracing::warn!("Ignoring resize event with 0 width or height");
+            // minimized, nothing is rendered until the window is restored
+            tracing::debug!("Pausing rendering while the window has 0 width or height");
+            self.surface_configured = false;
             return;
         }
 
         self.size = new_size;
         self.config.width = new_size.width;
         self.config.height = new_size.height;
-        self.surface.configure(&self.device, &self.config);
+        self.configure_surface();
+        self.surface_configured = true;
 
-        self.depth_texture =
-            Texture::create_depth_texture(&self.device, &self.config, "Depth Texture");
+        self.upscaler.resize(&self.device, &self.config);
+        self.ssao.resize(&self.device, &self.upscaler.depth);
 
         self.gui.resize(&self.queue, new_size);
         self.camera.aspect = new_size.width as f32 / new_size.height as f32;
         self.update_camera_uniform();
     }
 
+    /// Switches how frames are presented, falling back to `Fifo` if the surface doesn't support
+    /// `present_mode`.
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        let supported = matches!(
+            present_mode,
+            wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync
+        ) || self.present_modes.contains(&present_mode);
+        self.config.present_mode = match supported {
+            true => present_mode,
+            false => {
+                tracing::warn!("Present mode {present_mode:?} is unsupported, using Fifo");
+                wgpu::PresentMode::Fifo
+            }
+        };
+        self.configure_surface();
+    }
+
+    /// Switches to `settings`, loading or unloading chunks over the next frames if the render
+    /// distance changed. The mouse sensitivity is left to the camera controller.
+    pub fn apply_settings(&mut self, settings: Settings) {
+        let settings = settings.clamped();
+        self.world.render_distance = settings.render_distance;
+        self.world.meshes_per_frame = settings.chunk_uploads_per_frame;
+        (self.world.fade_start, self.world.fade_end) = settings.fade_range();
+        // past the fog, far enough for the loaded chunks above and below the camera
+        self.camera.zfar = 32.0 * (settings.render_distance + 2) as f32;
+        self.camera.set_fov(settings.fov);
+        // an unsupported mode falls back to another one, so it's only tried once
+        if settings.present_mode != self.settings.present_mode {
+            self.set_present_mode(settings.present_mode);
+        }
+        if settings.sky != self.settings.sky {
+            self.sky
+                .set_backend(&self.device, &self.queue, settings.sky);
+        }
+        self.settings = settings;
+    }
+
+    /// Sets the fraction of the window resolution the scene is rendered at.
+    pub fn set_render_scale(&mut self, render_scale: f32) {
+        (self.upscaler).set_render_scale(&self.device, &self.config, render_scale);
+        self.ssao.resize(&self.device, &self.upscaler.depth);
+    }
+
     pub fn update_camera_uniform(&mut self) {
         self.camera_uniform.update_view_proj(&self.camera);
         self.queue.write_buffer(
@@ -349,12 +699,250 @@ impl GraphicsState {
         );
     }
 
+    /// Advances the time of day, moving the sun across the sky and dimming the sky and the
+    /// sunlight as it sets.
+    pub fn update_daylight(&mut self, delta_time: f32) {
+        let t = self.world.time_of_day() + delta_time / self.day_length;
+        self.world.set_time_of_day(t);
+
+        // the sun rises at a quarter of the day and is highest at noon
+        let angle = (self.world.time_of_day() - 0.5) * std::f32::consts::TAU;
+        let dir = glam::Quat::from_rotation_z(angle)
+            * glam::Quat::from_rotation_x(self.sun_tilt.to_radians())
+            * glam::Vec3::NEG_Y;
+        let height = -dir.y;
+
+        self.light_uniform.dir = dir;
+        self.light_uniform.color = (4.0 * height).clamp(0.0, 1.0) * self.sun_color;
+        // dusk starts a bit before sunset and the night falls a bit after it
+        self.light_uniform.daylight = (0.5 + 2.0 * height).clamp(0.0, 1.0);
+        self.sky.update(&self.queue, self.light_uniform.daylight);
+    }
+
+    /// Sets the width and height of the shadow map in texels.
+    pub fn set_shadow_resolution(&mut self, resolution: u32) {
+        self.shadow.set_resolution(&self.device, resolution);
+        self.light_bind_group = create_light_bind_group(
+            &self.device,
+            &self.light_bind_group_layout,
+            &self.light_buffer,
+            &self.shadow,
+        );
+        self.world.invalidate_render_bundles();
+    }
+
+    /// Advances the time of day and animations, and updates the lighting and the targeted block
+    /// for the next frame.
+    pub fn update(&mut self, delta_time: f32) {
+        #[cfg(feature = "debug_ui")]
+        self.run_debug_ui();
+        self.update_daylight(delta_time);
+
+        // the ambient follows the sky, with light bounced off the ground from below
+        self.light_uniform.sky_color = self.sky.uniform.zenith_color.truncate();
+        self.light_uniform.ground_color = 0.4 * self.sky.uniform.horizon_color.truncate();
+        self.light_uniform.ambient_intensity = self.ambient_intensity;
+        self.queue.write_buffer(
+            &self.light_buffer,
+            0,
+            bytemuck::cast_slice(&[self.light_uniform]),
+        );
+        self.ssao.update(&self.queue);
+        (self.shadow).update(&self.queue, self.camera.pos, self.light_uniform.dir);
+        self.world
+            .update_fog(&self.queue, self.sky.uniform.horizon_color);
+        self.world.update_mining(&self.camera, delta_time, 6.0);
+        self.world.update_highlight(&self.queue, &self.camera, 6.0);
+        self.world.audio.set_listener(&self.camera);
+        (self.world).update(&self.device, &self.queue, delta_time, &self.camera);
+    }
+
+    /// Lays out the debug panels, applying what was changed in them since the last frame.
+    #[cfg(feature = "debug_ui")]
+    fn run_debug_ui(&mut self) {
+        let pixels_per_point = self
+            .window()
+            .map_or(1.0, |window| window.scale_factor() as f32);
+        let mut settings = self.settings.clone();
+        self.debug_ui.run(self.size, pixels_per_point, |ctx| {
+            egui::Window::new("Debug").show(ctx, |ui| {
+                ui.heading("Time");
+                let mut time_of_day = self.world.time_of_day();
+                ui.add(egui::Slider::new(&mut time_of_day, 0.0..=1.0).text("time of day"));
+                self.world.set_time_of_day(time_of_day);
+                ui.add(
+                    egui::Slider::new(&mut self.day_length, 10.0..=3600.0)
+                        .logarithmic(true)
+                        .text("day length (s)"),
+                );
+
+                ui.heading("Light");
+                ui.add(egui::Slider::new(&mut self.sun_tilt, -90.0..=90.0).text("sun tilt (°)"));
+                ui.add(egui::Slider::new(&mut self.ambient_intensity, 0.0..=1.0).text("ambient"));
+                ui.checkbox(&mut self.shadow.enabled, "shadows");
+                ui.add(
+                    egui::Slider::new(&mut self.shadow.extent, 16.0..=256.0).text("shadow extent"),
+                );
+                ui.checkbox(&mut self.ssao.enabled, "SSAO");
+                ui.checkbox(&mut self.depth_prepass, "depth prepass");
+
+                ui.heading("Settings");
+                ui.add(
+                    egui::Slider::new(
+                        &mut settings.render_distance,
+                        1..=Settings::MAX_RENDER_DISTANCE,
+                    )
+                    .text("render distance (chunks)"),
+                );
+                ui.add(egui::Slider::new(&mut settings.fog_density, 0.0..=1.0).text("fog density"));
+                ui.add(
+                    egui::Slider::new(&mut settings.chunk_uploads_per_frame, 1..=64)
+                        .text("chunk uploads per frame"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut settings.fov, 30.0..=120.0).text("field of view (°)"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut settings.mouse_sensitivity, 1.0..=100.0)
+                        .logarithmic(true)
+                        .text("mouse sensitivity"),
+                );
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut settings.sky, sky::SkyBackend::Gradient, "gradient sky");
+                    ui.radio_value(&mut settings.sky, sky::SkyBackend::Cubemap, "cubemap sky");
+                });
+            });
+        });
+        if settings != self.settings {
+            self.apply_settings(settings);
+        }
+    }
+
+    /// Copies the last frame rendered offscreen back from the GPU, as tightly packed RGBA rows.
+    pub fn read_pixels(&self) -> Result<Vec<u8>> {
+        let Output::Offscreen(texture) = &self.output else {
+            bail!("Only offscreen frames can be read back");
+        };
+        let (width, height) = (texture.width(), texture.height());
+        // rows of a texture copy are padded to a multiple of 256 bytes
+        let row_size = 4 * width;
+        let padded_row_size = row_size.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen Readback Buffer"),
+            size: (padded_row_size * height) as _,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = (self.device).create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+        self.queue.submit(iter::once(encoder.finish()));
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let padded = slice.get_mapped_range();
+        Ok((padded.chunks(padded_row_size as _))
+            .flat_map(|row| &row[..row_size as _])
+            .copied()
+            .collect())
+    }
+
+    /// Averages the frame time in, call once per frame.
+    pub fn record_frame_time(&mut self, delta_time: f32) {
+        self.frame_time += (delta_time - self.frame_time) * 0.05;
+    }
+
+    fn queue_debug_text(&mut self) {
+        if !self.show_debug_text {
+            self.text
+                .queue_text(&self.gui, "mclone", glam::vec2(8.0, 8.0), 2.0, [255; 4]);
+            return;
+        }
+        let text = format!("mclone\n{}", self.debug_info());
+        self.text
+            .queue_text(&self.gui, &text, glam::vec2(8.0, 8.0), 2.0, [255; 4]);
+    }
+
+    /// The GPU in use and stats of the last frame, one per line, for the debug text and bug
+    /// reports.
+    pub fn debug_info(&self) -> String {
+        let pos = self.camera.pos + (32 * self.world.origin).as_vec3();
+        let model_triangles: usize = (self.model.meshes.iter())
+            .map(|mesh| mesh.indices.len() / 3)
+            .sum();
+        let stats = self.world.draw_stats;
+        let triangles = stats.triangles + model_triangles * self.instances.range().len();
+        let info = &self.adapter_info;
+        format!(
+            "{:.2} ms ({:.0} fps)\nXYZ {:.2} {:.2} {:.2}\nChunks {} ({} drawn)\n\
+            Triangles {}\nMesh buffers {:.1} MiB\nBlocks {:.1} MiB\nGPU {} ({:?}, {})\n{:?} {:?}",
+            1000.0 * self.frame_time,
+            1.0 / self.frame_time.max(1e-6),
+            pos.x,
+            pos.y,
+            pos.z,
+            self.world.loaded_chunks.len(),
+            stats.chunks,
+            triangles,
+            self.world.mesh_buffer_size() as f32 / (1 << 20) as f32,
+            self.world.block_memory_size() as f32 / (1 << 20) as f32,
+            info.name,
+            info.backend,
+            info.driver,
+            self.surface_format,
+            self.config.present_mode,
+        )
+    }
+
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
+        let (frame, view) = match &self.output {
+            Output::Window { surface, .. } => {
+                let frame = surface.get_current_texture()?;
+                let view = (frame.texture).create_view(&wgpu::TextureViewDescriptor {
+                    format: Some(self.config.format),
+                    ..Default::default()
+                });
+                (Some(frame), view)
+            }
+            Output::Offscreen(texture) => (
+                None,
+                texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            ),
+        };
 
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.queue_debug_text();
+        self.pause_menu.layout(&mut self.gui, self.paused);
+        self.gui.update_buttons(&self.queue);
+        (self.gui).queue_button_labels(&mut self.text, 2.0, [255; 4]);
+        self.text.prepare(&self.device, &self.queue);
+        (self.hotbar).update(
+            &self.device,
+            &self.queue,
+            &self.gui,
+            self.world.selected_slot,
+        );
+        // culled to the camera, so models out of view don't cast shadows into it either
+        let frustum = self.camera.frustum();
+        (self.instances).update_buffer(&self.device, &self.queue, &frustum, self.model.bounds());
 
         let mut encoder = self
             .device
@@ -362,11 +950,65 @@ impl GraphicsState {
                 label: Some("Render Encoder"),
             });
 
+        self.world.upload_meshes(
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            &mut self.staging_belt,
+            &self.camera,
+        );
+
+        // Without shadows the map is only cleared, leaving everything lit
+        let mut render_pass = self.shadow.begin(&mut encoder);
+        if self.shadow.enabled {
+            let view_proj = self.shadow.uniform.view_proj;
+            (self.world).draw_shadows(&mut render_pass, &self.shadow.bind_group, view_proj);
+
+            render_pass.set_pipeline(&self.model_shadow_render_pipeline);
+            render_pass.set_vertex_buffer(1, self.instances.buffer().slice(..));
+            for mesh in &self.model.meshes {
+                mesh.draw(&mut render_pass, self.instances.range());
+            }
+        }
+        drop(render_pass);
+
+        self.world.set_depth_prepass(self.depth_prepass);
+        if self.depth_prepass {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Depth Prepass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: self.upscaler.depth_attachment(),
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(0.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_pipeline(&self.model_depth_prepass_render_pipeline);
+            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.light_bind_group, &[]);
+            render_pass.set_vertex_buffer(1, self.instances.buffer().slice(..));
+            self.model
+                .draw(&mut render_pass, .., self.instances.range());
+
+            self.world.draw_depth_prepass(
+                &mut render_pass,
+                &self.camera,
+                &self.camera_bind_group,
+                &self.light_bind_group,
+            );
+        }
+
+        let (color_view, color_resolve_target) = self.upscaler.color_attachment();
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Render Pass"),
+            label: Some("Scene Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
+                view: color_view,
+                resolve_target: color_resolve_target,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Clear(wgpu::Color {
                         r: 0.1,
@@ -378,9 +1020,12 @@ impl GraphicsState {
                 },
             })],
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &self.depth_texture.view,
+                view: self.upscaler.depth_attachment(),
                 depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
+                    load: match self.depth_prepass {
+                        true => wgpu::LoadOp::Load,
+                        false => wgpu::LoadOp::Clear(0.0),
+                    },
                     store: wgpu::StoreOp::Store,
                 }),
                 stencil_ops: None,
@@ -388,12 +1033,18 @@ impl GraphicsState {
             occlusion_query_set: None,
             timestamp_writes: None,
         });
-        render_pass.set_pipeline(&self.render_pipeline);
+        self.sky.draw(&mut render_pass, &self.camera_bind_group);
+
+        render_pass.set_pipeline(match self.depth_prepass {
+            true => &self.prepassed_render_pipeline,
+            false => &self.render_pipeline,
+        });
         render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
         render_pass.set_bind_group(2, &self.light_bind_group, &[]);
 
-        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-        self.model.draw(&mut render_pass, .., 0..self.num_instances);
+        render_pass.set_vertex_buffer(1, self.instances.buffer().slice(..));
+        self.model
+            .draw(&mut render_pass, .., self.instances.range());
 
         render_pass.set_pipeline(&self.light_render_pipeline);
         render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
@@ -404,31 +1055,185 @@ impl GraphicsState {
         self.world.draw(
             &self.device,
             &mut render_pass,
+            &self.camera,
             &self.camera_bind_group,
             &self.light_bind_group,
         );
 
-        self.sprite.draw(&mut render_pass, &self.gui);
+        drop(render_pass);
+
+        self.upscaler.resolve_depth(&mut encoder);
+
+        // The scene depth can only be sampled once it's no longer attached
+        if self.ssao.enabled {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("SSAO Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.upscaler.color.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            self.ssao.draw(&mut render_pass, &self.camera_bind_group);
+        }
+
+        #[cfg(feature = "debug_ui")]
+        (self.debug_ui).prepare(&self.device, &self.queue, &mut encoder);
+
+        // The GUI is drawn at the native resolution on top of the upscaled scene, without a
+        // depth attachment so nothing in the scene can hide it
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Overlay Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        self.upscaler.draw(&mut render_pass);
+
+        self.gui.draw_crosshair(&mut render_pass);
+        self.hotbar.draw(&mut render_pass, &self.gui);
+        self.gui.draw_buttons(&mut render_pass);
+        self.text.draw(&mut render_pass, &self.gui);
+        #[cfg(feature = "debug_ui")]
+        self.debug_ui.draw(&mut render_pass);
 
         drop(render_pass);
 
+        self.staging_belt.finish();
         self.queue.submit(iter::once(encoder.finish()));
+        self.staging_belt.recall();
 
-        output.present();
+        if let Some(frame) = frame {
+            frame.present();
+        }
 
         Ok(())
     }
 }
 
+/// Finds an adapter that can present to `window`, trying the primary backends before the
+/// secondary ones, a high-performance adapter before a low-power one and a software adapter last.
+/// Returns the adapter's instance with the surface of `window` created by it.
+async fn request_adapter(
+    window: Option<&Arc<Window>>,
+) -> Result<(
+    wgpu::Instance,
+    Option<wgpu::Surface<'static>>,
+    wgpu::Adapter,
+)> {
+    for backends in [wgpu::Backends::PRIMARY, wgpu::Backends::SECONDARY] {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends,
+            ..Default::default()
+        });
+        let surface = match window.map(|window| instance.create_surface(window.clone())) {
+            Some(Ok(surface)) => Some(surface),
+            Some(Err(err)) => {
+                tracing::warn!("Failed to create a surface with backends {backends:?}: {err}");
+                continue;
+            }
+            None => None,
+        };
+        for (power_preference, force_fallback_adapter) in [
+            (wgpu::PowerPreference::HighPerformance, false),
+            (wgpu::PowerPreference::LowPower, false),
+            (wgpu::PowerPreference::LowPower, true),
+        ] {
+            let adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference,
+                    compatible_surface: surface.as_ref(),
+                    force_fallback_adapter,
+                })
+                .await;
+            if let Some(adapter) = adapter {
+                return Ok((instance, surface, adapter));
+            }
+        }
+    }
+    bail!(
+        "No compatible graphics adapter found, make sure a driver supporting Vulkan, Metal, \
+        DirectX 12 or OpenGL is installed"
+    )
+}
+
+/// Binds the light uniform along with the shadow map the sun's light is tested against.
+fn create_light_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    light_buffer: &wgpu::Buffer,
+    shadow: &shadow::Shadow,
+) -> wgpu::BindGroup {
+    let [shadow_uniform, shadow_map, shadow_sampler] = shadow.light_entries();
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Light Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: light_buffer.as_entire_binding(),
+            },
+            shadow_uniform,
+            shadow_map,
+            shadow_sampler,
+        ],
+    })
+}
+
 struct App {
     rt: tokio::runtime::Runtime,
     graphics: Option<GraphicsState>,
     camera_controller: CameraController,
+    /// Anisotropic filtering level to ask for, clamped to what the adapter supports.
+    requested_anisotropy: u16,
+    /// MSAA sample count to ask for, lowered to what the adapter supports.
+    requested_msaa_samples: u32,
+    /// Whether block textures are sampled from one atlas or a texture array.
+    block_texture_backend: BlockTextureBackend,
+    /// Optional device features to enable when the adapter supports them.
+    requested_features: wgpu::Features,
+    /// Gamepad input, `None` if it couldn't be initialized.
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<gilrs::Gilrs>,
 
     last_render_time: Instant,
+    /// Time not yet simulated, less than a tick after each frame.
+    tick_accumulator: f32,
+    /// Whether the window has focus. Mouse motion is ignored and rendering paused without it.
+    focused: bool,
+    /// Whether the cursor is held in the window for mouse look, toggled with Escape.
+    cursor_grabbed: bool,
+    /// Position of the cursor in pixels from the window's top-left corner, and whether the left
+    /// mouse button is held, for the GUI's buttons while the cursor is free.
+    cursor_pos: glam::Vec2,
+    pointer_pressed: bool,
+    /// Camera position before the last tick, `None` until the first tick has run.
+    previous_camera_pos: Option<glam::Vec3>,
 }
 
 impl App {
+    /// Length of a logic tick, movement and physics advance in steps of this regardless of the
+    /// frame rate.
+    const TICK_DURATION: f32 = 1.0 / 60.0;
+    /// Longest frame simulated in full, so a stall doesn't freeze the game catching up.
+    const MAX_FRAME_TIME: f32 = 0.25;
+    /// How often the event loop still wakes up while unfocused, to poll gamepads.
+    const UNFOCUSED_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
     fn new() -> Self {
         Self {
             rt: tokio::runtime::Builder::new_current_thread()
@@ -436,7 +1241,21 @@ impl App {
                 .unwrap(),
             graphics: None,
             camera_controller: CameraController::new(12., 20.),
+            requested_anisotropy: 1,
+            requested_msaa_samples: 1,
+            block_texture_backend: BlockTextureBackend::default(),
+            requested_features: graphics::OPTIONAL_FEATURES,
+            #[cfg(feature = "gamepad")]
+            gilrs: gilrs::Gilrs::new()
+                .inspect_err(|err| tracing::warn!("Failed to initialize gamepads: {err}"))
+                .ok(),
             last_render_time: Instant::now(),
+            tick_accumulator: 0.0,
+            previous_camera_pos: None,
+            focused: true,
+            cursor_grabbed: false,
+            cursor_pos: glam::Vec2::ZERO,
+            pointer_pressed: false,
         }
     }
 }
@@ -448,14 +1267,33 @@ impl ApplicationHandler for App {
                 .create_window(Window::default_attributes().with_title("mclone"))
                 .unwrap(),
         );
-        window
-            .set_cursor_grab(winit::window::CursorGrabMode::Locked)
-            .unwrap();
-        window.set_cursor_visible(false);
+        self.cursor_grabbed = set_cursor_grabbed(&window, true);
+        self.camera_controller.cursor_grabbed();
 
         match &mut self.graphics {
-            Some(graphics) => graphics.update_window(window.clone()),
-            None => self.graphics = Some(self.rt.block_on(GraphicsState::new(window))),
+            Some(graphics) => {
+                if let Err(err) = graphics.update_window(window.clone()) {
+                    tracing::error!("Failed to present to the new window: {err:#}");
+                    event_loop.exit();
+                }
+            }
+            None => {
+                let graphics = self.rt.block_on(GraphicsState::new(
+                    OutputTarget::Window(window),
+                    self.requested_features,
+                    self.requested_anisotropy,
+                    self.requested_msaa_samples,
+                    self.block_texture_backend,
+                    Settings::load(Path::new(SETTINGS_FILE)),
+                ));
+                match graphics {
+                    Ok(graphics) => self.graphics = Some(graphics),
+                    Err(err) => {
+                        tracing::error!("Failed to initialize graphics: {err:#}");
+                        event_loop.exit();
+                    }
+                }
+            }
         }
     }
 
@@ -465,6 +1303,10 @@ impl ApplicationHandler for App {
         _device_id: DeviceId,
         event: DeviceEvent,
     ) {
+        // device events arrive regardless of focus and cursor grab
+        if !self.focused || !self.cursor_grabbed {
+            return;
+        }
         if self.camera_controller.device_event(&event) {
             return;
         }
@@ -480,78 +1322,421 @@ impl ApplicationHandler for App {
             tracing::warn!("Ignoring window event without graphics state");
             return;
         };
-        if graphics.window.id() != window_id {
+        if graphics
+            .window()
+            .is_none_or(|window| window.id() != window_id)
+        {
+            return;
+        }
+        // the panels need the cursor, so they only get input while it's free
+        #[cfg(feature = "debug_ui")]
+        if !self.cursor_grabbed && graphics.debug_ui.window_event(&event) {
+            return;
+        }
+        // the pause menu takes the pointer while the cursor is free, so clicks don't reach the
+        // world behind it
+        let pointer = match event {
+            WindowEvent::CursorMoved { position, .. } => Some((
+                glam::vec2(position.x as _, position.y as _),
+                self.pointer_pressed,
+            )),
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => Some((self.cursor_pos, state.is_pressed())),
+            _ => None,
+        };
+        if let Some((cursor_pos, pressed)) = pointer.filter(|_| !self.cursor_grabbed) {
+            (self.cursor_pos, self.pointer_pressed) = (cursor_pos, pressed);
+            let clicked = graphics.gui.handle_pointer(cursor_pos, pressed);
+            if clicked == Some(graphics.pause_menu.resume) {
+                if let Some(window) = graphics.window() {
+                    self.cursor_grabbed = set_cursor_grabbed(window, true);
+                    if self.cursor_grabbed {
+                        self.camera_controller.cursor_grabbed();
+                    }
+                }
+            } else if clicked == Some(graphics.pause_menu.quit) {
+                event_loop.exit();
+            }
             return;
         }
         if self.camera_controller.window_event(&event) {
             return;
         } else if graphics.world.window_event(
-            &graphics.device,
             &graphics.queue,
             &graphics.camera,
+            self.camera_controller.player_aabb(&graphics.camera),
             &event,
         ) {
             return;
         }
         match event {
-            WindowEvent::CloseRequested
-            | WindowEvent::KeyboardInput {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
                         physical_key: PhysicalKey::Code(KeyCode::Escape),
                         state: ElementState::Pressed,
+                        repeat: false,
                         ..
                     },
                 ..
             } => {
-                event_loop.exit();
+                if let Some(window) = graphics.window() {
+                    self.cursor_grabbed = set_cursor_grabbed(window, !self.cursor_grabbed);
+                    if self.cursor_grabbed {
+                        self.camera_controller.cursor_grabbed();
+                    }
+                }
+            }
+            WindowEvent::Focused(focused) => {
+                self.focused = focused;
+                if let Some(window) = graphics.window().filter(|_| self.cursor_grabbed) {
+                    // keep wanting the grab while unfocused, so it comes back with the focus
+                    set_cursor_grabbed(window, focused);
+                }
+                if focused {
+                    // don't simulate the time spent unfocused in one go
+                    self.last_render_time = Instant::now();
+                    event_loop.set_control_flow(ControlFlow::Wait);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key @ (KeyCode::Minus | KeyCode::Equal)),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                let step = match key {
+                    KeyCode::Minus => -0.125,
+                    _ => 0.125,
+                };
+                graphics.set_render_scale(graphics.upscaler.render_scale() + step);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key @ (KeyCode::PageDown | KeyCode::PageUp)),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                let render_distance = match key {
+                    KeyCode::PageDown => graphics.settings.render_distance.saturating_sub(1),
+                    _ => graphics.settings.render_distance + 1,
+                };
+                graphics.apply_settings(Settings {
+                    render_distance,
+                    ..graphics.settings.clone()
+                });
+                tracing::info!("Render distance: {}", graphics.settings.render_distance);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F6),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                graphics.ssao.enabled = !graphics.ssao.enabled;
+                tracing::info!("SSAO enabled: {}", graphics.ssao.enabled);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F12),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                graphics.depth_prepass = !graphics.depth_prepass;
+                tracing::info!("Depth prepass enabled: {}", graphics.depth_prepass);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F3),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                graphics.show_debug_text = !graphics.show_debug_text;
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F1),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                graphics.gui.show_crosshair = !graphics.gui.show_crosshair;
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F2),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                // F2 cycles the shadow map through a few resolutions and off
+                match (graphics.shadow.enabled, graphics.shadow.resolution()) {
+                    (false, _) => {
+                        graphics.shadow.enabled = true;
+                        graphics.set_shadow_resolution(1024);
+                    }
+                    (true, res) if res < 4096 => graphics.set_shadow_resolution(2 * res),
+                    (true, _) => graphics.shadow.enabled = false,
+                }
+                tracing::info!(
+                    "Shadows enabled: {}, resolution: {}",
+                    graphics.shadow.enabled,
+                    graphics.shadow.resolution(),
+                );
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F10),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                // F10 cycles between vsync, no vsync, mailbox and fifo
+                const PRESENT_MODES: [wgpu::PresentMode; 4] = [
+                    wgpu::PresentMode::AutoVsync,
+                    wgpu::PresentMode::AutoNoVsync,
+                    wgpu::PresentMode::Mailbox,
+                    wgpu::PresentMode::Fifo,
+                ];
+                let current = (PRESENT_MODES.iter())
+                    .position(|&mode| mode == graphics.config.present_mode)
+                    .unwrap_or(0);
+                graphics.apply_settings(Settings {
+                    present_mode: PRESENT_MODES[(current + 1) % PRESENT_MODES.len()],
+                    ..graphics.settings.clone()
+                });
+                tracing::info!("Present mode: {:?}", graphics.config.present_mode);
             }
             WindowEvent::Resized(size) => {
                 graphics.resize(size);
             }
             WindowEvent::ScaleFactorChanged { .. } => {
-                graphics.resize(graphics.window.inner_size());
+                if let Some(size) = graphics.window().map(|window| window.inner_size()) {
+                    graphics.resize(size);
+                }
             }
             WindowEvent::RedrawRequested => {
                 let delta_time = self.last_render_time.elapsed().as_secs_f32();
                 self.last_render_time = Instant::now();
+                graphics.record_frame_time(delta_time);
+                graphics.paused = !self.cursor_grabbed;
+
+                (self.camera_controller).set_sensitivity(graphics.settings.mouse_sensitivity);
+                self.tick_accumulator =
+                    (self.tick_accumulator + delta_time).min(Self::MAX_FRAME_TIME);
+                while Self::TICK_DURATION <= self.tick_accumulator {
+                    self.tick_accumulator -= Self::TICK_DURATION;
+                    let pos = graphics.camera.pos;
+                    self.camera_controller.update_camera(
+                        Self::TICK_DURATION,
+                        &mut graphics.camera,
+                        &graphics.world,
+                    );
+                    // keep the previous position relative to the same origin as the current one
+                    let shift = graphics.camera.pos;
+                    graphics.world.rebase_origin(&mut graphics.camera);
+                    self.previous_camera_pos = Some(pos + graphics.camera.pos - shift);
+                }
 
-                self.camera_controller
-                    .update_camera(delta_time, &mut graphics.camera);
+                // render between the last two ticks, so movement stays smooth at any frame rate
+                let pos = graphics.camera.pos;
+                if let Some(previous) = self.previous_camera_pos {
+                    let alpha = self.tick_accumulator / Self::TICK_DURATION;
+                    graphics.camera.pos = previous.lerp(pos, alpha);
+                }
                 graphics.update_camera_uniform();
 
-                // graphics.light_uniform.dir =
-                //     glam::Quat::from_rotation_y(1f32.to_radians()) * graphics.light_uniform.dir;
-                graphics.queue.write_buffer(
-                    &graphics.light_buffer,
-                    0,
-                    bytemuck::cast_slice(&[graphics.light_uniform]),
-                );
+                graphics.update(delta_time);
 
-                match graphics.render() {
-                    Ok(()) => {}
-                    Err(wgpu::SurfaceError::Lost) => graphics.resize(graphics.size),
-                    Err(wgpu::SurfaceError::OutOfMemory) => event_loop.exit(),
-                    Err(e) => tracing::warn!("Encountered surface error: {e}"),
+                if graphics.surface_configured {
+                    let mut result = graphics.render();
+                    if let Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) = result {
+                        // the surface no longer matches the window, reconfigure it and try again
+                        graphics.resize(graphics.size);
+                        result = graphics.render();
+                    }
+                    match result {
+                        Ok(()) => {}
+                        Err(wgpu::SurfaceError::OutOfMemory) => event_loop.exit(),
+                        Err(e) => tracing::warn!("Encountered surface error: {e}"),
+                    }
                 }
+                graphics.camera.pos = pos;
             }
             _ => {}
         }
     }
 
-    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        let Some(graphics) = &self.graphics else {
+            return;
+        };
+        if let Err(err) = graphics.settings.save(Path::new(SETTINGS_FILE)) {
+            tracing::error!("{err:#}");
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        #[cfg(feature = "gamepad")]
+        if let Some(gilrs) = &mut self.gilrs {
+            while let Some(event) = gilrs.next_event() {
+                self.camera_controller.gamepad_event(&event);
+            }
+        }
+
+        if !self.focused {
+            event_loop.set_control_flow(ControlFlow::WaitUntil(
+                Instant::now() + Self::UNFOCUSED_POLL_INTERVAL,
+            ));
+            return;
+        }
         let Some(graphics) = &mut self.graphics else {
             return;
         };
-        graphics.window.request_redraw();
+        if let Some(window) = graphics.window() {
+            window.request_redraw();
+        }
     }
 }
 
+/// Holds and hides the cursor for mouse look, or releases it. Prefers locking the cursor in
+/// place, confining it to the window where that isn't supported. Returns whether the cursor
+/// ended up grabbed.
+fn set_cursor_grabbed(window: &Window, grabbed: bool) -> bool {
+    use winit::window::CursorGrabMode;
+
+    let result = match grabbed {
+        true => window
+            .set_cursor_grab(CursorGrabMode::Locked)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined)),
+        false => window.set_cursor_grab(CursorGrabMode::None),
+    };
+    let grabbed = match result {
+        Ok(()) => grabbed,
+        Err(err) => {
+            tracing::warn!("Failed to set the cursor grab: {err}");
+            false
+        }
+    };
+    window.set_cursor_visible(!grabbed);
+    grabbed
+}
+
+/// Renders a frame of the starting scene without a window, as tightly packed RGBA rows.
+fn render_offscreen(width: u32, height: u32) -> Result<Vec<u8>> {
+    let rt = tokio::runtime::Builder::new_current_thread().build()?;
+    let mut graphics = rt.block_on(GraphicsState::new(
+        OutputTarget::Offscreen { width, height },
+        graphics::OPTIONAL_FEATURES,
+        1,
+        1,
+        BlockTextureBackend::default(),
+        Settings::default(),
+    ))?;
+    // load, mesh and upload every chunk in the one frame instead of spreading them out
+    graphics.world.loads_per_frame = usize::MAX;
+    graphics.world.meshes_per_frame = usize::MAX;
+    graphics.update(0.0);
+    graphics.render()?;
+    graphics.read_pixels()
+}
+
+/// Renders a frame of the starting scene without a window and writes it to the PNG at `path`.
+fn screenshot(path: &str) -> Result<()> {
+    let (width, height) = (800, 600);
+    let pixels = render_offscreen(width, height)?;
+    image::RgbaImage::from_raw(width, height, pixels)
+        .context("Read back a frame of the wrong size")?
+        .save(path)?;
+    tracing::info!("Saved a {width}x{height} screenshot to {path}");
+    Ok(())
+}
+
 fn main() -> Result<()> {
     tracing_subscriber::fmt().init();
 
+    // `--screenshot <path>` renders a single frame offscreen instead of opening a window
+    let args: Vec<_> = std::env::args().collect();
+    if let [_, flag, path] = args.as_slice() {
+        if flag == "--screenshot" {
+            return screenshot(path);
+        }
+    }
+
     let event_loop = EventLoop::new()?;
     event_loop.run_app(&mut App::new())?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        hash::{DefaultHasher, Hash, Hasher},
+    };
+
+    use super::*;
+
+    /// Hash of the starting scene rendered at 320x240, set `MCLONE_BLESS=1` to record it again
+    /// after changing what the scene looks like.
+    const FRAME_HASH_PATH: &str = "res/tests/starting_scene.hash";
+
+    fn frame_hash(pixels: &[u8]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        pixels.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    #[ignore = "needs a graphics adapter"]
+    fn offscreen_frames_of_the_starting_scene_match_the_recorded_one() {
+        let (width, height) = (320, 240);
+        let pixels = render_offscreen(width, height).unwrap();
+        assert_eq!(pixels.len(), (4 * width * height) as usize);
+        // the scene was drawn rather than a single flat color
+        assert!(pixels.chunks(4).any(|pixel| pixel != &pixels[..4]));
+
+        let hash = frame_hash(&pixels);
+        assert_eq!(frame_hash(&render_offscreen(width, height).unwrap()), hash);
+        let recorded = fs::read_to_string(FRAME_HASH_PATH).ok();
+        match recorded.filter(|_| std::env::var_os("MCLONE_BLESS").is_none()) {
+            Some(recorded) => assert_eq!(format!("{hash:016x}"), recorded.trim()),
+            None => {
+                fs::create_dir_all(Path::new(FRAME_HASH_PATH).parent().unwrap()).unwrap();
+                fs::write(FRAME_HASH_PATH, format!("{hash:016x}\n")).unwrap();
+            }
+        }
+    }
+}
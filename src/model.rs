@@ -5,13 +5,18 @@ use futures::future::OptionFuture;
 use tokio::{fs, io::AsyncReadExt};
 use wgpu::util::DeviceExt;
 
-use crate::{graphics::VertexBuffer, texture::Texture};
+use crate::{
+    camera::Frustum,
+    graphics::VertexBuffer,
+    texture::{Mipmaps, Texture},
+};
 
 #[repr(C, packed(4))]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct InstanceRaw {
     pub model: glam::Mat4,
     pub normal: glam::Mat3,
+    pub tint: [u8; 4],
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -19,6 +24,8 @@ pub struct Instance {
     pub scale: glam::Vec3,
     pub position: glam::Vec3,
     pub rotation: glam::Quat,
+    /// RGBA color multiplied into the instance's material, white leaving it unchanged.
+    pub tint: [u8; 4],
 }
 
 impl Instance {
@@ -26,6 +33,7 @@ impl Instance {
         scale: glam::Vec3::new(1.0, 1.0, 1.0),
         position: glam::Vec3::new(0.0, 0.0, 0.0),
         rotation: glam::Quat::IDENTITY,
+        tint: [255; 4],
     };
 }
 
@@ -45,6 +53,7 @@ impl VertexBuffer for Instance {
                 self.position,
             ),
             normal: glam::Mat3::from_quat(self.rotation),
+            tint: self.tint,
         }
     }
 
@@ -59,10 +68,102 @@ impl VertexBuffer for Instance {
             9 => Float32x3,
             10 => Float32x3,
             11 => Float32x3,
+            12 => Unorm8x4, // tint
         ],
     };
 }
 
+/// A growable GPU buffer of model instances, holding the ones in view each frame.
+#[derive(Debug)]
+pub struct Instances {
+    pub name: String,
+    instances: Vec<Instance>,
+    buffer: wgpu::Buffer,
+    /// Number of instances `buffer` has room for.
+    capacity: usize,
+    num_uploaded: u32,
+}
+
+impl Instances {
+    pub fn new(device: &wgpu::Device, name: String, instances: Vec<Instance>) -> Self {
+        let raw: Vec<_> = instances.iter().map(Instance::to_raw).collect();
+        Self {
+            buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{name} / Instance Buffer")),
+                contents: bytemuck::cast_slice(&raw),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            }),
+            capacity: instances.len(),
+            num_uploaded: instances.len() as _,
+            instances,
+            name,
+        }
+    }
+
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    pub fn instances_mut(&mut self) -> &mut Vec<Instance> {
+        &mut self.instances
+    }
+
+    pub fn push(&mut self, instance: Instance) -> usize {
+        self.instances.push(instance);
+        self.instances.len() - 1
+    }
+
+    pub fn swap_remove(&mut self, i: usize) -> Instance {
+        self.instances.swap_remove(i)
+    }
+
+    /// Uploads the instances whose bounds may be inside `frustum`, reallocating the buffer if
+    /// they don't fit. `bounds` is the box around the model they're drawn with, in model space.
+    pub fn update_buffer(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frustum: &Frustum,
+        (min, max): (glam::Vec3, glam::Vec3),
+    ) {
+        // a sphere around the box, so rotated instances never need a new box
+        let center = 0.5 * (min + max);
+        let radius = 0.5 * (max - min).length();
+        let raw: Vec<_> = (self.instances.iter())
+            .filter(|instance| {
+                let center = instance.position + instance.rotation * (instance.scale * center);
+                let radius = radius * instance.scale.abs().max_element();
+                frustum.intersects_aabb(center - radius, center + radius)
+            })
+            .map(Instance::to_raw)
+            .collect();
+        let bytes: &[u8] = bytemuck::cast_slice(&raw);
+        if self.capacity < raw.len() {
+            self.capacity = raw.len().next_power_of_two();
+            self.buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&format!("{} / Instance Buffer", self.name)),
+                size: (mem::size_of::<InstanceRaw>() * self.capacity) as _,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: true,
+            });
+            self.buffer.slice(..).get_mapped_range_mut()[..bytes.len()].copy_from_slice(bytes);
+            self.buffer.unmap();
+        } else {
+            queue.write_buffer(&self.buffer, 0, bytes);
+        }
+        self.num_uploaded = raw.len() as _;
+    }
+
+    /// The range of instances present in the buffer, to pass to `Model::draw`.
+    pub fn range(&self) -> ops::Range<u32> {
+        0..self.num_uploaded
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
@@ -316,6 +417,7 @@ impl Model {
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         material_layout: &wgpu::BindGroupLayout,
+        anisotropy: u16,
     ) -> Result<Self> {
         let obj_path = obj_path.as_ref();
 
@@ -348,11 +450,31 @@ impl Model {
         for obj_material in obj_materials? {
             let diffuse_texture = obj_material.diffuse_texture.map(|path| async move {
                 let path = rel_path(path.as_ref());
-                Texture::load(device, queue, &path, false, &path.display().to_string()).await
+                let label = path.display().to_string();
+                Texture::load_aniso(
+                    device,
+                    queue,
+                    &path,
+                    false,
+                    &label,
+                    Mipmaps::Full,
+                    anisotropy,
+                )
+                .await
             });
             let normal_texture = obj_material.normal_texture.map(|path| async move {
                 let path = rel_path(path.as_ref());
-                Texture::load(device, queue, &path, true, &path.display().to_string()).await
+                let label = path.display().to_string();
+                Texture::load_aniso(
+                    device,
+                    queue,
+                    &path,
+                    true,
+                    &label,
+                    Mipmaps::Full,
+                    anisotropy,
+                )
+                .await
             });
             materials.push(Material::new(
                 device,
@@ -455,6 +577,16 @@ impl Model {
         Ok(Self { meshes, materials })
     }
 
+    /// Smallest and largest corner of the box around all the meshes' vertices.
+    pub fn bounds(&self) -> (glam::Vec3, glam::Vec3) {
+        let positions =
+            (self.meshes.iter()).flat_map(|mesh| mesh.vertices.iter().map(|v| v.position));
+        positions.fold(
+            (glam::Vec3::INFINITY, glam::Vec3::NEG_INFINITY),
+            |(min, max), pos| (min.min(pos), max.max(pos)),
+        )
+    }
+
     pub fn draw<'a>(
         &'a self,
         render_pass: &mut wgpu::RenderPass<'a>,
@@ -0,0 +1,107 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::sky::SkyBackend;
+
+/// File in the working directory the settings are read from and saved to.
+pub const SETTINGS_FILE: &str = "settings.toml";
+
+/// Options the player can change, read from `settings.toml` at startup and written back on
+/// exit. Missing fields keep their defaults, so older files still load.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Radius in chunks of the circle of chunks loaded around the camera.
+    pub render_distance: u32,
+    /// Maximum number of chunks meshed and uploaded each frame.
+    pub chunk_uploads_per_frame: usize,
+    /// Vertical field of view in degrees.
+    pub fov: f32,
+    pub mouse_sensitivity: f32,
+    #[serde(with = "PresentModeDef")]
+    pub present_mode: wgpu::PresentMode,
+    /// Fraction of the render distance the fog thickens over, from 0 for a sharp edge at the
+    /// last loaded chunks to 1 for fog starting at the camera.
+    pub fog_density: f32,
+    /// How the sky is drawn, the cubemap falls back to the gradient when its faces are missing.
+    pub sky: SkyBackend,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            render_distance: 3,
+            chunk_uploads_per_frame: 4,
+            fov: 70.0,
+            mouse_sensitivity: 20.0,
+            present_mode: wgpu::PresentMode::AutoVsync,
+            fog_density: 0.33,
+            sky: SkyBackend::Gradient,
+        }
+    }
+}
+
+impl Settings {
+    pub const MAX_RENDER_DISTANCE: u32 = 32;
+
+    /// Reads the settings at `path`, falling back to the defaults when the file is missing or
+    /// can't be parsed.
+    pub fn load(path: &Path) -> Self {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                tracing::info!("No {}, using the default settings", path.display());
+                return Self::default();
+            }
+            Err(err) => {
+                tracing::warn!("Failed to read {}: {err}", path.display());
+                return Self::default();
+            }
+        };
+        match toml::from_str::<Self>(&text) {
+            Ok(settings) => settings.clamped(),
+            Err(err) => {
+                tracing::warn!(
+                    "Failed to parse {}, using the defaults: {err}",
+                    path.display()
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = toml::to_string_pretty(self)?;
+        fs::write(path, text).with_context(|| format!("Failed to save {}", path.display()))
+    }
+
+    /// Brings values edited by hand back into their ranges.
+    pub fn clamped(mut self) -> Self {
+        self.render_distance = self.render_distance.clamp(1, Self::MAX_RENDER_DISTANCE);
+        self.chunk_uploads_per_frame = self.chunk_uploads_per_frame.max(1);
+        self.fog_density = self.fog_density.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Horizontal distances from the camera at which chunks start fading into the fog and are
+    /// fully faded, ending just inside the loaded chunks.
+    pub fn fade_range(&self) -> (f32, f32) {
+        let end = 32.0 * self.render_distance as f32;
+        ((1.0 - self.fog_density) * end, end)
+    }
+}
+
+/// Names of `wgpu::PresentMode`'s variants in the file, since wgpu's own serde support isn't
+/// enabled.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "wgpu::PresentMode")]
+enum PresentModeDef {
+    AutoVsync,
+    AutoNoVsync,
+    Fifo,
+    FifoRelaxed,
+    Immediate,
+    Mailbox,
+}
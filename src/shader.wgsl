@@ -14,14 +14,18 @@ struct InstanceInput {
     @location(9) normal_mat0: vec3<f32>,
     @location(10) normal_mat1: vec3<f32>,
     @location(11) normal_mat2: vec3<f32>,
+
+    @location(12) tint: vec4<f32>,
 };
 
 struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
+    // invariant so the depth prepass, computing it the same way, leaves the exact same depths
+    @builtin(position) @invariant clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
     @location(1) position: vec3<f32>,
     @location(2) normal: vec3<f32>,
     @location(3) tangent: vec3<f32>,
+    @location(4) tint: vec4<f32>,
 };
 
 struct CameraUniform {
@@ -34,11 +38,26 @@ var<uniform> camera: CameraUniform;
 struct LightUniform {
     dir: vec3<f32>,
     color: vec3<f32>,
+    sky_color: vec3<f32>,
+    ambient_intensity: f32,
+    ground_color: vec3<f32>,
+    // 0 at night and 1 during the day
+    daylight: f32,
 };
 
 @group(2) @binding(0)
 var<uniform> light: LightUniform;
 
+struct ShadowUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(2) @binding(1)
+var<uniform> shadow: ShadowUniform;
+@group(2) @binding(2)
+var t_shadow: texture_depth_2d;
+@group(2) @binding(3)
+var s_shadow: sampler_comparison;
+
 @vertex
 fn vs_main(
     model: VertexInput,
@@ -63,6 +82,7 @@ fn vs_main(
     out.clip_position = camera.view_proj * world_position;
     out.position = world_position.xyz / world_position.w;
     out.normal = normal_mat * model.normal;
+    out.tint = instance.tint;
 
     out.tangent = normalize(normal_mat * model.tangent);
     out.tangent = normalize(out.tangent - dot(out.tangent, out.normal) * out.normal);
@@ -70,6 +90,48 @@ fn vs_main(
     return out;
 }
 
+// Depth prepass, filling the scene depth the color pass then only shades the nearest faces of
+@vertex
+fn vs_depth(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> @builtin(position) @invariant vec4<f32> {
+    let model_mat = mat4x4<f32>(
+        instance.model_mat0,
+        instance.model_mat1,
+        instance.model_mat2,
+        instance.model_mat3,
+    );
+    let world_position = model_mat * vec4<f32>(model.position, 1.0);
+    return camera.view_proj * world_position;
+}
+
+// Hemispheric ambient light, from the sky above and the ground below
+fn ambient_light(normal: vec3<f32>) -> vec3<f32> {
+    return light.ambient_intensity * mix(light.ground_color, light.sky_color, 0.5 + 0.5 * normal.y);
+}
+
+// Fraction of the sun's light reaching `position`, filtering 3x3 texels of the shadow map
+fn sun_visibility(position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    // moving off the surface keeps faces from shadowing themselves
+    let light_position = shadow.view_proj * vec4(position + 0.05 * normal, 1.0);
+    let coords = light_position.xyz / light_position.w;
+    let uv = vec2(0.5 + 0.5 * coords.x, 0.5 - 0.5 * coords.y);
+    if any(uv < vec2(0.0)) || any(uv > vec2(1.0)) || coords.z < 0.0 {
+        return 1.0;
+    }
+
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_shadow));
+    var visibility = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2(f32(x), f32(y)) * texel;
+            visibility += textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, coords.z);
+        }
+    }
+    return visibility / 9.0;
+}
+
 // Fragment shader
 @group(0) @binding(0)
 var t_diffuse: texture_2d<f32>;
@@ -83,13 +145,11 @@ var s_normal: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let object_color: vec4<f32> = textureSample(t_diffuse, s_diffuse, in.tex_coords);
-
-    let ambient_strength = 0.02;
-    let ambient_color = ambient_strength * light.color;
+    let object_color: vec4<f32> = in.tint * textureSample(t_diffuse, s_diffuse, in.tex_coords);
 
     let tangent_normal = normalize(2 * textureSample(t_normal, s_normal, in.tex_coords).xyz - 1);
     let normal = normalize(in.normal);
+    let ambient_color = ambient_light(normal);
     let tangent = normalize(in.tangent);
     let bitangent = cross(normal, tangent);
     let TBN = transpose(mat3x3<f32>(tangent, bitangent, normal));
@@ -104,6 +164,7 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let specular_strength = pow(max(dot(tangent_normal, half_dir), 0.0), 32.0);
     let specular_color = specular_strength * light.color;
 
-    let result = (ambient_color + diffuse_color + specular_color) * object_color.rgb;
+    let sun = sun_visibility(in.position, normal);
+    let result = (ambient_color + sun * (diffuse_color + specular_color)) * object_color.rgb;
     return vec4<f32>(result, object_color.a);
 }
@@ -0,0 +1,187 @@
+use wgpu::util::DeviceExt;
+
+use crate::texture::Texture;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowUniform {
+    pub view_proj: glam::Mat4,
+}
+
+/// Shadows of the sun. The shadow casters around the camera are rendered into a depth map from
+/// the sun's point of view, which the scene passes sample through the light bind group.
+pub struct Shadow {
+    pub enabled: bool,
+    /// Width and height of the shadow map in texels.
+    resolution: u32,
+    /// Half the width of the square around the camera that casts and receives shadows, in
+    /// blocks.
+    pub extent: f32,
+
+    pub uniform: ShadowUniform,
+    pub uniform_buffer: wgpu::Buffer,
+    pub map: Texture,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    /// Binds the uniform for the shadow pass.
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Shadow {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let uniform = ShadowUniform {
+            view_proj: glam::Mat4::IDENTITY,
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shadow Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shadow Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shadow Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let resolution = 2048;
+        Self {
+            enabled: true,
+            resolution,
+            extent: 64.0,
+
+            uniform,
+            uniform_buffer,
+            map: Self::create_map(device, resolution),
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    fn create_map(device: &wgpu::Device, resolution: u32) -> Texture {
+        Texture::create_sized_depth_texture(device, resolution, resolution, 1, "Shadow Map")
+    }
+
+    pub fn resolution(&self) -> u32 {
+        self.resolution
+    }
+
+    /// Recreates the shadow map, the light bind group has to be recreated after this.
+    pub fn set_resolution(&mut self, device: &wgpu::Device, resolution: u32) {
+        self.resolution = resolution.clamp(1, device.limits().max_texture_dimension_2d);
+        self.map = Self::create_map(device, self.resolution);
+    }
+
+    /// Entries of the light bind group layout for the shadow uniform, map and sampler, after the
+    /// light uniform at binding 0.
+    pub fn light_layout_entries() -> [wgpu::BindGroupLayoutEntry; 3] {
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Depth,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                count: None,
+            },
+        ]
+    }
+
+    /// Entries of the light bind group matching `light_layout_entries`.
+    pub fn light_entries(&self) -> [wgpu::BindGroupEntry<'_>; 3] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: self.uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&self.map.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(&self.map.sampler),
+            },
+        ]
+    }
+
+    /// Fits the shadow map around `center`, lit from `light_dir`. The map moves in whole texels,
+    /// so shadow edges don't shimmer as the camera moves.
+    pub fn update(&mut self, queue: &wgpu::Queue, center: glam::Vec3, light_dir: glam::Vec3) {
+        let up = match light_dir.y.abs() < 0.99 {
+            true => glam::Vec3::Y,
+            false => glam::Vec3::Z,
+        };
+        let view = glam::Mat4::look_to_rh(glam::Vec3::ZERO, light_dir, up);
+        let texel = 2.0 * self.extent / self.resolution as f32;
+        let center = (view.transform_point3(center) / texel).round() * texel;
+
+        let extent = self.extent;
+        // reversed-Z like the scene, reaching further towards the sun for casters above the view
+        let (near, far) = (-center.z - 2.0 * extent, -center.z + extent);
+        let proj = glam::Mat4::orthographic_rh(
+            center.x - extent,
+            center.x + extent,
+            center.y - extent,
+            center.y + extent,
+            far,
+            near,
+        );
+        self.uniform.view_proj = proj * view;
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform]),
+        );
+    }
+
+    /// Starts the shadow pass, clearing the map to lit everywhere.
+    pub fn begin<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder) -> wgpu::RenderPass<'a> {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Shadow Render Pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.map.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        })
+    }
+}
@@ -0,0 +1,27 @@
+// Depth-only passes rendering the shadow casters from the sun's point of view
+
+struct ShadowUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> shadow: ShadowUniform;
+
+@vertex
+fn vs_chunk(
+    @location(0) position: vec3<f32>,
+    @location(5) offset: vec3<f32>,
+) -> @builtin(position) vec4<f32> {
+    return shadow.view_proj * vec4(position + offset, 1.0);
+}
+
+@vertex
+fn vs_model(
+    @location(0) position: vec3<f32>,
+    @location(5) model_mat0: vec4<f32>,
+    @location(6) model_mat1: vec4<f32>,
+    @location(7) model_mat2: vec4<f32>,
+    @location(8) model_mat3: vec4<f32>,
+) -> @builtin(position) vec4<f32> {
+    let model_mat = mat4x4(model_mat0, model_mat1, model_mat2, model_mat3);
+    return shadow.view_proj * model_mat * vec4(position, 1.0);
+}
@@ -0,0 +1,313 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use wgpu::util::DeviceExt;
+
+use crate::texture::Texture;
+
+/// Directory of the cubemap skybox's faces, named `px.png`, `nx.png`, `py.png`, `ny.png`,
+/// `pz.png` and `nz.png` after the axis each one faces.
+pub const SKYBOX_DIR: &str = "res/images/skybox";
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkyBackend {
+    /// Blend from the horizon color to the zenith color, without any assets.
+    #[default]
+    Gradient,
+    /// The six faces of a cubemap from `SKYBOX_DIR`, dimmed at night.
+    Cubemap,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkyUniform {
+    pub horizon_color: glam::Vec4,
+    pub zenith_color: glam::Vec4,
+    /// Factor the cubemap's colors are scaled by.
+    pub skybox_brightness: f32,
+    pub _pad1: [u32; 3],
+}
+
+/// Cubemap the skybox backend samples by the view ray's direction.
+pub struct Skybox {
+    pub texture: Texture,
+    pub bind_group: wgpu::BindGroup,
+}
+
+/// Sky drawn behind everything, either a procedural gradient blending from the horizon color to
+/// the zenith color by the view ray's height, or a cubemap skybox. The gradient's colors are
+/// kept up to date with either, as the fog and the ambient light follow them.
+pub struct Sky {
+    pub day_horizon_color: glam::Vec3,
+    pub day_zenith_color: glam::Vec3,
+    pub night_horizon_color: glam::Vec3,
+    pub night_zenith_color: glam::Vec3,
+    /// Brightness of the cubemap at night, it's drawn as is at noon.
+    pub night_skybox_brightness: f32,
+
+    pub uniform: SkyUniform,
+    pub uniform_buffer: wgpu::Buffer,
+    pub uniform_bind_group: wgpu::BindGroup,
+    pub render_pipeline: wgpu::RenderPipeline,
+
+    /// The backend drawn, only `Cubemap` once `skybox` is loaded.
+    backend: SkyBackend,
+    /// Loaded the first time the cubemap backend is picked.
+    skybox: Option<Skybox>,
+    skybox_bind_group_layout: wgpu::BindGroupLayout,
+    skybox_render_pipeline: wgpu::RenderPipeline,
+}
+
+impl Sky {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        sample_count: u32,
+    ) -> Self {
+        let uniform = SkyUniform {
+            horizon_color: glam::Vec4::ZERO,
+            zenith_color: glam::Vec4::ZERO,
+            skybox_brightness: 1.0,
+            _pad1: [0; 3],
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sky Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Sky Uniform Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sky Uniform Bind Group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let skybox_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Skybox Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::Cube,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+
+        let module = device.create_shader_module(wgpu::include_wgsl!("sky.wgsl"));
+        let render_pipeline = Self::create_render_pipeline(
+            device,
+            config,
+            "Sky Render Pipeline",
+            &[camera_bind_group_layout, &uniform_bind_group_layout],
+            &module,
+            "fs_main",
+            sample_count,
+        );
+        let skybox_render_pipeline = Self::create_render_pipeline(
+            device,
+            config,
+            "Skybox Render Pipeline",
+            &[
+                camera_bind_group_layout,
+                &uniform_bind_group_layout,
+                &skybox_bind_group_layout,
+            ],
+            &module,
+            "fs_skybox",
+            sample_count,
+        );
+
+        Self {
+            day_horizon_color: glam::vec3(0.70, 0.82, 0.95),
+            day_zenith_color: glam::vec3(0.25, 0.45, 0.85),
+            night_horizon_color: glam::vec3(0.05, 0.06, 0.12),
+            night_zenith_color: glam::vec3(0.0, 0.0, 0.03),
+            night_skybox_brightness: 0.1,
+
+            uniform,
+            uniform_buffer,
+            uniform_bind_group,
+            render_pipeline,
+
+            backend: SkyBackend::Gradient,
+            skybox: None,
+            skybox_bind_group_layout,
+            skybox_render_pipeline,
+        }
+    }
+
+    /// Fullscreen pipeline drawing the sky with `fs_entry_point`.
+    fn create_render_pipeline(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        label: &str,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        module: &wgpu::ShaderModule,
+        fs_entry_point: &str,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(
+                &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some(&format!("{label} Layout")),
+                    bind_group_layouts,
+                    push_constant_ranges: &[],
+                }),
+            ),
+            vertex: wgpu::VertexState {
+                module,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module,
+                entry_point: fs_entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // drawn first, behind everything, without touching the depth buffer
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        })
+    }
+
+    pub fn backend(&self) -> SkyBackend {
+        self.backend
+    }
+
+    /// Switches to drawing the sky with `backend`, loading the skybox from `SKYBOX_DIR` the first
+    /// time the cubemap is picked. Stays on the gradient when it can't be loaded.
+    pub fn set_backend(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, backend: SkyBackend) {
+        if backend == SkyBackend::Cubemap && self.skybox.is_none() {
+            match self.load_skybox(device, queue, Path::new(SKYBOX_DIR)) {
+                Ok(skybox) => self.skybox = Some(skybox),
+                Err(err) => {
+                    tracing::warn!("Failed to load the skybox, using the gradient sky: {err:#}");
+                    self.backend = SkyBackend::Gradient;
+                    return;
+                }
+            }
+        }
+        self.backend = backend;
+    }
+
+    fn load_skybox(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        dir: &Path,
+    ) -> Result<Skybox> {
+        let faces = ["px", "nx", "py", "ny", "pz", "nz"].map(|name| {
+            let path = dir.join(name).with_extension("png");
+            image::open(&path)
+                .with_context(|| format!("Failed to load {}", path.display()))
+                .map(|img| img.into_rgba8())
+        });
+        let [px, nx, py, ny, pz, nz] = faces;
+        let faces = [px?, nx?, py?, ny?, pz?, nz?];
+        let texture = Texture::cube(device, queue, &faces, "Skybox")?;
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Skybox Bind Group"),
+            layout: &self.skybox_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+        });
+        Ok(Skybox {
+            texture,
+            bind_group,
+        })
+    }
+
+    /// Blends between the night and day colors, `daylight` being 0 at night and 1 at noon.
+    pub fn update(&mut self, queue: &wgpu::Queue, daylight: f32) {
+        let daylight = daylight.clamp(0.0, 1.0);
+        let horizon = self
+            .night_horizon_color
+            .lerp(self.day_horizon_color, daylight);
+        let zenith = self
+            .night_zenith_color
+            .lerp(self.day_zenith_color, daylight);
+        self.uniform.horizon_color = horizon.extend(1.0);
+        self.uniform.zenith_color = zenith.extend(1.0);
+        self.uniform.skybox_brightness =
+            self.night_skybox_brightness + (1.0 - self.night_skybox_brightness) * daylight;
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform]),
+        );
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        match (self.backend, &self.skybox) {
+            (SkyBackend::Cubemap, Some(skybox)) => {
+                render_pass.set_pipeline(&self.skybox_render_pipeline);
+                render_pass.set_bind_group(2, &skybox.bind_group, &[]);
+            }
+            _ => render_pass.set_pipeline(&self.render_pipeline),
+        }
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,58 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view_position: vec3<f32>,
+    inv_view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct SkyUniform {
+    horizon_color: vec4<f32>,
+    zenith_color: vec4<f32>,
+    skybox_brightness: f32,
+};
+@group(1) @binding(0)
+var<uniform> sky: SkyUniform;
+
+@group(2) @binding(0)
+var t_skybox: texture_cube<f32>;
+@group(2) @binding(1)
+var s_skybox: sampler;
+
+// A single triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2(f32(vertex_index & 1u), f32(vertex_index >> 1u));
+    let ndc = 4.0 * uv - 1.0;
+
+    var out: VertexOutput;
+    // on the far plane, at depth 0 with reversed-Z
+    out.clip_position = vec4(ndc, 0.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+// Direction of the view ray through the fragment
+fn view_dir(ndc: vec2<f32>) -> vec3<f32> {
+    let far = camera.inv_view_proj * vec4(ndc, 1.0, 1.0);
+    return normalize(far.xyz / far.w - camera.view_position);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dir = view_dir(in.ndc);
+
+    let t = sqrt(clamp(dir.y, 0.0, 1.0));
+    return mix(sky.horizon_color, sky.zenith_color, t);
+}
+
+@fragment
+fn fs_skybox(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_skybox, s_skybox, view_dir(in.ndc));
+    return vec4(sky.skybox_brightness * color.rgb, 1.0);
+}
@@ -0,0 +1,105 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+};
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view_position: vec3<f32>,
+    inv_view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct SsaoUniform {
+    radius: f32,
+    intensity: f32,
+    sample_count: u32,
+};
+@group(1) @binding(0)
+var<uniform> ssao: SsaoUniform;
+@group(1) @binding(1)
+var t_depth: texture_depth_2d;
+
+// A single triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2(f32(vertex_index & 1u), f32(vertex_index >> 1u));
+
+    var out: VertexOutput;
+    out.clip_position = vec4(4.0 * uv - 1.0, 0.0, 1.0);
+    return out;
+}
+
+fn load_depth(coords: vec2<i32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(t_depth));
+    return textureLoad(t_depth, clamp(coords, vec2(0), size - 1), 0);
+}
+
+fn world_position(coords: vec2<i32>) -> vec3<f32> {
+    let uv = (vec2<f32>(coords) + 0.5) / vec2<f32>(textureDimensions(t_depth));
+    let ndc = vec2(2.0 * uv.x - 1.0, 1.0 - 2.0 * uv.y);
+    let pos = camera.inv_view_proj * vec4(ndc, load_depth(coords), 1.0);
+    return pos.xyz / pos.w;
+}
+
+// Of the differences to the two neighbours, picks the smaller one to avoid crossing edges
+fn min_diff(pos: vec3<f32>, a: vec3<f32>, b: vec3<f32>) -> vec3<f32> {
+    let da = a - pos;
+    let db = pos - b;
+    return select(db, da, dot(da, da) < dot(db, db));
+}
+
+fn hash(coords: vec2<i32>) -> f32 {
+    let p = vec2<f32>(coords);
+    return fract(52.9829189 * fract(dot(p, vec2(0.06711056, 0.00583715))));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(in.clip_position.xy);
+    if load_depth(coords) <= 0.0 {
+        // the sky
+        return vec4(1.0);
+    }
+
+    let pos = world_position(coords);
+    let dx = min_diff(pos, world_position(coords + vec2(1, 0)), world_position(coords - vec2(1, 0)));
+    let dy = min_diff(pos, world_position(coords + vec2(0, 1)), world_position(coords - vec2(0, 1)));
+    var normal = normalize(cross(dx, dy));
+    let to_camera = camera.view_position - pos;
+    if dot(normal, to_camera) < 0.0 {
+        normal = -normal;
+    }
+
+    // randomly rotated tangent frame to turn banding into noise
+    let angle = 6.2831853 * hash(coords);
+    let random = vec3(cos(angle), sin(angle), 0.0);
+    let helper = select(random, random.zxy, abs(dot(random, normal)) > 0.9);
+    let tangent = normalize(helper - normal * dot(helper, normal));
+    let bitangent = cross(normal, tangent);
+
+    let size = vec2<f32>(textureDimensions(t_depth));
+    var occlusion = 0.0;
+    for (var i = 0u; i < ssao.sample_count; i++) {
+        // points on a golden angle spiral over the hemisphere, denser close to the surface
+        let h = (f32(i) + 0.5) / f32(ssao.sample_count);
+        let phi = 2.3999632 * f32(i);
+        let dir = vec3(cos(phi) * sqrt(h), sin(phi) * sqrt(h), sqrt(1.0 - h));
+        let scale = mix(0.1, 1.0, h * h);
+        let sample = pos + ssao.radius * scale * (dir.x * tangent + dir.y * bitangent + dir.z * normal);
+
+        let clip = camera.view_proj * vec4(sample, 1.0);
+        let ndc = clip.xy / clip.w;
+        let sample_coords = vec2<i32>(vec2(0.5 + 0.5 * ndc.x, 0.5 - 0.5 * ndc.y) * size);
+        let scene = world_position(sample_coords);
+
+        let sample_distance = distance(sample, camera.view_position);
+        let scene_distance = distance(scene, camera.view_position);
+        // ignore occluders far in front of the sample so silhouettes don't get dark halos
+        let range = smoothstep(0.0, 1.0, ssao.radius / max(distance(scene, pos), 1e-4));
+        occlusion += select(0.0, range, scene_distance < sample_distance - 0.02);
+    }
+
+    let ao = clamp(1.0 - ssao.intensity * occlusion / f32(max(ssao.sample_count, 1u)), 0.0, 1.0);
+    return vec4(vec3(ao), 1.0);
+}
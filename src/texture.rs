@@ -3,6 +3,61 @@ use std::path::Path;
 use anyhow::*;
 use tokio::fs;
 
+/// Clamps the requested anisotropy level to one `adapter` can sample with.
+/// The result is a power of two in `1..=16`, as wgpu requires.
+pub fn supported_anisotropy(adapter: &wgpu::Adapter, requested: u16) -> u16 {
+    let max = match (adapter.get_downlevel_capabilities().flags)
+        .contains(wgpu::DownlevelFlags::ANISOTROPIC_FILTERING)
+    {
+        true => 16,
+        false => 1,
+    };
+    let level = 1 << requested.clamp(1, max).ilog2();
+    tracing::info!("Anisotropic filtering: requested {requested}x, using {level}x");
+    level
+}
+
+/// Which mip levels `Texture::from_image_with_mipmaps` fills in below the full image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mipmaps {
+    /// Only the full image.
+    None,
+    /// Every level down to 1x1.
+    Full,
+    /// For atlases of square tiles this many pixels wide, aligned to a grid of them. Stops at
+    /// the level where tiles are a single pixel, so no level averages pixels of different tiles
+    /// together. Linear filtering still blends the edge pixels of neighbouring tiles, so atlases
+    /// sampled with anisotropy can bleed a little at the smallest levels.
+    Tiled(u32),
+}
+
+impl Mipmaps {
+    pub fn level_count(self, width: u32, height: u32) -> u32 {
+        let full = width.max(height).max(1).ilog2() + 1;
+        match self {
+            Self::None => 1,
+            Self::Full => full,
+            Self::Tiled(tile) => full.min(tile.max(1).ilog2() + 1),
+        }
+    }
+}
+
+/// Halves `img` by averaging 2x2 blocks of pixels, leaving a size of 1 at 1. Pixels past an odd
+/// edge are clamped.
+fn downsample(img: &image::RgbaImage) -> image::RgbaImage {
+    let (width, height) = img.dimensions();
+    image::RgbaImage::from_fn((width / 2).max(1), (height / 2).max(1), |x, y| {
+        let mut sum = [0u32; 4];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let pixel = img.get_pixel((2 * x + dx).min(width - 1), (2 * y + dy).min(height - 1));
+            for (sum, &c) in sum.iter_mut().zip(&pixel.0) {
+                *sum += c as u32;
+            }
+        }
+        image::Rgba(sum.map(|c| ((c + 2) / 4) as u8))
+    })
+}
+
 #[derive(Debug)]
 pub struct Texture {
     pub texture: wgpu::Texture,
@@ -71,6 +126,76 @@ impl Texture {
         Self::from_bytes(device, queue, &fs::read(path).await?, is_normal, label)
     }
 
+    /// Like `load`, but also fills in smaller mip levels as described by `mipmaps`, and blends
+    /// between them when sampling.
+    pub async fn load_with_mipmaps(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+        is_normal: bool,
+        label: &str,
+        mipmaps: Mipmaps,
+    ) -> Result<Self> {
+        let img = image::load_from_memory(&fs::read(path).await?)?;
+        Self::from_image_with_mipmaps(device, queue, &img, is_normal, label, mipmaps)
+    }
+
+    /// Like `load_with_mipmaps`, but samples with the given anisotropy clamp, see
+    /// `set_anisotropy`.
+    pub async fn load_aniso(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+        is_normal: bool,
+        label: &str,
+        mipmaps: Mipmaps,
+        anisotropy: u16,
+    ) -> Result<Self> {
+        let mut texture =
+            Self::load_with_mipmaps(device, queue, path, is_normal, label, mipmaps).await?;
+        texture.set_anisotropy(device, label, anisotropy)?;
+        Ok(texture)
+    }
+
+    /// Switches to sampling with the given anisotropy clamp (see `supported_anisotropy`).
+    /// Anisotropic filtering requires linear filtering, so levels above 1 also switch the
+    /// sampler from nearest to linear.
+    ///
+    /// Anisotropy picks and blends mip levels along the direction the texture is squashed in,
+    /// so without mipmaps it only costs samples. `anisotropy` must be a power of two up to 16,
+    /// and levels above 1 need a texture with mipmaps.
+    pub fn set_anisotropy(
+        &mut self,
+        device: &wgpu::Device,
+        label: &str,
+        anisotropy: u16,
+    ) -> Result<()> {
+        ensure!(
+            anisotropy.is_power_of_two() && anisotropy <= 16,
+            "{label}: anisotropy must be a power of two in 1..=16, got {anisotropy}"
+        );
+        let mip_level_count = self.texture.mip_level_count();
+        ensure!(
+            anisotropy == 1 || 1 < mip_level_count,
+            "{label}: anisotropic filtering needs mipmaps"
+        );
+        if 1 < anisotropy {
+            self.sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some(&format!("{label} / Sampler")),
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Linear,
+                lod_max_clamp: mip_level_count as f32,
+                anisotropy_clamp: anisotropy,
+                ..Default::default()
+            });
+        }
+        Ok(())
+    }
+
     pub fn from_bytes(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
@@ -89,16 +214,86 @@ impl Texture {
         is_normal: bool,
         label: &str,
     ) -> Result<Self> {
-        let rgba = img.to_rgba8();
+        Self::from_image_with_mipmaps(device, queue, img, is_normal, label, Mipmaps::None)
+    }
+
+    pub fn from_image_with_mipmaps(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        is_normal: bool,
+        label: &str,
+        mipmaps: Mipmaps,
+    ) -> Result<Self> {
+        let layers = [img.to_rgba8()];
+        Self::from_rgba_layers(device, queue, &layers, is_normal, label, mipmaps, None)
+    }
+
+    /// A 2D texture array with a layer for each of `layers`, which must all be the same size.
+    /// Its view is a `D2Array` even with a single layer.
+    pub fn from_layers(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layers: &[image::RgbaImage],
+        is_normal: bool,
+        label: &str,
+        mipmaps: Mipmaps,
+    ) -> Result<Self> {
+        let dimension = Some(wgpu::TextureViewDimension::D2Array);
+        Self::from_rgba_layers(device, queue, layers, is_normal, label, mipmaps, dimension)
+    }
+
+    /// A cube texture from its square `faces`, in the order +X, -X, +Y, -Y, +Z, -Z, sampled
+    /// linearly.
+    pub fn cube(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[image::RgbaImage; 6],
+        label: &str,
+    ) -> Result<Self> {
+        let (width, height) = faces[0].dimensions();
+        ensure!(width == height, "{label}: cube faces must be square");
+        let dimension = Some(wgpu::TextureViewDimension::Cube);
+        let mut texture =
+            Self::from_rgba_layers(device, queue, faces, false, label, Mipmaps::None, dimension)?;
+        texture.sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(&format!("{label} / Sampler")),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Ok(texture)
+    }
+
+    fn from_rgba_layers(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layers: &[image::RgbaImage],
+        is_normal: bool,
+        label: &str,
+        mipmaps: Mipmaps,
+        view_dimension: Option<wgpu::TextureViewDimension>,
+    ) -> Result<Self> {
+        let Some(first) = layers.first() else {
+            bail!("{label}: a texture needs at least one layer");
+        };
+        let (width, height) = first.dimensions();
+        ensure!(
+            layers
+                .iter()
+                .all(|layer| layer.dimensions() == (width, height)),
+            "{label}: all layers must be {width}x{height}"
+        );
         let size = wgpu::Extent3d {
-            width: img.width(),
-            height: img.height(),
-            depth_or_array_layers: 1,
+            width,
+            height,
+            depth_or_array_layers: layers.len() as _,
         };
+        let mip_level_count = mipmaps.level_count(width, height);
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some(&format!("{label} / Texture")),
             size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: match is_normal {
@@ -108,23 +303,43 @@ impl Texture {
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
             view_formats: &[],
         });
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * size.width),
-                rows_per_image: Some(size.height),
-            },
-            size,
-        );
+        for (layer, rgba) in layers.iter().enumerate() {
+            let mut rgba = rgba.clone();
+            for mip_level in 0..mip_level_count {
+                if mip_level != 0 {
+                    rgba = downsample(&rgba);
+                }
+                let size = wgpu::Extent3d {
+                    width: rgba.width(),
+                    height: rgba.height(),
+                    depth_or_array_layers: 1,
+                };
+                queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &texture,
+                        mip_level,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: layer as _,
+                        },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    &rgba,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(4 * size.width),
+                        rows_per_image: Some(size.height),
+                    },
+                    size,
+                );
+            }
+        }
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: view_dimension,
+            ..Default::default()
+        });
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             label: Some(&format!("{label} / Sampler")),
             address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -132,7 +347,11 @@ impl Texture {
             address_mode_w: wgpu::AddressMode::ClampToEdge,
             mag_filter: wgpu::FilterMode::Nearest,
             min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: match mip_level_count {
+                1 => wgpu::FilterMode::Nearest,
+                _ => wgpu::FilterMode::Linear,
+            },
+            lod_max_clamp: mip_level_count as f32,
             ..Default::default()
         });
 
@@ -143,16 +362,78 @@ impl Texture {
         })
     }
 
+    /// A color texture that can be rendered to and then sampled.
+    pub fn create_render_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&format!("{label} / Texture")),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(&format!("{label} / Sampler")),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
     pub fn create_depth_texture(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
         label: &str,
+    ) -> Self {
+        Self::create_multisampled_depth_texture(device, config, 1, label)
+    }
+
+    pub fn create_multisampled_depth_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        Self::create_sized_depth_texture(device, config.width, config.height, sample_count, label)
+    }
+
+    /// A depth texture of any size, sampled with a comparison sampler.
+    pub fn create_sized_depth_texture(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+        label: &str,
     ) -> Self {
         let size = wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
 
@@ -160,7 +441,7 @@ impl Texture {
             label: Some(&format!("{label} / Texture")),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -175,7 +456,7 @@ impl Texture {
             mag_filter: wgpu::FilterMode::Linear,
             min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::FilterMode::Nearest,
-            compare: Some(wgpu::CompareFunction::LessEqual),
+            compare: Some(wgpu::CompareFunction::GreaterEqual),
             lod_min_clamp: 0.0,
             lod_max_clamp: 100.0,
             ..Default::default()
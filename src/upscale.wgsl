@@ -0,0 +1,26 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+// A single triangle covering the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2(f32(vertex_index & 1u), f32(vertex_index >> 1u));
+    let ndc = 4.0 * uv - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4(ndc, 0.0, 1.0);
+    out.tex_coords = vec2(0.5 * ndc.x + 0.5, 0.5 - 0.5 * ndc.y);
+    return out;
+}
+
+@group(0) @binding(0)
+var t_scene: texture_2d<f32>;
+@group(0) @binding(1)
+var s_scene: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(t_scene, s_scene, in.tex_coords);
+}
@@ -0,0 +1,39 @@
+/// Climate of a column of blocks, picking its surface and the tint of its plants.
+#[repr(u8)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Biome {
+    #[default]
+    Plains,
+    Desert,
+    Snow,
+}
+
+/// Which of a biome's colors a texture is tinted with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BiomeTint {
+    Grass,
+    Foliage,
+}
+
+impl Biome {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Plains),
+            1 => Some(Self::Desert),
+            2 => Some(Self::Snow),
+            _ => None,
+        }
+    }
+
+    pub fn tint(self, tint: BiomeTint) -> image::Rgba<u8> {
+        let rgb = match (self, tint) {
+            (Self::Plains, BiomeTint::Grass) => [0x97, 0xc6, 0x67],
+            (Self::Plains, BiomeTint::Foliage) => [0x77, 0xab, 0x2f],
+            (Self::Desert, BiomeTint::Grass) => [0xbf, 0xb7, 0x55],
+            (Self::Desert, BiomeTint::Foliage) => [0xae, 0xa4, 0x2a],
+            (Self::Snow, BiomeTint::Grass) => [0x80, 0xb4, 0x97],
+            (Self::Snow, BiomeTint::Foliage) => [0x60, 0xa1, 0x7b],
+        };
+        image::Rgba([rgb[0], rgb[1], rgb[2], 0xff])
+    }
+}
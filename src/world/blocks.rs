@@ -0,0 +1,265 @@
+use std::{mem, ops::Index};
+
+use ahash::HashMap;
+
+use super::chunk::{ChunkBlock, Lighting};
+
+/// The 32x32x32 blocks of a chunk, compressed with a palette.
+///
+/// Blocks without faces are described by their id, direction and state alone, so they're stored
+/// once in the palette and each block keeps only its index into it, packed into as few bits as
+/// the palette needs. Blocks with faces have face indices of their own and are kept whole on the
+/// side, which for terrain is only the blocks on its surface.
+#[derive(Debug)]
+pub struct ChunkBlocks {
+    /// Distinct blocks of the chunk, all without faces.
+    palette: Vec<ChunkBlock>,
+    /// Index of each block in `palette`.
+    palette_indices: HashMap<ChunkBlock, usize>,
+    /// Index into `palette` of each block, `bits` bits each, packed from the low bits of each
+    /// word up. Blocks in `faced` have stale indices.
+    indices: Vec<u64>,
+    /// Bits per palette index, a power of two so none straddle two words. 0 when the whole
+    /// chunk is one block.
+    bits: u32,
+    /// Blocks with faces, by index.
+    faced: HashMap<u16, ChunkBlock>,
+    /// Light in each cell, kept for every block as air is lit too.
+    light: Vec<Lighting>,
+}
+
+impl ChunkBlocks {
+    pub const LEN: usize = 1 << 15;
+
+    /// Compresses the chunk's blocks, in index order.
+    pub fn new(blocks: impl IntoIterator<Item = ChunkBlock>) -> Self {
+        let mut palette: Vec<ChunkBlock> = Vec::new();
+        let mut palette_indices = HashMap::default();
+        let mut faced = HashMap::default();
+        let mut indices = Vec::with_capacity(Self::LEN);
+        for (idx, block) in blocks.into_iter().enumerate() {
+            if block.has_faces() {
+                faced.insert(idx as u16, block);
+                indices.push(0);
+                continue;
+            }
+            let i = *palette_indices.entry(block).or_insert_with_key(|block| {
+                palette.push(block.clone());
+                palette.len() - 1
+            });
+            indices.push(i);
+        }
+        assert_eq!(indices.len(), Self::LEN, "a chunk has 32x32x32 blocks");
+        if palette.is_empty() {
+            let block = faced[&0].clone().without_faces();
+            palette_indices.insert(block.clone(), 0);
+            palette.push(block);
+        }
+
+        let mut blocks = Self {
+            palette,
+            palette_indices,
+            indices: Vec::new(),
+            bits: 0,
+            faced,
+            light: vec![Lighting::default(); Self::LEN],
+        };
+        blocks.bits = Self::bits_for(blocks.palette.len());
+        blocks.indices = vec![0; Self::words_for(blocks.bits)];
+        for (idx, i) in indices.into_iter().enumerate() {
+            blocks.write_index(idx, i);
+        }
+        blocks
+    }
+
+    pub fn get(&self, idx: usize) -> &ChunkBlock {
+        match self.faced.get(&(idx as u16)) {
+            Some(block) => block,
+            None => &self.palette[self.read_index(idx)],
+        }
+    }
+
+    /// Replaces the block at `idx`, moving it into the palette when it has no faces.
+    pub fn set(&mut self, idx: usize, block: ChunkBlock) {
+        if block.has_faces() {
+            self.faced.insert(idx as u16, block);
+            return;
+        }
+        self.faced.remove(&(idx as u16));
+        let i = match self.palette_indices.get(&block) {
+            Some(&i) => i,
+            None if self.palette.len() == Self::LEN => {
+                // edits only ever add to the palette, drop the entries no block uses anymore
+                self.repack(idx, block);
+                return;
+            }
+            None => {
+                self.palette.push(block.clone());
+                self.palette_indices.insert(block, self.palette.len() - 1);
+                self.palette.len() - 1
+            }
+        };
+        let bits = Self::bits_for(self.palette.len());
+        if self.bits < bits {
+            self.widen(bits);
+        }
+        self.write_index(idx, i);
+    }
+
+    /// Changes the block at `idx` through `f`, returning what `f` returns.
+    pub fn modify<R>(&mut self, idx: usize, f: impl FnOnce(&mut ChunkBlock) -> R) -> R {
+        let mut block = match self.faced.remove(&(idx as u16)) {
+            Some(block) => block,
+            None => self.get(idx).clone(),
+        };
+        let result = f(&mut block);
+        self.set(idx, block);
+        result
+    }
+
+    /// Changes every block through `f`, which sees each distinct block without faces once. `f`
+    /// must not give blocks faces.
+    pub fn update_all(&mut self, mut f: impl FnMut(&mut ChunkBlock)) {
+        self.palette.iter_mut().for_each(&mut f);
+        self.faced.values_mut().for_each(f);
+        // entries that became the same block keep their indices, only the last is looked up
+        self.palette_indices = (self.palette.iter().cloned())
+            .enumerate()
+            .map(|(i, block)| (block, i))
+            .collect();
+    }
+
+    /// Removes the faces of every block, moving them all back into the palette.
+    pub fn clear_faces(&mut self) {
+        for (idx, block) in mem::take(&mut self.faced) {
+            self.set(idx as usize, block.without_faces());
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ChunkBlock> + '_ {
+        (0..Self::LEN).map(|idx| self.get(idx))
+    }
+
+    pub fn light(&self, idx: usize) -> Lighting {
+        self.light[idx]
+    }
+
+    pub fn light_mut(&mut self, idx: usize) -> &mut Lighting {
+        &mut self.light[idx]
+    }
+
+    /// Bytes taken by the blocks, leaving out the allocator's overhead.
+    pub fn memory_size(&self) -> usize {
+        let data_size = |block: &ChunkBlock| block.data.as_ref().map_or(0, |data| data.len());
+        // the lookup holds a copy of every palette entry
+        let palette = self.palette.capacity() * mem::size_of::<ChunkBlock>()
+            + self.palette_indices.capacity() * mem::size_of::<(ChunkBlock, usize)>()
+            + 2 * self.palette.iter().map(data_size).sum::<usize>();
+        let faced = self.faced.capacity() * mem::size_of::<(u16, ChunkBlock)>()
+            + self.faced.values().map(data_size).sum::<usize>();
+        let indices = self.indices.capacity() * mem::size_of::<u64>();
+        let light = self.light.capacity() * mem::size_of::<Lighting>();
+        mem::size_of::<Self>() + palette + faced + indices + light
+    }
+
+    /// Bits per index for a palette of `len` blocks, rounded up to a power of two.
+    fn bits_for(len: usize) -> u32 {
+        match len {
+            0..=1 => 0,
+            _ => (usize::BITS - (len - 1).leading_zeros()).next_power_of_two(),
+        }
+    }
+
+    fn words_for(bits: u32) -> usize {
+        (Self::LEN * bits as usize).div_ceil(64)
+    }
+
+    fn read_index(&self, idx: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_word = 64 / self.bits as usize;
+        let shift = (idx % per_word) as u32 * self.bits;
+        (self.indices[idx / per_word] >> shift & ((1 << self.bits) - 1)) as usize
+    }
+
+    fn write_index(&mut self, idx: usize, i: usize) {
+        if self.bits == 0 {
+            return;
+        }
+        let per_word = 64 / self.bits as usize;
+        let shift = (idx % per_word) as u32 * self.bits;
+        let word = &mut self.indices[idx / per_word];
+        *word &= !(((1 << self.bits) - 1) << shift);
+        *word |= (i as u64) << shift;
+    }
+
+    /// Repacks the indices with `bits` bits each.
+    fn widen(&mut self, bits: u32) {
+        let indices: Vec<_> = (0..Self::LEN).map(|idx| self.read_index(idx)).collect();
+        self.bits = bits;
+        self.indices = vec![0; Self::words_for(bits)];
+        for (idx, i) in indices.into_iter().enumerate() {
+            self.write_index(idx, i);
+        }
+    }
+
+    /// Rebuilds the palette from the blocks with `block` in place of the one at `idx`, keeping
+    /// only the entries in use. There's one entry per block at most, so `block` always fits.
+    fn repack(&mut self, idx: usize, block: ChunkBlock) {
+        let light = mem::take(&mut self.light);
+        let mut blocks: Vec<_> = (0..Self::LEN).map(|idx| self.get(idx).clone()).collect();
+        blocks[idx] = block;
+        *self = Self::new(blocks);
+        self.light = light;
+    }
+}
+
+impl Index<usize> for ChunkBlocks {
+    type Output = ChunkBlock;
+
+    fn index(&self, idx: usize) -> &ChunkBlock {
+        self.get(idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::BlockFace;
+
+    /// A block made distinct from the others by its state.
+    fn block(n: u32) -> ChunkBlock {
+        ChunkBlock::new(1, BlockFace::Front, Some(Box::new(n.to_le_bytes())))
+    }
+
+    #[test]
+    fn set_into_a_full_palette_replaces_the_unused_entry() {
+        let mut blocks = ChunkBlocks::new((0..ChunkBlocks::LEN as u32).map(block));
+        assert_eq!(blocks.palette.len(), ChunkBlocks::LEN);
+
+        // every entry is in use, but the one of the replaced block no longer is
+        let n = ChunkBlocks::LEN as u32;
+        for (idx, n) in [(0, n), (7, n + 1), (0, n + 2)] {
+            blocks.set(idx, block(n));
+            assert_eq!(blocks[idx], block(n));
+            assert_eq!(blocks.palette.len(), ChunkBlocks::LEN);
+        }
+        assert_eq!(blocks[1], block(1));
+        assert_eq!(blocks[ChunkBlocks::LEN - 1], block(n - 1));
+
+        // blocks already in the palette are found again
+        blocks.set(1, block(n + 1));
+        assert_eq!(blocks.read_index(1), blocks.read_index(7));
+    }
+
+    #[test]
+    fn update_all_keeps_the_palette_lookup_current() {
+        let mut blocks = ChunkBlocks::new((0..ChunkBlocks::LEN as u32).map(|i| block(i % 3)));
+        blocks.update_all(|b| b.id = 2);
+        let moved = ChunkBlock { id: 2, ..block(1) };
+        blocks.set(0, moved.clone());
+        assert_eq!(blocks[0], moved);
+        assert_eq!(blocks.palette.len(), 3);
+    }
+}
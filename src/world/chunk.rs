@@ -1,11 +1,29 @@
-use std::{iter, mem};
+use std::{
+    array, fs,
+    io::{self, Read},
+    iter, mem,
+    ops::{Index, IndexMut, Range},
+    path::Path,
+};
 
 use rand::prelude::*;
-use wgpu::util::DeviceExt;
 
 use crate::graphics::{self, VertexBuffer};
 
-use super::{BlockData, BlockMeshType, BlockRegistry};
+use super::{
+    blocks::ChunkBlocks, Biome, BlockData, BlockMeshType, BlockRegistry, TerrainBlocks,
+    TerrainGenerator,
+};
+
+const MESH_CACHE_MAGIC: [u8; 4] = *b"MCMC";
+const MESH_CACHE_VERSION: u32 = 11;
+/// Magic, version, vertex size, content hash and face count.
+const MESH_CACHE_HEADER_LEN: usize = 4 + 4 + 4 + 8 + 4;
+
+const CHUNK_FILE_MAGIC: [u8; 4] = *b"MCCK";
+const CHUNK_FILE_VERSION: u32 = 2;
+/// Magic, version, chunk position and the biome of each column.
+const CHUNK_FILE_HEADER_LEN: usize = 4 + 4 + 12 + 32 * 32;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
@@ -32,8 +50,20 @@ impl VertexBuffer for ChunkInstance {
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
     pub position: glam::Vec3,
+    /// Coordinates within `tile`, wrapping around so merged quads repeat the texture.
     pub tex_coords: glam::Vec2,
     pub color: [u8; 4],
+    /// Atlas rect of the face's texture, its position followed by its size.
+    pub tile: [f32; 4],
+    /// Ambient occlusion from 0 for a fully occluded corner to 1 for an open one. The tint in
+    /// `color` only shifts hue and saturation, so it can't darken the face itself.
+    pub ao: f32,
+    /// Red, green and blue block light and sky light reaching the face.
+    pub light: [u8; 4],
+    /// Layer of the block texture `tile` is in.
+    pub layer: u32,
+    /// Frame count and frames per second of the face's texture, see `BlockTexture::animated`.
+    pub animation: [f32; 2],
 }
 
 impl VertexBuffer for Vertex {
@@ -48,11 +78,16 @@ impl VertexBuffer for Vertex {
             0 => Float32x3, // position
             1 => Float32x2, // tex_coords
             2 => Unorm8x4, // color
+            3 => Float32x4, // tile
+            4 => Float32, // ao
+            6 => Unorm8x4, // light
+            7 => Uint32, // layer
+            8 => Float32x2, // animation
         ],
     };
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, bytemuck::Zeroable)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, bytemuck::Zeroable)]
 #[repr(u8)]
 pub enum BlockFace {
     Back,
@@ -107,6 +142,21 @@ impl BlockFace {
             _ => None,
         }
     }
+    /// The face whose normal is closest to `dir`, the horizontal ones winning ties.
+    pub fn nearest(dir: glam::Vec3) -> Self {
+        let abs = dir.abs();
+        let (positive, negative, value) = if abs.x.max(abs.z) < abs.y {
+            (Self::Top, Self::Bottom, dir.y)
+        } else if abs.z < abs.x {
+            (Self::Right, Self::Left, dir.x)
+        } else {
+            (Self::Back, Self::Front, dir.z)
+        };
+        match 0.0 < value {
+            true => positive,
+            false => negative,
+        }
+    }
 
     pub fn is_edge(self, i: usize) -> bool {
         match self {
@@ -139,53 +189,238 @@ impl BlockFace {
         unsafe { Self::from_u8_unchecked(self as u8 ^ 1) }
     }
 
+    /// Rotation turning a block facing `Front` to face `self`. It turns about the vertical axis,
+    /// or about the x axis for `Top` and `Bottom`, which leaves the block's top facing `Back`
+    /// or `Front`.
+    pub fn rotation(self) -> glam::Mat3 {
+        let back = -self.voffset().as_vec3();
+        let up = match self {
+            Self::Top => Self::Back.voffset(),
+            Self::Bottom => Self::Front.voffset(),
+            _ => glam::IVec3::Y,
+        }
+        .as_vec3();
+        glam::Mat3::from_cols(up.cross(back), up, back)
+    }
+
+    /// The face of a block turned to face `dir` that ends up on the `self` side.
     pub fn on(self, dir: Self) -> Self {
-        match dir {
-            Self::Front => self,
-            Self::Back => match self {
-                Self::Top | Self::Bottom => self,
-                _ => self.flip(),
-            },
-            _ => match self {
-                Self::Front => dir.flip(),
-                Self::Back => dir,
-                _ if self == dir.flip() => Self::Back,
-                _ if self == dir => Self::Front,
-                _ => self,
-            },
+        let local = dir.rotation().transpose() * self.voffset().as_vec3();
+        Self::try_from_dir(local).unwrap()
+    }
+
+    /// Directions of a texture's u axis and of its top edge on the face of a block facing
+    /// `Front`, as seen from outside. Side textures stand upright, and the top's faces the front.
+    /// The axes' cross product points out of the face.
+    fn texture_axes(self) -> (glam::IVec3, glam::IVec3) {
+        match self {
+            Self::Right => (-glam::IVec3::Z, glam::IVec3::Y),
+            Self::Left => (glam::IVec3::Z, glam::IVec3::Y),
+            Self::Back => (glam::IVec3::X, glam::IVec3::Y),
+            Self::Front => (-glam::IVec3::X, glam::IVec3::Y),
+            Self::Top => (glam::IVec3::X, -glam::IVec3::Z),
+            Self::Bottom => (glam::IVec3::X, glam::IVec3::Z),
         }
     }
+
+    /// Corners of the unit cube's face on the `self` side and their texture coordinates, for a
+    /// block turned to face `dir`. The block's own face `self.on(dir)` is laid out unturned and
+    /// then turned with the block, so its texture is never mirrored. The corners go
+    /// counter-clockwise seen from outside, starting at the texture's top-left.
+    pub fn quad(self, dir: Self) -> [(glam::Vec3, glam::Vec2); 4] {
+        let local = self.on(dir);
+        let normal = local.voffset().as_vec3();
+        let (right, up) = local.texture_axes();
+        let rotation = dir.rotation();
+        [(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0)].map(|(u, v)| {
+            let corner = 0.5 * normal + (u - 0.5) * right.as_vec3() + (0.5 - v) * up.as_vec3();
+            (rotation * corner + 0.5, glam::vec2(u, v))
+        })
+    }
 }
 
-#[derive(Debug)]
+/// A value for each of the six faces of a block or chunk.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct FacedData<T>(pub [T; 6]);
+
+impl<T> FacedData<T> {
+    pub fn from_fn(mut f: impl FnMut(BlockFace) -> T) -> Self {
+        let mut faces = BlockFace::iter();
+        Self(array::from_fn(|_| f(faces.next().unwrap())))
+    }
+}
+
+impl<T> Index<BlockFace> for FacedData<T> {
+    type Output = T;
+    fn index(&self, face: BlockFace) -> &T {
+        &self.0[face as usize]
+    }
+}
+
+impl<T> IndexMut<BlockFace> for FacedData<T> {
+    fn index_mut(&mut self, face: BlockFace) -> &mut T {
+        &mut self.0[face as usize]
+    }
+}
+
+/// Light levels from 0 to 15 packed in nibbles: sky light, then red, green and blue block light.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Lighting(pub u16);
+
+impl Lighting {
+    pub const MAX: u8 = 15;
+    /// Channel of the sky light, after the red, green and blue block light.
+    pub const SKY: usize = 3;
+
+    pub fn sky(self) -> u8 {
+        (self.0 & 15) as u8
+    }
+    pub fn set_sky(&mut self, level: u8) {
+        self.0 = self.0 & !15 | level.min(Self::MAX) as u16;
+    }
+
+    pub fn block(self) -> [u8; 3] {
+        [1, 2, 3].map(|i| (self.0 >> (4 * i) & 15) as u8)
+    }
+    pub fn set_block(&mut self, rgb: [u8; 3]) {
+        self.0 &= 15;
+        for (i, level) in rgb.into_iter().enumerate() {
+            self.0 |= (level.min(Self::MAX) as u16) << (4 * (i + 1));
+        }
+    }
+
+    /// Level of one channel: red, green, blue, or [`Self::SKY`].
+    pub fn channel(self, channel: usize) -> u8 {
+        (self.0 >> Self::shift(channel) & 15) as u8
+    }
+    pub fn set_channel(&mut self, channel: usize, level: u8) {
+        let shift = Self::shift(channel);
+        self.0 = self.0 & !(15 << shift) | (level.min(Self::MAX) as u16) << shift;
+    }
+    fn shift(channel: usize) -> u32 {
+        4 * ((channel as u32 + 1) % 4)
+    }
+
+    /// The block light followed by the sky light, scaled to the full range of a `u8`.
+    pub fn to_unorm(self) -> [u8; 4] {
+        let [r, g, b] = self.block();
+        [r, g, b, self.sky()].map(|level| level * 17)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ChunkBlock {
     pub id: u32,
+    /// Low 16 bits of the index in `Chunk::vertices` of each of the block's faces, see `face`.
     pub faces: [u16; 6],
+    /// 17th bit of each face's index, bit `i` for face `i`.
     pub faces_bit16: u8,
     pub dir: BlockFace,
     pub data: Option<Box<[u8]>>,
 }
 
+bitflags::bitflags! {
+    #[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+    pub struct ChunkFlags: u8 {
+        /// Every block is transparent, so the chunk has no faces.
+        const EMPTY = 1 << 0;
+        /// Every block is opaque, so only the chunk's outer shell can be seen.
+        const SOLID = 1 << 1;
+    }
+}
+
 #[derive(Debug)]
 pub struct Chunk {
     pub pos: glam::IVec3,
-    pub blocks: Vec<ChunkBlock>,
+    pub flags: ChunkFlags,
+    /// The blocks and the light in their cells. Full blocks don't let light in, so theirs
+    /// stays dark unless they emit light.
+    pub blocks: ChunkBlocks,
+    /// Biome of each column of blocks, indexed by `x + 32 * z`.
+    pub biomes: Vec<Biome>,
+    /// Whether a block was placed since the chunk was generated or loaded, so it's saved
+    /// before it's unloaded.
+    pub modified: bool,
     pub vertices: Vec<[Vertex; 4]>,
     pub vertex_buffer: Option<wgpu::Buffer>,
-    pub index_buffer: Option<wgpu::Buffer>,
+    /// Faces of translucent blocks, kept out of the face table as they're sorted back to front
+    /// whenever the camera moves.
+    pub translucent_vertices: Vec<[Vertex; 4]>,
+    pub translucent_buffer: Option<wgpu::Buffer>,
+    /// Block the camera was in when the translucent faces were last sorted and uploaded.
+    translucent_sorted_for: Option<glam::IVec3>,
+    /// Range of faces edited since the vertex buffer was last uploaded.
+    dirty_faces: Option<Range<usize>>,
+    /// Recorded draw of the opaque faces and the number of faces it draws. Replayed every frame
+    /// until the buffers are recreated or the number of faces changes.
+    pub render_bundle: Option<(usize, wgpu::RenderBundle)>,
+    /// Merge adjacent coplanar faces when meshing. Merged quads can't be edited one at a time,
+    /// so editing a block remeshes the chunk face by face.
+    pub greedy: bool,
+    /// Whether the current mesh has merged quads, leaving the blocks' face table empty.
+    greedy_mesh: bool,
+    /// Whether the chunk is waiting to be meshed, from when it's created until `gen_mesh` or
+    /// `load_mesh_cache`, and again after `invalidate_mesh`. Edits in the meantime only change
+    /// the blocks and leave the faces to the next mesh.
+    needs_mesh: bool,
 }
 
 impl ChunkBlock {
+    /// Number of faces a chunk's face table can index. Face indices take 17 bits, with the
+    /// largest value standing for no face, which leaves indices `0..MAX_FACES`.
+    ///
+    /// A chunk of full blocks has at most `6 * 32^3 / 2` faces, a checkerboard of blocks and air.
+    /// Blocks that aren't full show all 6 faces, so a chunk of mostly those can exceed this.
+    pub const MAX_FACES: usize = (1 << 17) - 1;
+    /// Index stored for faces the block doesn't have.
+    const NO_FACE: usize = Self::MAX_FACES;
+
+    /// A block without faces.
+    pub fn new(id: u32, dir: BlockFace, data: Option<Box<[u8]>>) -> Self {
+        Self {
+            id,
+            faces: [!0; 6],
+            faces_bit16: !0,
+            dir,
+            data,
+        }
+    }
+
+    pub fn has_faces(&self) -> bool {
+        BlockFace::iter().any(|face| self.face(face).is_some())
+    }
+
+    /// The block with its faces removed.
+    pub fn without_faces(self) -> Self {
+        Self::new(self.id, self.dir, self.data)
+    }
+
+    /// Whether both blocks have the same id, direction and state, ignoring their faces.
+    pub fn same_kind(&self, other: &Self) -> bool {
+        self.id == other.id && self.dir == other.dir && self.data == other.data
+    }
+
+    /// Index in `Chunk::vertices` of the block's quad for `face`, `None` when the face is hidden
+    /// or the chunk is greedy meshed.
     pub fn face(&self, face: BlockFace) -> Option<usize> {
         let face = face as usize;
-        let value = ((self.faces_bit16 as usize >> face & 1) << 16) + self.faces[face] as usize;
-        (value + 1 < 1 << 17).then_some(value)
+        let value = ((self.faces_bit16 as usize >> face & 1) << 16) | self.faces[face] as usize;
+        (value != Self::NO_FACE).then_some(value)
     }
+
+    /// Panics if `value` is `MAX_FACES` or more, rather than storing an index pointing at
+    /// another block's face. Chunks switch to a greedy mesh before they have that many faces.
     pub fn set_face(&mut self, face: BlockFace, value: Option<usize>) {
         let assigned_face = face;
         let assigned_value = value;
-        debug_assert!(!value.is_some_and(|v| 1 << 17 - 1 <= v));
-        let value = value.unwrap_or((1 << 17) - 1);
+        if let Some(value) = value {
+            assert!(
+                value < Self::MAX_FACES,
+                "Face {value} is past the {} faces a chunk can index",
+                Self::MAX_FACES,
+            );
+        }
+        let value = value.unwrap_or(Self::NO_FACE);
 
         let face = face as usize;
         self.faces_bit16 &= !(1 << face);
@@ -199,13 +434,59 @@ impl ChunkBlock {
         &reg.blocks[self.id as usize]
     }
 
-    pub fn gen_face(&self, reg: &BlockRegistry, pos: glam::Vec3, face: BlockFace) -> [Vertex; 4] {
+    /// Value of the property `name` of the block's state, `None` if its block has no such
+    /// property.
+    pub fn state(&self, reg: &BlockRegistry, name: &str) -> Option<u32> {
+        self.data(reg).state.get(self.data.as_deref(), name)
+    }
+
+    /// Sets the property `name` of the block's state, returns whether it changed.
+    pub fn set_state(&mut self, reg: &BlockRegistry, name: &str, value: u32) -> bool {
+        (reg.blocks[self.id as usize].state).set(&mut self.data, name, value)
+    }
+
+    /// Bitmask of the horizontal faces a fence connects through, resolved from its neighbours.
+    pub fn connections(&self, reg: &BlockRegistry) -> u8 {
+        self.state(reg, "connections").unwrap_or(0) as u8
+    }
+
+    /// A post in the middle of the block, stretched to the sides the fence connects through.
+    /// Corners fill in the space between their two arms, as every block only has one box.
+    fn fence_box(&self, reg: &BlockRegistry) -> (glam::Vec3, glam::Vec3) {
+        let connections = self.connections(reg);
+        let connected = |face: BlockFace| connections & 1 << face as u8 != 0;
+        let mut min = glam::vec3(6.0, 0.0, 6.0) / 16.0;
+        let mut max = glam::vec3(10.0, 16.0, 10.0) / 16.0;
+        if connected(BlockFace::Right) {
+            max.x = 1.0;
+        }
+        if connected(BlockFace::Left) {
+            min.x = 0.0;
+        }
+        if connected(BlockFace::Back) {
+            max.z = 1.0;
+        }
+        if connected(BlockFace::Front) {
+            min.z = 0.0;
+        }
+        (min, max)
+    }
+
+    pub fn gen_face(
+        &self,
+        reg: &BlockRegistry,
+        pos: glam::Vec3,
+        face: BlockFace,
+        biome: Biome,
+    ) -> [Vertex; 4] {
         let data = self.data(reg);
 
         let face_on_block = face.on(self.dir);
         let texture = match data.mesh_type {
             BlockMeshType::Transparent => panic!("Transparent blocks should not be rendered"),
-            BlockMeshType::SameSided(coords) => coords,
+            BlockMeshType::SameSided(coords)
+            | BlockMeshType::Translucent(coords)
+            | BlockMeshType::Fence(coords) => coords,
             BlockMeshType::Surrounded { top, bottom, sides } => match face {
                 BlockFace::Top => top,
                 BlockFace::Bottom => bottom,
@@ -227,37 +508,29 @@ impl ChunkBlock {
                 BlockFace::Bottom => bottom,
             },
         };
+        let texture = (data.state_textures.iter())
+            .find(|variant| {
+                variant.face == face_on_block
+                    && self.state(reg, variant.property) == Some(variant.value)
+            })
+            .map_or(texture, |variant| variant.texture);
 
-        let mut vertices = [(0, 0), (1, 0), (1, 1), (0, 1)].map(|(i, j): (i8, i8)| {
-            use BlockFace as BF;
-            let axis = (face as u8 + 1 & 1) as _;
-            let local_pos = match (face, self.dir) {
-                (BF::Right | BF::Left, BF::Top) => glam::vec3(axis, i as _, (1 - j) as _),
-                (BF::Right | BF::Left, BF::Bottom) => glam::vec3(axis, (1 - i) as _, j as _),
-                (BF::Right | BF::Left, _) => glam::vec3(axis, (1 - j) as _, (1 - i) as _),
-
-                (BF::Front | BF::Back, BF::Left) => glam::vec3(i as _, (1 - j) as _, axis),
-                (BF::Front | BF::Back, BF::Top) => glam::vec3((1 - i) as _, j as _, axis),
-                (BF::Front | BF::Back, BF::Bottom) => glam::vec3(i as _, (1 - j) as _, axis),
-                (BF::Front | BF::Back, _) => glam::vec3(i as _, (1 - j) as _, axis),
-
-                (BF::Top | BF::Bottom, BF::Left) => glam::vec3((1 - j) as _, axis, i as _),
-                (BF::Top | BF::Bottom, BF::Back) => glam::vec3(i as _, axis, j as _),
-                (BF::Top | BF::Bottom, BF::Right) => glam::vec3(j as _, axis, (1 - i) as _),
-                (BF::Top | BF::Bottom, BF::Bottom) => glam::vec3(i as _, axis, j as _),
-                (BF::Top | BF::Bottom, _) => glam::vec3((1 - i) as _, axis, (1 - j) as _),
-            };
-            Vertex {
-                position: pos + local_pos,
-                tex_coords: texture.get(glam::vec2(i as _, j as _)),
+        let (box_min, box_max) = match data.mesh_type {
+            BlockMeshType::Fence(_) => self.fence_box(reg),
+            _ => (glam::Vec3::ZERO, glam::Vec3::ONE),
+        };
 
-                color: texture.color.0,
-            }
-        });
-        if face as u8 & 1 == 0 {
-            vertices.reverse();
-        }
-        vertices
+        face.quad(self.dir).map(|(corner, tex_coords)| Vertex {
+            position: pos + box_min + corner * (box_max - box_min),
+            tex_coords,
+
+            color: texture.color_in(biome).0,
+            tile: [texture.pos.x, texture.pos.y, texture.size.x, texture.size.y],
+            ao: 1.0,
+            light: [0; 4],
+            layer: texture.layer,
+            animation: [texture.frames as _, texture.fps],
+        })
     }
 }
 
@@ -268,6 +541,8 @@ impl Chunk {
         chunk_bind_group_layout: &wgpu::BindGroupLayout,
         camera_bind_group_layout: &wgpu::BindGroupLayout,
         light_bind_group_layout: &wgpu::BindGroupLayout,
+        world_bind_group_layout: &wgpu::BindGroupLayout,
+        sample_count: u32,
     ) -> wgpu::RenderPipeline {
         graphics::create_render_pipeline(
             device,
@@ -279,11 +554,122 @@ impl Chunk {
                     chunk_bind_group_layout,
                     camera_bind_group_layout,
                     light_bind_group_layout,
+                    world_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            }),
+            &[Vertex::DESC, ChunkInstance::DESC],
+            &device.create_shader_module(wgpu::include_wgsl!("chunk.wgsl")),
+            sample_count,
+        )
+    }
+
+    /// Pipeline for the opaque faces after the depth prepass, see
+    /// `graphics::create_prepassed_render_pipeline`.
+    pub fn create_prepassed_render_pipeline(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        chunk_bind_group_layout: &wgpu::BindGroupLayout,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        light_bind_group_layout: &wgpu::BindGroupLayout,
+        world_bind_group_layout: &wgpu::BindGroupLayout,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        graphics::create_prepassed_render_pipeline(
+            device,
+            config,
+            "Chunk Prepassed Render Pipeline",
+            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Chunk Prepassed Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    chunk_bind_group_layout,
+                    camera_bind_group_layout,
+                    light_bind_group_layout,
+                    world_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            }),
+            &[Vertex::DESC, ChunkInstance::DESC],
+            &device.create_shader_module(wgpu::include_wgsl!("chunk.wgsl")),
+            sample_count,
+        )
+    }
+
+    /// Depth-only pipeline drawing the opaque faces into the scene depth before the scene pass.
+    pub fn create_depth_prepass_render_pipeline(
+        device: &wgpu::Device,
+        chunk_bind_group_layout: &wgpu::BindGroupLayout,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        light_bind_group_layout: &wgpu::BindGroupLayout,
+        world_bind_group_layout: &wgpu::BindGroupLayout,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        graphics::create_depth_prepass_render_pipeline(
+            device,
+            "Chunk Depth Prepass Render Pipeline",
+            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Chunk Depth Prepass Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    chunk_bind_group_layout,
+                    camera_bind_group_layout,
+                    light_bind_group_layout,
+                    world_bind_group_layout,
+                ],
+                push_constant_ranges: &[],
+            }),
+            &[Vertex::DESC, ChunkInstance::DESC],
+            &device.create_shader_module(wgpu::include_wgsl!("chunk.wgsl")),
+            "vs_depth",
+            sample_count,
+        )
+    }
+
+    /// Depth-only pipeline drawing the opaque faces into the shadow map.
+    pub fn create_shadow_render_pipeline(
+        device: &wgpu::Device,
+        shadow_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::RenderPipeline {
+        graphics::create_shadow_render_pipeline(
+            device,
+            "Chunk Shadow Render Pipeline",
+            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Chunk Shadow Render Pipeline Layout"),
+                bind_group_layouts: &[shadow_bind_group_layout],
+                push_constant_ranges: &[],
+            }),
+            &[Vertex::DESC, ChunkInstance::DESC],
+            &device.create_shader_module(wgpu::include_wgsl!("../shadow.wgsl")),
+            "vs_chunk",
+        )
+    }
+
+    /// Pipeline for the translucent faces, blended over the opaque ones without writing depth.
+    pub fn create_translucent_render_pipeline(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        chunk_bind_group_layout: &wgpu::BindGroupLayout,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        light_bind_group_layout: &wgpu::BindGroupLayout,
+        world_bind_group_layout: &wgpu::BindGroupLayout,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        graphics::create_translucent_render_pipeline(
+            device,
+            config,
+            "Chunk Translucent Render Pipeline",
+            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Chunk Translucent Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    chunk_bind_group_layout,
+                    camera_bind_group_layout,
+                    light_bind_group_layout,
+                    world_bind_group_layout,
                 ],
                 push_constant_ranges: &[],
             }),
             &[Vertex::DESC, ChunkInstance::DESC],
             &device.create_shader_module(wgpu::include_wgsl!("chunk.wgsl")),
+            sample_count,
         )
     }
 
@@ -294,183 +680,444 @@ impl Chunk {
         pos.x as usize + pos.y as usize * 32 + pos.z as usize * 1024
     }
 
-    pub fn generate(pos: glam::IVec3) -> Self {
-        let blocks = (0..1 << 15)
-            .map(|i| {
-                let pos = Self::block_idx_to_pos(i);
-
-                let dir_id = 4;
-                let (id, dir);
-                if pos == glam::uvec3(16, 20, 16) {
-                    id = dir_id;
-                    dir = BlockFace::Front;
-                } else if pos == glam::uvec3(18, 20, 16) {
-                    id = dir_id;
-                    dir = BlockFace::Back;
-                } else if pos == glam::uvec3(20, 20, 16) {
-                    id = dir_id;
-                    dir = BlockFace::Right;
-                } else if pos == glam::uvec3(22, 20, 16) {
-                    id = dir_id;
-                    dir = BlockFace::Left;
-                } else if pos == glam::uvec3(24, 20, 16) {
-                    id = dir_id;
-                    dir = BlockFace::Top;
-                } else if pos == glam::uvec3(26, 20, 16) {
-                    id = dir_id;
-                    dir = BlockFace::Bottom;
-                } else {
-                    id = match pos.y {
-                        0..=9 => 1,
-                        10..=14 => 2,
-                        15 => 3,
-                        16..=31 => 0,
-                        _ => unreachable!(),
-                    };
-                    dir = BlockFace::iter()
-                        .take(4)
-                        .choose(&mut rand::thread_rng())
-                        .unwrap();
-                };
+    /// Biome of the column of the block at `idx`.
+    pub fn biome_at(&self, idx: usize) -> Biome {
+        let pos = Self::block_idx_to_pos(idx);
+        self.biomes[(pos.x + 32 * pos.z) as usize]
+    }
 
-                ChunkBlock {
-                    id,
-                    faces: [!0; 6],
-                    faces_bit16: !0,
-                    dir,
-                    data: None,
-                }
-            })
+    /// Generates the blocks `terrain` picks for the chunk out of `terrain_blocks`. Block
+    /// directions are picked with `rng`.
+    pub fn generate(
+        pos: glam::IVec3,
+        terrain: &dyn TerrainGenerator,
+        terrain_blocks: &TerrainBlocks,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let ids = terrain.fill_chunk(terrain_blocks, 32 * pos);
+        let blocks = ChunkBlocks::new(ids.into_iter().map(|id| {
+            let dir = BlockFace::iter().take(4).choose(rng).unwrap();
+
+            ChunkBlock::new(id, dir, None)
+        }));
+        let biomes = (0..32 * 32)
+            .map(|i| terrain.biome(32 * pos.x + (i & 31), 32 * pos.z + (i >> 5)))
             .collect();
 
         Self {
             pos,
+            flags: ChunkFlags::empty(),
             blocks,
+            biomes,
+            modified: false,
             vertices: Vec::new(),
             vertex_buffer: None,
-            index_buffer: None,
+            translucent_vertices: Vec::new(),
+            translucent_buffer: None,
+            translucent_sorted_for: None,
+            dirty_faces: None,
+            render_bundle: None,
+            greedy: false,
+            greedy_mesh: false,
+            needs_mesh: true,
         }
     }
 
     pub fn place_block(
         &mut self,
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
         reg: &BlockRegistry,
         idx: usize,
         id: u32,
         dir: BlockFace,
+        neighbours: &FacedData<Option<&Chunk>>,
     ) {
-        let block = &mut self.blocks[idx];
-        let was_transparent = block.data(reg).is_transparent();
+        let (was_transparent, was_full, was_translucent) = {
+            let data = self.blocks[idx].data(reg);
+            (data.is_transparent(), data.is_full(), data.is_translucent())
+        };
 
-        block.id = id;
-        block.dir = dir;
-        block.data = None;
+        self.modified = true;
+        self.blocks.modify(idx, |block| {
+            block.id = id;
+            block.dir = dir;
+            block.data = block.data(reg).state.default_data();
+        });
 
-        let is_transparent = block.data(reg).is_transparent();
+        let (is_transparent, is_full, is_translucent) = {
+            let data = self.blocks[idx].data(reg);
+            (data.is_transparent(), data.is_full(), data.is_translucent())
+        };
+        if was_transparent != is_transparent || was_full != is_full {
+            self.update_flags(reg);
+        }
 
-        if self.vertex_buffer.is_none() {
+        if self.needs_mesh {
             return;
         }
-        self.recreate_buffers_if_full(device);
-
-        let vertex_buffer = self.vertex_buffer.as_ref().unwrap();
-
-        let pos = Self::block_idx_to_pos(idx).as_vec3();
-        match (was_transparent, is_transparent) {
-            (true, true) => {}
-            (true, false) => {
-                // place block
-                // tracing::info!("Placing block at {:?}", pos);
-
-                for face in BlockFace::iter() {
-                    match idx
-                        .checked_add_signed(face.ioffset())
-                        .filter(|_| !face.is_edge(idx))
-                        .and_then(|j| Some((j, self.blocks.get(j)?)))
-                    {
-                        Some((j, neighbour)) if !neighbour.data(reg).is_transparent() => {
-                            self.remove_face(queue, j, face.flip());
-                        }
-                        _ => {
-                            self.add_face(queue, reg, idx, face);
-                        }
-                    }
-                }
+        if self.greedy_mesh {
+            self.resolve_state(reg, idx);
+            self.resolve_states_around(reg, idx);
+            self.gen_face_mesh(reg, neighbours);
+            return;
+        }
+
+        self.resolve_state(reg, idx);
+        self.resolve_states_around(reg, idx);
+        for face in BlockFace::iter() {
+            let visible = self.is_face_visible(reg, idx, face, neighbours);
+            self.update_face(reg, idx, face, visible, neighbours);
+            if let Some(j) = Self::neighbour_idx(idx, face) {
+                let visible = self.is_face_visible(reg, j, face.flip(), neighbours);
+                self.update_face(reg, j, face.flip(), visible, neighbours);
             }
-            (false, true) => {
-                // remove block
-
-                // tracing::info!("Removing block at {:?}", pos);
-                for face in BlockFace::iter() {
-                    match idx
-                        .checked_add_signed(face.ioffset())
-                        .filter(|_| !face.is_edge(idx))
-                        .and_then(|j| Some((j, self.blocks.get(j)?)))
-                    {
-                        Some((j, neighbour)) if !neighbour.data(reg).is_transparent() => {
-                            self.add_face(queue, reg, j, face.flip());
-                        }
-                        _ => {
-                            self.remove_face(queue, idx, face);
-                        }
-                    }
-                }
+        }
+        // the block can occlude the corners of any face around it, and change the states of
+        // its neighbours
+        let pos = Self::block_idx_to_pos(idx).as_ivec3();
+        for offset in (-1..=1)
+            .flat_map(|z| (-1..=1).flat_map(move |y| (-1..=1).map(move |x| glam::ivec3(x, y, z))))
+        {
+            let pos = pos + offset;
+            if offset != glam::IVec3::ZERO
+                && pos.cmpge(glam::IVec3::ZERO).all()
+                && pos.cmplt(glam::IVec3::splat(32)).all()
+            {
+                self.regen_faces(reg, Self::block_pos_to_idx(pos.as_uvec3()), neighbours);
             }
-            (false, false) => {
-                // replace block
-
-                // tracing::info!("Replacing block at {:?}", pos);
-                let block = &self.blocks[idx];
-                for face in BlockFace::iter() {
-                    let Some(face_i) = block.face(face) else {
-                        continue;
-                    };
-                    let vertices = block.gen_face(reg, pos, face);
-                    queue.write_buffer(
-                        vertex_buffer,
-                        (mem::size_of_val(&vertices) * face_i) as _,
-                        bytemuck::cast_slice(&vertices),
-                    );
-                }
+        }
+        if was_translucent || is_translucent || !self.translucent_vertices.is_empty() {
+            self.gen_translucent_mesh(reg, neighbours);
+        }
+    }
+
+    pub fn neighbour_idx(idx: usize, face: BlockFace) -> Option<usize> {
+        (idx.checked_add_signed(face.ioffset())).filter(|&j| !face.is_edge(idx) && j < 1 << 15)
+    }
+
+    /// Index of the block across the chunk's edge from `idx`, in the neighbouring chunk.
+    pub fn edge_neighbour_idx(idx: usize, face: BlockFace) -> usize {
+        let pos = Self::block_idx_to_pos(idx).as_ivec3() + face.voffset();
+        Self::block_pos_to_idx(pos.rem_euclid(glam::IVec3::splat(32)).as_uvec3())
+    }
+
+    /// Whether `face` of block `idx` should be meshed, looking into `neighbours` at the chunk's
+    /// edges. Faces are hidden behind full blocks, and between two translucent blocks of the same
+    /// kind. They're always shown next to a chunk that isn't loaded.
+    pub fn is_face_visible(
+        &self,
+        reg: &BlockRegistry,
+        idx: usize,
+        face: BlockFace,
+        neighbours: &FacedData<Option<&Chunk>>,
+    ) -> bool {
+        let data = self.blocks[idx].data(reg);
+        if data.is_transparent() {
+            return false;
+        } else if !data.is_full() && !data.is_translucent() {
+            return true;
+        }
+        let neighbour = match Self::neighbour_idx(idx, face) {
+            Some(j) => &self.blocks[j],
+            None => match neighbours[face] {
+                Some(chunk) => &chunk.blocks[Self::edge_neighbour_idx(idx, face)],
+                None => return true,
+            },
+        };
+        // translucent blocks of the same kind merge into one volume
+        let same_translucent = data.is_translucent() && neighbour.id == self.blocks[idx].id;
+        !neighbour.data(reg).is_full() && !same_translucent
+    }
+
+    /// Adds or removes a face of a block after the block across it changed, possibly in one of
+    /// the `neighbours`.
+    pub fn refresh_face(
+        &mut self,
+        reg: &BlockRegistry,
+        idx: usize,
+        face: BlockFace,
+        neighbours: &FacedData<Option<&Chunk>>,
+    ) {
+        if self.needs_mesh {
+            return;
+        } else if self.greedy_mesh {
+            self.gen_face_mesh(reg, neighbours);
+            return;
+        }
+        let visible = self.is_face_visible(reg, idx, face, neighbours);
+        self.update_face(reg, idx, face, visible, neighbours);
+        if !self.translucent_vertices.is_empty() {
+            self.gen_translucent_mesh(reg, neighbours);
+        }
+    }
+
+    /// Resolves the state of a block from its neighbours, returns whether it changed.
+    pub fn resolve_state(&mut self, reg: &BlockRegistry, idx: usize) -> bool {
+        if !matches!(
+            self.blocks[idx].data(reg).mesh_type,
+            BlockMeshType::Fence(_)
+        ) {
+            return false;
+        }
+        let mut connections = 0u8;
+        for face in [
+            BlockFace::Right,
+            BlockFace::Left,
+            BlockFace::Front,
+            BlockFace::Back,
+        ] {
+            let connects = Self::neighbour_idx(idx, face).is_some_and(|j| {
+                let data = self.blocks[j].data(reg);
+                data.is_full() || matches!(data.mesh_type, BlockMeshType::Fence(_))
+            });
+            if connects {
+                connections |= 1 << face as u8;
             }
         }
+        (self.blocks).modify(idx, |block| {
+            block.set_state(reg, "connections", connections as u32)
+        })
     }
 
-    /// Prior to calling this function, call `recreate_buffers_if_full` to ensure that the buffers are large enough
-    pub fn add_face(
+    /// Resolves the states of all the blocks, call before meshing.
+    pub fn resolve_states(&mut self, reg: &BlockRegistry) {
+        for idx in 0..ChunkBlocks::LEN {
+            self.resolve_state(reg, idx);
+        }
+    }
+
+    /// Resolves the states of the horizontal neighbours of `idx`.
+    fn resolve_states_around(&mut self, reg: &BlockRegistry, idx: usize) {
+        for face in [
+            BlockFace::Right,
+            BlockFace::Left,
+            BlockFace::Front,
+            BlockFace::Back,
+        ] {
+            if let Some(j) = Self::neighbour_idx(idx, face) {
+                self.resolve_state(reg, j);
+            }
+        }
+    }
+
+    /// Adds, removes or regenerates a face of a block to match whether it should be visible.
+    fn update_face(
         &mut self,
-        queue: &wgpu::Queue,
         reg: &BlockRegistry,
         idx: usize,
         face: BlockFace,
+        visible: bool,
+        neighbours: &FacedData<Option<&Chunk>>,
+    ) {
+        // translucent faces are meshed separately
+        let visible = visible && !self.blocks[idx].data(reg).is_translucent();
+        match (self.blocks[idx].face(face), visible) {
+            (Some(face_i), true) => {
+                self.vertices[face_i] = self.gen_quad(reg, idx, face, neighbours);
+                self.mark_dirty(face_i);
+            }
+            (Some(_), false) => self.remove_face(idx, face),
+            (None, true) => self.add_face(reg, idx, face, neighbours),
+            (None, false) => {}
+        }
+    }
+
+    /// Regenerates the existing faces of a block, after its state or surroundings changed.
+    fn regen_faces(
+        &mut self,
+        reg: &BlockRegistry,
+        idx: usize,
+        neighbours: &FacedData<Option<&Chunk>>,
     ) {
-        let Some(vertex_buffer) = &self.vertex_buffer else {
+        for face in BlockFace::iter() {
+            if let Some(face_i) = self.blocks[idx].face(face) {
+                self.vertices[face_i] = self.gen_quad(reg, idx, face, neighbours);
+                self.mark_dirty(face_i);
+            }
+        }
+    }
+
+    /// Regenerates the faces of the blocks at `idxs` after their light or surroundings changed.
+    /// Merged quads can't be patched, so a greedy mesh is dropped to be meshed again instead.
+    pub fn refresh_faces(
+        &mut self,
+        reg: &BlockRegistry,
+        idxs: impl IntoIterator<Item = usize>,
+        neighbours: &FacedData<Option<&Chunk>>,
+    ) {
+        if self.needs_mesh {
+            return;
+        } else if self.greedy_mesh {
+            self.invalidate_mesh();
             return;
+        }
+        for idx in idxs {
+            self.regen_faces(reg, idx, neighbours);
+        }
+        if !self.translucent_vertices.is_empty() {
+            self.gen_translucent_mesh(reg, neighbours);
+        }
+    }
+
+    /// The chunk and index of the block at `pos` relative to this chunk, looking into
+    /// `neighbours` for blocks just across one of its faces. Blocks past its edges or corners
+    /// aren't reachable.
+    fn locate<'a>(
+        &'a self,
+        pos: glam::IVec3,
+        neighbours: &FacedData<Option<&'a Chunk>>,
+    ) -> Option<(&'a Chunk, usize)> {
+        let chunk_offset = pos.div_euclid(glam::IVec3::splat(32));
+        let chunk = match chunk_offset {
+            glam::IVec3::ZERO => self,
+            offset => neighbours[BlockFace::try_from_dir(offset.as_vec3())?]?,
         };
+        let pos = pos.rem_euclid(glam::IVec3::splat(32)).as_uvec3();
+        Some((chunk, Self::block_pos_to_idx(pos)))
+    }
 
-        let block = &mut self.blocks[idx];
+    /// The block at `pos` relative to this chunk, see `locate`.
+    fn block_at<'a>(
+        &'a self,
+        pos: glam::IVec3,
+        neighbours: &FacedData<Option<&'a Chunk>>,
+    ) -> Option<&'a ChunkBlock> {
+        let (chunk, idx) = self.locate(pos, neighbours)?;
+        Some(&chunk.blocks[idx])
+    }
+
+    /// The light in the cell at `pos` relative to this chunk, see `locate`.
+    fn light_at(
+        &self,
+        pos: glam::IVec3,
+        neighbours: &FacedData<Option<&Chunk>>,
+    ) -> Option<Lighting> {
+        let (chunk, idx) = self.locate(pos, neighbours)?;
+        Some(chunk.blocks.light(idx))
+    }
+
+    /// Ambient occlusion at each corner of a full block's face quad, counting the full blocks
+    /// touching the corner in front of the face: 3 for none, down to 0 when both sides are.
+    fn face_ao(
+        &self,
+        reg: &BlockRegistry,
+        idx: usize,
+        face: BlockFace,
+        quad: &[Vertex; 4],
+        neighbours: &FacedData<Option<&Chunk>>,
+    ) -> [u8; 4] {
+        let block_pos = Self::block_idx_to_pos(idx).as_ivec3();
+        let front = block_pos + face.voffset();
+        let occludes =
+            |pos| (self.block_at(pos, neighbours)).is_some_and(|block| block.data(reg).is_full());
+        quad.map(|vertex| {
+            // steps towards the corner along the two axes of the face's plane
+            let steps = (vertex.position - block_pos.as_vec3() - 0.5)
+                .signum()
+                .as_ivec3()
+                * (glam::IVec3::ONE - face.voffset().abs());
+            let axes = [glam::IVec3::X, glam::IVec3::Y, glam::IVec3::Z].map(|axis| steps * axis);
+            let [side1, side2] = match axes.map(|axis| axis != glam::IVec3::ZERO) {
+                [false, _, _] => [axes[1], axes[2]],
+                [_, false, _] => [axes[0], axes[2]],
+                _ => [axes[0], axes[1]],
+            };
+            let (side1, side2) = (occludes(front + side1), occludes(front + side2));
+            if side1 && side2 {
+                0
+            } else {
+                3 - side1 as u8 - side2 as u8 - occludes(front + steps) as u8
+            }
+        })
+    }
+
+    /// Generates the quad of a block's face with its light and ambient occlusion. The quad is rotated so its
+    /// diagonal joins the brighter pair of corners, hiding the seam between its triangles.
+    fn gen_quad(
+        &self,
+        reg: &BlockRegistry,
+        idx: usize,
+        face: BlockFace,
+        neighbours: &FacedData<Option<&Chunk>>,
+    ) -> [Vertex; 4] {
+        let block = &self.blocks[idx];
+        let block_pos = Self::block_idx_to_pos(idx).as_ivec3();
+        let mut quad = block.gen_face(reg, block_pos.as_vec3(), face, self.biome_at(idx));
+        let is_full = block.data(reg).is_full();
+
+        // full blocks are lit by the cell in front of the face, others by their own cell
+        let lit_from = match is_full {
+            true => block_pos + face.voffset(),
+            false => block_pos,
+        };
+        let light = (self.light_at(lit_from, neighbours)).unwrap_or(self.blocks.light(idx));
+        for vertex in &mut quad {
+            vertex.light = light.to_unorm();
+        }
+        if !is_full {
+            return quad;
+        }
+        let ao = self.face_ao(reg, idx, face, &quad, neighbours);
+        for (vertex, ao) in quad.iter_mut().zip(ao) {
+            vertex.ao = ao as f32 / 3.0;
+        }
+        if ao[0] + ao[2] < ao[1] + ao[3] {
+            quad.rotate_left(1);
+        }
+        quad
+    }
+
+    pub fn update_flags(&mut self, reg: &BlockRegistry) {
+        let (mut empty, mut solid) = (true, true);
+        for block in self.blocks.iter() {
+            let data = block.data(reg);
+            if !data.is_full() {
+                solid = false;
+            }
+            if !data.is_transparent() {
+                empty = false;
+            }
+            if !empty && !solid {
+                break;
+            }
+        }
+        self.flags.set(ChunkFlags::EMPTY, empty);
+        self.flags.set(ChunkFlags::SOLID, solid);
+    }
+
+    /// Prior to calling this function, call `recreate_buffers_if_full` to ensure that the buffers are large enough
+    pub fn add_face(
+        &mut self,
+        reg: &BlockRegistry,
+        idx: usize,
+        face: BlockFace,
+        neighbours: &FacedData<Option<&Chunk>>,
+    ) {
+        // a greedy mesh has no face table to patch, as when an edit fell back to one
+        if self.needs_mesh || self.greedy_mesh {
+            return;
+        }
 
         let face_i = self.vertices.len();
-        block.set_face(face, Some(face_i as _));
+        if face_i == ChunkBlock::MAX_FACES {
+            self.gen_face_mesh(reg, neighbours);
+            return;
+        }
+        (self.blocks).modify(idx, |block| block.set_face(face, Some(face_i as _)));
 
-        let vertices = block.gen_face(reg, Self::block_idx_to_pos(idx).as_vec3(), face);
+        let vertices = self.gen_quad(reg, idx, face, neighbours);
         self.vertices.push(vertices);
-        let bytes = bytemuck::cast_slice(&vertices);
-        queue.write_buffer(vertex_buffer, (bytes.len() * face_i) as _, bytes);
+        self.mark_dirty(face_i);
     }
 
-    pub fn remove_face(&mut self, queue: &wgpu::Queue, idx: usize, face: BlockFace) {
-        let Some(vertex_buffer) = &self.vertex_buffer else {
+    pub fn remove_face(&mut self, idx: usize, face: BlockFace) {
+        if self.needs_mesh || self.greedy_mesh {
             return;
-        };
+        }
 
-        let block = &mut self.blocks[idx];
-        let Some(face_i) = block.face(face) else {
+        let Some(face_i) = self.blocks[idx].face(face) else {
             return;
         };
-        block.set_face(face, None);
+        (self.blocks).modify(idx, |block| block.set_face(face, None));
         self.vertices.swap_remove(face_i);
 
         if face_i == self.vertices.len() {
@@ -478,53 +1125,583 @@ impl Chunk {
         }
 
         let vertices = self.vertices[face_i];
+        let (idx, face) = Self::face_owner(&vertices);
+
+        debug_assert_eq!(self.blocks[idx].face(face), Some(self.vertices.len()));
+        (self.blocks).modify(idx, |block| block.set_face(face, Some(face_i as _)));
+        self.mark_dirty(face_i);
+    }
+
+    fn mark_all_dirty(&mut self) {
+        self.dirty_faces = Some(0..self.vertices.len());
+    }
+
+    fn mark_dirty(&mut self, face_i: usize) {
+        self.dirty_faces = Some(match self.dirty_faces.take() {
+            Some(range) => range.start.min(face_i)..range.end.max(face_i + 1),
+            None => face_i..face_i + 1,
+        });
+    }
+
+    /// Uploads the faces edited since the last upload. Uploads of at most `direct_limit` bytes
+    /// are written directly to the queue, larger ones go through the staging belt.
+    pub fn upload_mesh(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut wgpu::util::StagingBelt,
+        direct_limit: u64,
+    ) {
+        // the buffer is made once the chunk is first meshed
+        if self.vertex_buffer.is_some() || !self.needs_mesh {
+            self.recreate_buffers_if_full(device);
+        }
+        let (Some(vertex_buffer), Some(range)) = (&self.vertex_buffer, self.dirty_faces.take())
+        else {
+            return;
+        };
+        // faces removed from the end of the mesh don't need to be uploaded
+        let range = range.start..range.end.min(self.vertices.len());
+        let Some(size) =
+            wgpu::BufferSize::new(mem::size_of_val(&self.vertices[range.clone()]) as _)
+        else {
+            return;
+        };
+        let offset = (mem::size_of::<[Vertex; 4]>() * range.start) as _;
+        let bytes = bytemuck::cast_slice(&self.vertices[range]);
+        if size.get() <= direct_limit {
+            queue.write_buffer(vertex_buffer, offset, bytes);
+        } else {
+            staging_belt
+                .write_buffer(encoder, vertex_buffer, offset, size, device)
+                .copy_from_slice(bytes);
+        }
+    }
+
+    /// Returns the block index and face a quad of this chunk's mesh belongs to.
+    fn face_owner(vertices: &[Vertex; 4]) -> (usize, BlockFace) {
         let dir = (vertices[1].position - vertices[0].position)
             .cross(vertices[2].position - vertices[0].position);
         let middle = vertices.iter().map(|v| v.position).sum::<glam::Vec3>() / 4.0;
         let pos = middle - 0.5 * dir.normalize();
 
         let idx = Self::block_pos_to_idx(pos.as_uvec3());
-        let face = BlockFace::try_from_dir(dir).unwrap();
+        let face = BlockFace::try_from_dir(dir.normalize()).unwrap();
+        (idx, face)
+    }
 
-        debug_assert_eq!(self.blocks[idx].face(face), Some(self.vertices.len()));
-        self.blocks[idx].set_face(face, Some(face_i as _));
+    /// FNV-1a hash of the blocks' ids, directions, data and light, the biomes, the blocks
+    /// across the edges, and where the block textures are, used to validate cached meshes.
+    pub fn content_hash(&self, reg: &BlockRegistry, neighbours: &FacedData<Option<&Chunk>>) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        let mut write = |bytes: &[u8]| {
+            for &b in bytes {
+                hash ^= b as u64;
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        };
+        for (idx, block) in self.blocks.iter().enumerate() {
+            write(&block.id.to_le_bytes());
+            write(&[block.dir as u8]);
+            write(&self.blocks.light(idx).0.to_le_bytes());
+            match &block.data {
+                Some(data) => {
+                    write(&(data.len() as u32).to_le_bytes());
+                    write(data);
+                }
+                None => write(&[0xff; 4]),
+            }
+        }
+        write(
+            &self
+                .biomes
+                .iter()
+                .map(|&biome| biome as u8)
+                .collect::<Vec<_>>(),
+        );
+        write(&[self.greedy as u8]);
+        // the quads embed the texture rects and layers, which move with the texture backend
+        for tex in reg.face_textures() {
+            for v in [tex.pos, tex.size].into_iter().flat_map(|v| v.to_array()) {
+                write(&v.to_le_bytes());
+            }
+            write(&tex.layer.to_le_bytes());
+            write(&tex.frames.to_le_bytes());
+        }
+        for face in BlockFace::iter() {
+            let Some(chunk) = neighbours[face] else {
+                write(&[0]);
+                continue;
+            };
+            write(&[1]);
+            for idx in (0..1 << 15).filter(|&i| face.is_edge(i)) {
+                let neighbour_idx = Self::edge_neighbour_idx(idx, face);
+                write(&[chunk.blocks[neighbour_idx].data(reg).is_full() as u8]);
+                write(&chunk.blocks.light(neighbour_idx).0.to_le_bytes());
+            }
+        }
+        hash
+    }
+
+    pub fn file_name(pos: glam::IVec3) -> String {
+        format!("{}.{}.{}.chunk", pos.x, pos.y, pos.z)
+    }
+
+    /// Writes the chunk's blocks to `path`. Runs of identical blocks are stored once, prefixed
+    /// by their length.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(CHUNK_FILE_HEADER_LEN);
+        bytes.extend_from_slice(&CHUNK_FILE_MAGIC);
+        bytes.extend_from_slice(&CHUNK_FILE_VERSION.to_le_bytes());
+        for c in self.pos.to_array() {
+            bytes.extend_from_slice(&c.to_le_bytes());
+        }
+        bytes.extend(self.biomes.iter().map(|&biome| biome as u8));
+
+        let blocks: Vec<_> = self.blocks.iter().collect();
+        let mut i = 0;
+        while i < blocks.len() {
+            let block = blocks[i];
+            let run = (blocks[i..].iter())
+                .take(u16::MAX as usize)
+                .take_while(|b| block.same_kind(b))
+                .count();
+            bytes.extend_from_slice(&(run as u16).to_le_bytes());
+            bytes.extend_from_slice(&block.id.to_le_bytes());
+            bytes.push(block.dir as u8);
+            match &block.data {
+                Some(data) => {
+                    bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                    bytes.extend_from_slice(data);
+                }
+                None => bytes.extend_from_slice(&u32::MAX.to_le_bytes()),
+            }
+            i += run;
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, bytes)
+    }
+
+    /// Reads a chunk written by `save`. It has no mesh, which is built when it's next drawn.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_owned());
+
+        let bytes = fs::read(path)?;
+        if bytes.len() < CHUNK_FILE_HEADER_LEN || bytes[..4] != CHUNK_FILE_MAGIC {
+            return Err(invalid("not a chunk file"));
+        }
+        let mut reader = &bytes[4..];
+        fn read<const N: usize>(reader: &mut &[u8]) -> io::Result<[u8; N]> {
+            let mut buf = [0; N];
+            reader.read_exact(&mut buf)?;
+            Ok(buf)
+        }
+        let read_u32 = |reader: &mut &[u8]| read(reader).map(u32::from_le_bytes);
+
+        if read_u32(&mut reader)? != CHUNK_FILE_VERSION {
+            return Err(invalid("unsupported chunk file version"));
+        }
+        let pos = glam::ivec3(
+            read_u32(&mut reader)? as _,
+            read_u32(&mut reader)? as _,
+            read_u32(&mut reader)? as _,
+        );
+        let biomes = (read::<{ 32 * 32 }>(&mut reader)?.into_iter())
+            .map(|b| Biome::from_u8(b).ok_or_else(|| invalid("invalid biome")))
+            .collect::<io::Result<_>>()?;
+
+        let mut blocks = Vec::with_capacity(1 << 15);
+        while !reader.is_empty() {
+            let run = u16::from_le_bytes(read(&mut reader)?) as usize;
+            let id = read_u32(&mut reader)?;
+            let [dir] = read(&mut reader)?;
+            if 6 <= dir {
+                return Err(invalid("invalid block direction"));
+            }
+            // SAFETY: checked to be a valid face above
+            let dir = unsafe { BlockFace::from_u8_unchecked(dir) };
+            let data = match read_u32(&mut reader)? {
+                u32::MAX => None,
+                len if reader.len() < len as usize => return Err(invalid("truncated block data")),
+                len => {
+                    let mut data = vec![0; len as usize].into_boxed_slice();
+                    reader.read_exact(&mut data)?;
+                    Some(data)
+                }
+            };
+            if (1 << 15) < blocks.len() + run {
+                return Err(invalid("too many blocks"));
+            }
+            blocks.extend((0..run).map(|_| ChunkBlock::new(id, dir, data.clone())));
+        }
+        if blocks.len() != 1 << 15 {
+            return Err(invalid("too few blocks"));
+        }
+
+        Ok(Self {
+            pos,
+            flags: ChunkFlags::empty(),
+            blocks: ChunkBlocks::new(blocks),
+            biomes,
+            modified: false,
+            vertices: Vec::new(),
+            vertex_buffer: None,
+            translucent_vertices: Vec::new(),
+            translucent_buffer: None,
+            translucent_sorted_for: None,
+            dirty_faces: None,
+            render_bundle: None,
+            greedy: false,
+            greedy_mesh: false,
+            needs_mesh: true,
+        })
+    }
+
+    /// Fits the loaded states of the blocks to the properties their blocks have now, after
+    /// their ids were remapped.
+    pub fn load_states(&mut self, reg: &BlockRegistry) {
+        (self.blocks)
+            .update_all(|block| block.data = block.data(reg).state.load(block.data.take()));
+    }
 
-        let bytes = bytemuck::cast_slice(&vertices);
-        queue.write_buffer(vertex_buffer, (bytes.len() * face_i) as _, bytes);
+    /// Replaces every block id with its entry in `id_map`, ids missing from it become air.
+    pub fn remap_ids(&mut self, reg: &BlockRegistry, id_map: &[u32]) {
+        let air = reg.air();
+        (self.blocks).update_all(|block| {
+            block.id = id_map.get(block.id as usize).copied().unwrap_or(air);
+        });
+    }
+
+    pub fn mesh_cache_file_name(pos: glam::IVec3) -> String {
+        format!("{}.{}.{}.mesh", pos.x, pos.y, pos.z)
+    }
+
+    pub fn save_mesh_cache(
+        &self,
+        reg: &BlockRegistry,
+        neighbours: &FacedData<Option<&Chunk>>,
+        path: &Path,
+    ) -> io::Result<()> {
+        // merged quads of a face mesh that overflowed would load back as faces of single blocks
+        if self.greedy_mesh != self.greedy {
+            return Ok(());
+        }
+        let vertex_bytes: &[u8] = bytemuck::cast_slice(&self.vertices);
+        let mut bytes = Vec::with_capacity(MESH_CACHE_HEADER_LEN + vertex_bytes.len());
+        bytes.extend_from_slice(&MESH_CACHE_MAGIC);
+        bytes.extend_from_slice(&MESH_CACHE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(mem::size_of::<Vertex>() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.content_hash(reg, neighbours).to_le_bytes());
+        bytes.extend_from_slice(&(self.vertices.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(vertex_bytes);
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, bytes)
     }
 
-    pub fn gen_mesh(&mut self, device: &wgpu::Device, reg: &BlockRegistry) {
+    /// Loads the mesh cached at `path` instead of generating it.
+    /// Returns `false` if the cache is missing, corrupt or was made for different blocks.
+    pub fn load_mesh_cache(
+        &mut self,
+        reg: &BlockRegistry,
+        neighbours: &FacedData<Option<&Chunk>>,
+        path: &Path,
+    ) -> bool {
+        let Ok(bytes) = fs::read(path) else {
+            return false;
+        };
+        if bytes.len() < MESH_CACHE_HEADER_LEN {
+            return false;
+        }
+        let (header, vertex_bytes) = bytes.split_at(MESH_CACHE_HEADER_LEN);
+        let u32_at = |i: usize| u32::from_le_bytes(header[i..i + 4].try_into().unwrap());
+        let hash = u64::from_le_bytes(header[12..20].try_into().unwrap());
+        let num_faces = u32_at(20) as usize;
+        if header[..4] != MESH_CACHE_MAGIC
+            || u32_at(4) != MESH_CACHE_VERSION
+            || u32_at(8) != mem::size_of::<Vertex>() as u32
+            || hash != self.content_hash(reg, neighbours)
+            || vertex_bytes.len() != num_faces * mem::size_of::<[Vertex; 4]>()
+        {
+            return false;
+        }
+
+        let mut vertices = vec![[bytemuck::Zeroable::zeroed(); 4]; num_faces];
+        bytemuck::cast_slice_mut::<_, u8>(&mut vertices).copy_from_slice(vertex_bytes);
+
+        self.clear_faces();
+        if !self.greedy {
+            for (face_i, quad) in vertices.iter().enumerate() {
+                let (idx, face) = Self::face_owner(quad);
+                (self.blocks).modify(idx, |block| block.set_face(face, Some(face_i)));
+            }
+        }
+        self.greedy_mesh = self.greedy;
+        self.needs_mesh = false;
+        self.vertices = vertices;
+
+        self.mark_all_dirty();
+        self.gen_translucent_mesh(reg, neighbours);
+        true
+    }
+
+    /// Meshes the chunk, hiding faces at its edges against full blocks of loaded `neighbours`.
+    pub fn gen_mesh(&mut self, reg: &BlockRegistry, neighbours: &FacedData<Option<&Chunk>>) {
+        if self.greedy {
+            self.gen_greedy_mesh(reg, neighbours);
+        } else {
+            self.gen_face_mesh(reg, neighbours);
+        }
+    }
+
+    /// Whether the chunk is waiting for `gen_mesh`.
+    pub fn needs_mesh(&self) -> bool {
+        self.needs_mesh
+    }
+
+    /// Queues the chunk to be meshed again. The current mesh is still drawn until then, but
+    /// edits stop patching it.
+    pub fn invalidate_mesh(&mut self) {
+        self.needs_mesh = true;
+    }
+
+    fn clear_faces(&mut self) {
+        self.blocks.clear_faces();
+    }
+
+    /// Meshes the chunk with a quad for every visible face, which can be edited individually.
+    /// Falls back to the greedy mesher when there are more faces than the face table can index,
+    /// which keeps no face table and merges the faces of full blocks it can.
+    fn gen_face_mesh(&mut self, reg: &BlockRegistry, neighbours: &FacedData<Option<&Chunk>>) {
         self.vertices = Vec::new();
+        self.clear_faces();
+        self.greedy_mesh = false;
+        self.needs_mesh = false;
 
+        let air = reg.air();
         for i in 0..1 << 15 {
-            let pos = Self::block_idx_to_pos(i).as_vec3();
-
-            let block = &self.blocks[i];
-            if block.id == 0 {
+            if self.blocks[i].id == air || self.blocks[i].data(reg).is_translucent() {
                 continue;
             }
 
             for face in BlockFace::iter() {
-                if i.checked_add_signed(face.ioffset())
-                    .filter(|_| !face.is_edge(i))
-                    .and_then(|j| self.blocks.get(j))
-                    .is_some_and(|neighbour| !neighbour.data(reg).is_transparent())
-                {
+                if !self.is_face_visible(reg, i, face, neighbours) {
                     continue;
                 }
 
-                let block = &mut self.blocks[i];
-                block.set_face(face, Some(self.vertices.len() as _));
-                self.vertices.push(block.gen_face(reg, pos, face));
+                let face_i = self.vertices.len();
+                if face_i == ChunkBlock::MAX_FACES {
+                    tracing::warn!(
+                        "Chunk {} has more than {face_i} faces, meshing it without a face table",
+                        self.pos
+                    );
+                    self.gen_greedy_mesh(reg, neighbours);
+                    return;
+                }
+                (self.blocks).modify(i, |block| block.set_face(face, Some(face_i)));
+                let quad = self.gen_quad(reg, i, face, neighbours);
+                self.vertices.push(quad);
             }
         }
 
-        self.recreate_buffers_if_full(device);
+        #[cfg(debug_assertions)]
+        self.validate_winding();
+
+        self.mark_all_dirty();
+        self.gen_translucent_mesh(reg, neighbours);
+    }
+
+    /// Meshes the chunk, merging the visible faces of full blocks with the same id and direction
+    /// in each layer into rectangles. Faces of other blocks and occluded faces get a quad each.
+    fn gen_greedy_mesh(&mut self, reg: &BlockRegistry, neighbours: &FacedData<Option<&Chunk>>) {
+        self.vertices = Vec::new();
+        self.clear_faces();
+        self.greedy_mesh = true;
+        self.needs_mesh = false;
+
+        for face in BlockFace::iter() {
+            let normal_axis = match face {
+                BlockFace::Right | BlockFace::Left => 0,
+                BlockFace::Top | BlockFace::Bottom => 1,
+                BlockFace::Front | BlockFace::Back => 2,
+            };
+            let (u_axis, v_axis) = ((normal_axis + 1) % 3, (normal_axis + 2) % 3);
+
+            for layer in 0..32 {
+                let idx_at = |u: usize, v: usize| {
+                    let mut pos = glam::UVec3::ZERO;
+                    pos[normal_axis] = layer;
+                    pos[u_axis] = u as _;
+                    pos[v_axis] = v as _;
+                    Self::block_pos_to_idx(pos)
+                };
+
+                // id, direction, light and texture of each face in the layer that can be merged,
+                // the texture telling apart blocks whose state swaps it
+                let mut mask = [[None; 32]; 32];
+                for (v, row) in mask.iter_mut().enumerate() {
+                    for (u, cell) in row.iter_mut().enumerate() {
+                        let idx = idx_at(u, v);
+                        let block = &self.blocks[idx];
+                        if block.data(reg).is_translucent()
+                            || !self.is_face_visible(reg, idx, face, neighbours)
+                        {
+                            continue;
+                        }
+                        let quad = self.gen_quad(reg, idx, face, neighbours);
+                        let uniform = quad.iter().all(|v| v.ao == 1.0 && v.light == quad[0].light);
+                        if block.data(reg).is_full() && uniform {
+                            let texture = (quad[0].tile.map(f32::to_bits), quad[0].layer);
+                            *cell =
+                                Some((block.id, block.dir, quad[0].light, quad[0].color, texture));
+                        } else {
+                            self.vertices.push(quad);
+                        }
+                    }
+                }
+
+                for v in 0..32 {
+                    let mut u = 0;
+                    while u < 32 {
+                        let Some(key) = mask[v][u] else {
+                            u += 1;
+                            continue;
+                        };
+                        let width = (u..32).take_while(|&u| mask[v][u] == Some(key)).count();
+                        let height = (v..32)
+                            .take_while(|&v| mask[v][u..u + width].iter().all(|&c| c == Some(key)))
+                            .count();
+                        for row in &mut mask[v..v + height] {
+                            row[u..u + width].fill(None);
+                        }
+
+                        let idx = idx_at(u, v);
+                        let pos = Self::block_idx_to_pos(idx).as_vec3();
+                        let mut size = glam::Vec3::ONE;
+                        size[u_axis] = width as _;
+                        size[v_axis] = height as _;
+                        // lit like every face it covers, which all share the key's light
+                        let quad = self.gen_quad(reg, idx, face, neighbours);
+                        self.vertices.push(Self::stretch_face(quad, pos, size));
+                        u += width;
+                    }
+                }
+            }
+        }
+
+        self.mark_all_dirty();
+        self.gen_translucent_mesh(reg, neighbours);
+    }
+
+    /// Meshes the faces of the translucent blocks, to be sorted when they're uploaded.
+    fn gen_translucent_mesh(
+        &mut self,
+        reg: &BlockRegistry,
+        neighbours: &FacedData<Option<&Chunk>>,
+    ) {
+        let mut vertices = Vec::new();
+        for i in 0..1 << 15 {
+            if !self.blocks[i].data(reg).is_translucent() {
+                continue;
+            }
+            for face in BlockFace::iter() {
+                if self.is_face_visible(reg, i, face, neighbours) {
+                    vertices.push(self.gen_quad(reg, i, face, neighbours));
+                }
+            }
+        }
+        self.translucent_vertices = vertices;
+        self.translucent_sorted_for = None;
+    }
+
+    /// Sorts the translucent faces back to front from `cam_pos`, relative to the chunk, and
+    /// uploads them. They're only sorted again once the camera moves into another block.
+    pub fn upload_translucent(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        cam_pos: glam::Vec3,
+    ) {
+        let cam_block = cam_pos.floor().as_ivec3();
+        if self.translucent_vertices.is_empty() || self.translucent_sorted_for == Some(cam_block) {
+            return;
+        }
+        self.translucent_sorted_for = Some(cam_block);
+
+        let distance = |quad: &[Vertex; 4]| {
+            let center = quad.iter().map(|v| v.position).sum::<glam::Vec3>() / 4.0;
+            center.distance_squared(cam_pos)
+        };
+        (self.translucent_vertices).sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+
+        let size = mem::size_of_val(self.translucent_vertices.as_slice()) as u64;
+        if (self.translucent_buffer.as_ref()).is_none_or(|b| b.size() < size) {
+            let face_capacity = self.translucent_vertices.len() * 5 / 4;
+            self.translucent_buffer = Some(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&format!("Chunk {} Translucent Vertex Buffer", self.pos)),
+                size: (4 * mem::size_of::<Vertex>() * face_capacity) as _,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }));
+        }
+        let bytes = bytemuck::cast_slice(&self.translucent_vertices);
+        queue.write_buffer(self.translucent_buffer.as_ref().unwrap(), 0, bytes);
+    }
+
+    /// Stretches the quad of a full block's face at `pos` over `size` blocks, repeating its
+    /// texture once per block.
+    fn stretch_face(quad: [Vertex; 4], pos: glam::Vec3, size: glam::Vec3) -> [Vertex; 4] {
+        let corners = quad.map(|v| v.position - pos);
+        let mut stretched = quad;
+        for t in 0..2 {
+            // the axis this texture coordinate runs along, either way
+            let axis = (0..3).find(|&axis| {
+                let along = |k: usize| corners[k][axis] == quad[k].tex_coords[t];
+                let against = |k: usize| corners[k][axis] == 1.0 - quad[k].tex_coords[t];
+                (0..4).all(along) || (0..4).all(against)
+            });
+            if let Some(axis) = axis {
+                for vertex in &mut stretched {
+                    vertex.tex_coords[t] *= size[axis];
+                }
+            }
+        }
+        for (vertex, corner) in stretched.iter_mut().zip(corners) {
+            vertex.position = pos + corner * size;
+        }
+        stretched
+    }
+
+    /// Logs every quad whose winding doesn't face outwards along the face it was meshed for.
+    #[cfg(debug_assertions)]
+    fn validate_winding(&self) {
+        for (idx, block) in self.blocks.iter().enumerate() {
+            for face in BlockFace::iter() {
+                let Some(face_i) = block.face(face) else {
+                    continue;
+                };
+                let v = &self.vertices[face_i];
+                let normal = (v[1].position - v[0].position).cross(v[2].position - v[0].position);
+                if normal.normalize_or_zero().dot(face.voffset().as_vec3()) < 0.99 {
+                    tracing::warn!(
+                        "Chunk {} block {idx} ({}) has a {face:?} face wound towards {normal}",
+                        self.pos,
+                        Self::block_idx_to_pos(idx),
+                    );
+                }
+            }
+        }
     }
 
     fn recreate_buffers_if_full(&mut self, device: &wgpu::Device) {
         if self.vertex_buffer.as_ref().is_some_and(|b| {
-            4 * mem::size_of::<Vertex>() * (self.vertices.len() + 6) <= b.size() as usize
+            4 * mem::size_of::<Vertex>() * (self.vertices.len() + 12) <= b.size() as usize
         }) {
             return;
         }
@@ -538,39 +1715,452 @@ impl Chunk {
                 .map(|b| b.size() / (4 * mem::size_of::<Vertex>()) as u64)
         );
 
-        let vertex_buffer =
-            self.vertex_buffer
-                .insert(device.create_buffer(&wgpu::BufferDescriptor {
-                    label: Some(&format!("Chunk {} Vertex Buffer", self.pos)),
-                    size: (4 * mem::size_of::<Vertex>() * face_capacity) as _,
-                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                    mapped_at_creation: true,
-                }));
-        let bytes = bytemuck::cast_slice(&self.vertices);
-        vertex_buffer
-            .slice(..bytes.len() as u64)
-            .get_mapped_range_mut()
-            .copy_from_slice(bytes);
-        vertex_buffer.unmap();
-
-        if (self.index_buffer)
-            .as_ref()
-            .is_some_and(|b| 24 * face_capacity <= b.size() as usize)
-        {
-            return;
+        self.vertex_buffer = Some(device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("Chunk {} Vertex Buffer", self.pos)),
+            size: (4 * mem::size_of::<Vertex>() * face_capacity) as _,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+        // filled by the next `upload_mesh`, batched with the other chunks' uploads
+        self.mark_all_dirty();
+        self.render_bundle = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::SmallRng, SeedableRng};
+
+    use super::*;
+    use crate::world::FlatTerrain;
+
+    const NO_NEIGHBOURS: FacedData<Option<&Chunk>> = FacedData([None; 6]);
+
+    fn registry() -> BlockRegistry {
+        BlockRegistry::new(BlockRegistry::default_blocks(), Vec::new(), Vec::new())
+    }
+
+    /// A chunk of flat terrain with its surface at `surface`, in blocks from its bottom.
+    fn flat_chunk(reg: &BlockRegistry, surface: i32) -> Chunk {
+        let terrain = FlatTerrain { surface };
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut chunk = Chunk::generate(glam::IVec3::ZERO, &terrain, &reg.terrain_blocks, &mut rng);
+        chunk.update_flags(reg);
+        chunk.resolve_states(reg);
+        chunk
+    }
+
+    fn vertex_bytes(vertices: &[[Vertex; 4]]) -> &[u8] {
+        bytemuck::cast_slice(vertices)
+    }
+
+    #[test]
+    fn mesh_cache_hit_matches_a_fresh_mesh() {
+        let reg = registry();
+        let path = std::env::temp_dir().join(format!("mclone-{}-mesh-cache", std::process::id()));
+
+        let mut chunk = flat_chunk(&reg, 16);
+        let furnace = reg.block_map["furnace"];
+        chunk.place_block(
+            &reg,
+            Chunk::block_pos_to_idx(glam::uvec3(3, 17, 5)),
+            furnace,
+            BlockFace::Front,
+            &NO_NEIGHBOURS,
+        );
+        chunk.gen_mesh(&reg, &NO_NEIGHBOURS);
+        chunk.save_mesh_cache(&reg, &NO_NEIGHBOURS, &path).unwrap();
+
+        let mut cached = flat_chunk(&reg, 16);
+        cached.place_block(
+            &reg,
+            Chunk::block_pos_to_idx(glam::uvec3(3, 17, 5)),
+            furnace,
+            BlockFace::Front,
+            &NO_NEIGHBOURS,
+        );
+        assert_eq!(
+            cached.content_hash(&reg, &NO_NEIGHBOURS),
+            chunk.content_hash(&reg, &NO_NEIGHBOURS)
+        );
+        assert!(cached.load_mesh_cache(&reg, &NO_NEIGHBOURS, &path));
+        assert!(!cached.needs_mesh());
+        assert_eq!(
+            vertex_bytes(&cached.vertices),
+            vertex_bytes(&chunk.vertices)
+        );
+        for idx in 0..ChunkBlocks::LEN {
+            for face in BlockFace::iter() {
+                assert_eq!(cached.blocks[idx].face(face), chunk.blocks[idx].face(face));
+            }
         }
 
-        self.index_buffer = Some(
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("Chunk {} Index Buffer", self.pos)),
-                contents: bytemuck::cast_slice(
-                    &(0..face_capacity as u32)
-                        .map(|f| [0, 1, 2, 2, 3, 0].map(|i| 4 * f + i))
-                        .flatten()
-                        .collect::<Vec<u32>>(),
-                ),
-                usage: wgpu::BufferUsages::INDEX,
-            }),
+        // any other block misses the cache
+        let mut edited = flat_chunk(&reg, 16);
+        edited.place_block(
+            &reg,
+            Chunk::block_pos_to_idx(glam::uvec3(3, 17, 5)),
+            furnace,
+            BlockFace::Back,
+            &NO_NEIGHBOURS,
+        );
+        assert_ne!(
+            edited.content_hash(&reg, &NO_NEIGHBOURS),
+            chunk.content_hash(&reg, &NO_NEIGHBOURS)
+        );
+        assert!(!edited.load_mesh_cache(&reg, &NO_NEIGHBOURS, &path));
+
+        // and so do the same blocks with their textures moved, as another texture backend does
+        let mut blocks = BlockRegistry::default_blocks();
+        for block in &mut blocks {
+            block
+                .mesh_type
+                .textures_mut()
+                .into_iter()
+                .for_each(|tex| tex.layer = 1);
+        }
+        let layered = BlockRegistry::new(blocks, Vec::new(), Vec::new());
+        assert!(!cached.load_mesh_cache(&layered, &NO_NEIGHBOURS, &path));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn saved_chunk_loads_back_identical() {
+        let reg = registry();
+        let path = std::env::temp_dir().join(format!("mclone-{}-save.chunk", std::process::id()));
+
+        let mut chunk = flat_chunk(&reg, 16);
+        chunk.pos = glam::ivec3(-4, 1, 9);
+        let placed = [
+            (glam::uvec3(0, 17, 0), "furnace", BlockFace::Left),
+            (glam::uvec3(31, 31, 31), "glass", BlockFace::Front),
+            (glam::uvec3(5, 3, 7), "air", BlockFace::Front),
+            (glam::uvec3(6, 17, 7), "cobblestone_wall", BlockFace::Front),
+        ];
+        for (pos, name, dir) in placed {
+            let idx = Chunk::block_pos_to_idx(pos);
+            chunk.place_block(&reg, idx, reg.block_map[name], dir, &NO_NEIGHBOURS);
+        }
+        let idx = Chunk::block_pos_to_idx(glam::uvec3(9, 17, 9));
+        (chunk.blocks).modify(idx, |block| block.data = Some(Box::new([1, 2, 3])));
+
+        chunk.save(&path).unwrap();
+        let loaded = Chunk::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.pos, chunk.pos);
+        assert_eq!(loaded.biomes, chunk.biomes);
+        assert!(loaded.blocks.iter().eq(chunk.blocks.iter()));
+        assert!(loaded.needs_mesh());
+    }
+
+    #[test]
+    fn chunk_files_of_other_versions_are_rejected() {
+        let reg = registry();
+        let path =
+            std::env::temp_dir().join(format!("mclone-{}-version.chunk", std::process::id()));
+
+        flat_chunk(&reg, 16).save(&path).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[4..8].copy_from_slice(&(CHUNK_FILE_VERSION + 1).to_le_bytes());
+        fs::write(&path, bytes).unwrap();
+        let err = Chunk::load(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn face_ao_counts_the_blocks_around_each_corner() {
+        let reg = registry();
+        let cobblestone = reg.block_map["cobblestone"];
+        let mut chunk = flat_chunk(&reg, -100);
+        let place = |chunk: &mut Chunk, pos: glam::UVec3| {
+            let idx = Chunk::block_pos_to_idx(pos);
+            chunk.place_block(&reg, idx, cobblestone, BlockFace::Front, &NO_NEIGHBOURS);
+        };
+        place(&mut chunk, glam::uvec3(10, 10, 10));
+        // in front of the top face: the +X and +Z sides, and the corner between +X and -Z
+        place(&mut chunk, glam::uvec3(11, 11, 10));
+        place(&mut chunk, glam::uvec3(10, 11, 11));
+        place(&mut chunk, glam::uvec3(11, 11, 9));
+
+        let idx = Chunk::block_pos_to_idx(glam::uvec3(10, 10, 10));
+        let face = BlockFace::Top;
+        let quad = chunk.blocks[idx].gen_face(&reg, glam::Vec3::splat(10.0), face, Biome::Plains);
+        let ao = chunk.face_ao(&reg, idx, face, &quad, &NO_NEIGHBOURS);
+        for (vertex, ao) in quad.iter().zip(ao) {
+            let corner = (vertex.position - 10.5).signum();
+            let expected = match (corner.x, corner.z) {
+                // both sides
+                (1.0, 1.0) => 0,
+                // a side and the corner
+                (1.0, -1.0) => 1,
+                // one side
+                (-1.0, 1.0) => 2,
+                // nothing
+                (-1.0, -1.0) => 3,
+                _ => unreachable!("corner {corner}"),
+            };
+            assert_eq!(ao, expected, "corner {corner}");
+        }
+
+        // the quad's diagonal joins the brighter pair of corners
+        let quad = chunk.gen_quad(&reg, idx, face, &NO_NEIGHBOURS);
+        assert!(quad[0].ao + quad[2].ao >= quad[1].ao + quad[3].ao);
+        assert_eq!(quad.iter().filter(|v| v.ao == 0.0).count(), 1);
+    }
+
+    #[test]
+    fn greedy_merged_quads_keep_their_light() {
+        let reg = registry();
+        let mut chunk = flat_chunk(&reg, 16);
+        let mut light = Lighting::default();
+        light.set_sky(12);
+        light.set_block([3, 0, 5]);
+        for idx in (0..ChunkBlocks::LEN).filter(|&idx| Chunk::block_idx_to_pos(idx).y > 16) {
+            *chunk.blocks.light_mut(idx) = light;
+        }
+        chunk.greedy = true;
+        chunk.gen_mesh(&reg, &NO_NEIGHBOURS);
+
+        // grass faces turned the same way merge into quads of several blocks
+        let top: Vec<_> = (chunk.vertices.iter())
+            .filter(|quad| quad.iter().all(|v| v.position.y == 17.0))
+            .collect();
+        let span = |quad: &[Vertex; 4]| (quad[2].position - quad[0].position).abs().element_sum();
+        assert!(top.iter().any(|quad| span(quad) > 2.0));
+        for vertex in top.into_iter().flatten() {
+            assert_eq!(vertex.light, light.to_unorm());
+            assert_eq!(vertex.ao, 1.0);
+        }
+    }
+
+    #[test]
+    fn greedy_meshing_a_solid_chunk_leaves_a_quad_per_side() {
+        let reg = registry();
+        let mut chunk = flat_chunk(&reg, 100);
+        // generated blocks are turned at random, which rotates their textures apart
+        chunk
+            .blocks
+            .update_all(|block| block.dir = BlockFace::Front);
+        chunk.gen_mesh(&reg, &NO_NEIGHBOURS);
+        assert_eq!(chunk.vertices.len(), 6 * 32 * 32);
+
+        chunk.greedy = true;
+        chunk.gen_mesh(&reg, &NO_NEIGHBOURS);
+        assert_eq!(chunk.vertices.len(), 6);
+        assert!(chunk.translucent_vertices.is_empty());
+    }
+
+    #[test]
+    fn greedy_meshing_keeps_apart_faces_textured_by_state() {
+        let reg = registry();
+        let mut chunk = flat_chunk(&reg, -1);
+        let furnace = reg.block_map["furnace"];
+        // a row of four furnaces, the last two lit
+        for x in 0..4 {
+            let idx = Chunk::block_pos_to_idx(glam::uvec3(x, 8, 8));
+            chunk.place_block(&reg, idx, furnace, BlockFace::Front, &NO_NEIGHBOURS);
+            if 2 <= x {
+                chunk
+                    .blocks
+                    .modify(idx, |block| block.set_state(&reg, "lit", 1));
+            }
+        }
+        chunk.greedy = true;
+        chunk.gen_mesh(&reg, &NO_NEIGHBOURS);
+
+        // a quad for each end and each long side, the front split between the two states
+        assert_eq!(chunk.vertices.len(), 7);
+        let lit = glam::vec2(640.0, 320.0) / 1024.0;
+        let lit_fronts: Vec<_> = (chunk.vertices.iter())
+            .filter(|quad| quad[0].tile[..2] == lit.to_array())
+            .collect();
+        assert_eq!(lit_fronts.len(), 1);
+        let span = |quad: &[Vertex; 4]| (quad[2].position - quad[0].position).abs().element_sum();
+        assert_eq!(span(lit_fronts[0]), 3.0);
+    }
+
+    #[test]
+    fn editing_the_seam_updates_the_faces_on_both_sides() {
+        fn beside(face: BlockFace, chunk: &Chunk) -> FacedData<Option<&Chunk>> {
+            let mut neighbours = NO_NEIGHBOURS;
+            neighbours[face] = Some(chunk);
+            neighbours
+        }
+        // places a block on the edge `face` of `chunk` and refreshes the face across it, like
+        // `World` does
+        fn place(
+            reg: &BlockRegistry,
+            chunk: &mut Chunk,
+            face: BlockFace,
+            neighbour: &mut Chunk,
+            pos: glam::UVec3,
+            id: u32,
+        ) {
+            let idx = Chunk::block_pos_to_idx(pos);
+            chunk.place_block(reg, idx, id, BlockFace::Front, &beside(face, neighbour));
+            let j = Chunk::edge_neighbour_idx(idx, face);
+            neighbour.refresh_face(reg, j, face.flip(), &beside(face.flip(), chunk));
+        }
+        // faces of the blocks along the seam that face across it, on the left and the right
+        fn seam_faces(left: &Chunk, right: &Chunk) -> (usize, usize) {
+            let count = |chunk: &Chunk, x, face| {
+                (0..1 << 15)
+                    .filter(|&i| Chunk::block_idx_to_pos(i).x == x)
+                    .filter(|&i| chunk.blocks[i].face(face).is_some())
+                    .count()
+            };
+            (
+                count(left, 31, BlockFace::Right),
+                count(right, 0, BlockFace::Left),
+            )
+        }
+
+        let reg = registry();
+        let mut left = flat_chunk(&reg, 16);
+        let mut right = flat_chunk(&reg, 16);
+        left.gen_mesh(&reg, &beside(BlockFace::Right, &right));
+        right.gen_mesh(&reg, &beside(BlockFace::Left, &left));
+        assert_eq!(seam_faces(&left, &right), (0, 0));
+
+        let (air, cobblestone) = (reg.air(), reg.block_map["cobblestone"]);
+        let (l, r) = (glam::uvec3(31, 10, 5), glam::uvec3(0, 12, 5));
+        place(&reg, &mut left, BlockFace::Right, &mut right, l, air);
+        assert_eq!(seam_faces(&left, &right), (0, 1));
+        place(&reg, &mut right, BlockFace::Left, &mut left, r, air);
+        assert_eq!(seam_faces(&left, &right), (1, 1));
+        place(
+            &reg,
+            &mut left,
+            BlockFace::Right,
+            &mut right,
+            l,
+            cobblestone,
         );
+        assert_eq!(seam_faces(&left, &right), (1, 0));
+        place(&reg, &mut right, BlockFace::Left, &mut left, r, cobblestone);
+        assert_eq!(seam_faces(&left, &right), (0, 0));
+    }
+
+    #[test]
+    fn chunks_with_too_many_faces_are_meshed_without_a_face_table() {
+        struct Walls(u32);
+        impl TerrainGenerator for Walls {
+            fn sample(&self, _blocks: &TerrainBlocks, _world_pos: glam::IVec3) -> u32 {
+                self.0
+            }
+        }
+
+        // walls aren't full, so every one of them shows all its faces and none of them merge
+        let reg = registry();
+        let walls = Walls(reg.block_map["cobblestone_wall"]);
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut chunk = Chunk::generate(glam::IVec3::ZERO, &walls, &reg.terrain_blocks, &mut rng);
+        chunk.update_flags(&reg);
+        chunk.resolve_states(&reg);
+        chunk.gen_mesh(&reg, &NO_NEIGHBOURS);
+        assert!(chunk.greedy_mesh);
+        assert_eq!(chunk.vertices.len(), 6 * ChunkBlocks::LEN);
+        assert!(ChunkBlock::MAX_FACES < chunk.vertices.len());
+
+        // edits remesh the chunk rather than patching a face table it doesn't have
+        let idx = Chunk::block_pos_to_idx(glam::uvec3(5, 5, 5));
+        chunk.place_block(&reg, idx, reg.air(), BlockFace::Front, &NO_NEIGHBOURS);
+        assert!(chunk.greedy_mesh);
+        assert!(chunk
+            .blocks
+            .iter()
+            .all(|block| block.faces == [u16::MAX; 6]));
+    }
+
+    #[test]
+    fn edits_before_the_first_mesh_are_meshed() {
+        let reg = registry();
+        let mut chunk = flat_chunk(&reg, 16);
+        assert!(chunk.needs_mesh());
+
+        // a block floating above the ground, placed before the chunk was ever meshed
+        let idx = Chunk::block_pos_to_idx(glam::uvec3(8, 20, 8));
+        let cobblestone = reg.block_map["cobblestone"];
+        chunk.place_block(&reg, idx, cobblestone, BlockFace::Front, &NO_NEIGHBOURS);
+        assert!(chunk.needs_mesh());
+        assert!(chunk.vertices.is_empty());
+
+        chunk.gen_mesh(&reg, &NO_NEIGHBOURS);
+        assert!(!chunk.needs_mesh());
+        for face in BlockFace::iter() {
+            let face_i = chunk.blocks[idx]
+                .face(face)
+                .expect("the placed block has every face");
+            assert_eq!(Chunk::face_owner(&chunk.vertices[face_i]), (idx, face));
+        }
+
+        // edits to a meshed chunk patch its faces, until it's queued to be meshed again
+        let below = Chunk::block_pos_to_idx(glam::uvec3(8, 19, 8));
+        chunk.place_block(&reg, below, cobblestone, BlockFace::Front, &NO_NEIGHBOURS);
+        assert!(!chunk.needs_mesh());
+        assert!(chunk.blocks[idx].face(BlockFace::Bottom).is_none());
+        assert!(chunk.blocks[below].face(BlockFace::Top).is_none());
+        assert!(chunk.blocks[below].face(BlockFace::Bottom).is_some());
+
+        chunk.invalidate_mesh();
+        assert!(chunk.needs_mesh());
+        chunk.place_block(&reg, below, reg.air(), BlockFace::Front, &NO_NEIGHBOURS);
+        assert!(chunk.blocks[idx].face(BlockFace::Bottom).is_none());
+        chunk.gen_mesh(&reg, &NO_NEIGHBOURS);
+        assert!(!chunk.needs_mesh());
+        assert!(chunk.blocks[idx].face(BlockFace::Bottom).is_some());
+        assert!(chunk.blocks[below].face(BlockFace::Top).is_none());
+    }
+
+    #[test]
+    fn saved_chunk_loads_back_by_name_after_the_blocks_are_reordered() {
+        let reg = registry();
+        let dir = std::env::temp_dir().join(format!("mclone-{}-reorder", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (chunk_path, names_path) = (dir.join("chunk"), dir.join("blocks.txt"));
+
+        let mut chunk = flat_chunk(&reg, 16);
+        let placed = [
+            (glam::uvec3(0, 17, 0), "furnace", BlockFace::Left),
+            (glam::uvec3(31, 20, 31), "glass", BlockFace::Front),
+            (glam::uvec3(6, 17, 7), "cobblestone_wall", BlockFace::Front),
+            (glam::uvec3(9, 17, 9), "observer", BlockFace::Back),
+        ];
+        for (pos, name, dir) in placed {
+            let idx = Chunk::block_pos_to_idx(pos);
+            chunk.place_block(&reg, idx, reg.block_map[name], dir, &NO_NEIGHBOURS);
+        }
+        chunk.save(&chunk_path).unwrap();
+        reg.save_name_table(&names_path).unwrap();
+
+        // every id moves, and the observer is gone
+        let mut blocks = BlockRegistry::default_blocks();
+        blocks.retain(|block| block.name != "observer");
+        blocks.reverse();
+        let reordered = BlockRegistry::new(blocks, Vec::new(), Vec::new());
+        let id_map = reordered.load_id_map(&names_path).unwrap();
+        let mut loaded = Chunk::load(&chunk_path).unwrap();
+        loaded.remap_ids(&reordered, &id_map);
+        loaded.load_states(&reordered);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let observer = Chunk::block_pos_to_idx(glam::uvec3(9, 17, 9));
+        for (idx, (block, loaded)) in chunk.blocks.iter().zip(loaded.blocks.iter()).enumerate() {
+            let name = match idx == observer {
+                true => "air",
+                false => reg.blocks[block.id as usize].name.as_str(),
+            };
+            assert_eq!(reordered.blocks[loaded.id as usize].name, name, "{idx}");
+            if idx != observer {
+                assert_eq!(
+                    (loaded.dir, &loaded.data),
+                    (block.dir, &block.data),
+                    "{idx}"
+                );
+            }
+        }
     }
 }
@@ -2,6 +2,13 @@ struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
     @location(2) color: vec4<f32>,
+    @location(3) tile: vec4<f32>,
+    @location(4) ao: f32,
+    // block light in rgb, sky light in a
+    @location(6) light: vec4<f32>,
+    @location(7) layer: u32,
+    // frame count and frames per second
+    @location(8) animation: vec2<f32>,
 };
 
 struct InstanceInput {
@@ -9,10 +16,16 @@ struct InstanceInput {
 };
 
 struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
+    // invariant so the depth prepass, computing it the same way, leaves the exact same depths
+    @builtin(position) @invariant clip_position: vec4<f32>,
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
     @location(2) color: vec4<f32>,
+    @location(3) tile: vec4<f32>,
+    @location(4) ao: f32,
+    @location(5) light: vec4<f32>,
+    @location(6) @interpolate(flat) layer: u32,
+    @location(7) @interpolate(flat) animation: vec2<f32>,
 };
 
 struct CameraUniform {
@@ -25,10 +38,38 @@ var<uniform> camera: CameraUniform;
 struct LightUniform {
     dir: vec3<f32>,
     color: vec3<f32>,
+    sky_color: vec3<f32>,
+    ambient_intensity: f32,
+    ground_color: vec3<f32>,
+    // 0 at night and 1 during the day
+    daylight: f32,
 };
 @group(2) @binding(0)
 var<uniform> light: LightUniform;
 
+struct ShadowUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(2) @binding(1)
+var<uniform> shadow: ShadowUniform;
+@group(2) @binding(2)
+var t_shadow: texture_depth_2d;
+@group(2) @binding(3)
+var s_shadow: sampler_comparison;
+
+struct WorldUniform {
+    // 0: off, 1: sky light, 2: block light
+    light_debug: u32,
+    // horizontal distances over which chunks fade into the fog color
+    fade_start: f32,
+    fade_end: f32,
+    // seconds animated textures have been playing for
+    time: f32,
+    fog_color: vec4<f32>,
+};
+@group(3) @binding(0)
+var<uniform> world: WorldUniform;
+
 @vertex
 fn vs_main(
     model: VertexInput,
@@ -41,13 +82,57 @@ fn vs_main(
     out.position = world_position;
     out.tex_coords = model.tex_coords;
     out.color = model.color;
+    out.tile = model.tile;
+    out.ao = model.ao;
+    out.light = model.light;
+    out.layer = model.layer;
+    out.animation = model.animation;
 
     return out;
 }
 
+// Depth prepass, filling the scene depth the color pass then only shades the nearest faces of
+@vertex
+fn vs_depth(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> @builtin(position) @invariant vec4<f32> {
+    let world_position = model.position + instance.offset;
+    return camera.view_proj * vec4(world_position, 1.0);
+}
+
+// Fraction of the sky's light left in the darkest caves, so they aren't pitch black
+const MIN_SKY_LIGHT: f32 = 0.05;
+
+// Hemispheric ambient light, from the sky above and the ground below
+fn ambient_light(normal: vec3<f32>) -> vec3<f32> {
+    return light.ambient_intensity * mix(light.ground_color, light.sky_color, 0.5 + 0.5 * normal.y);
+}
+
+// Fraction of the sun's light reaching `position`, filtering 3x3 texels of the shadow map
+fn sun_visibility(position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    // moving off the surface keeps faces from shadowing themselves
+    let light_position = shadow.view_proj * vec4(position + 0.05 * normal, 1.0);
+    let coords = light_position.xyz / light_position.w;
+    let uv = vec2(0.5 + 0.5 * coords.x, 0.5 - 0.5 * coords.y);
+    if any(uv < vec2(0.0)) || any(uv > vec2(1.0)) || coords.z < 0.0 {
+        return 1.0;
+    }
+
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_shadow));
+    var visibility = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2(f32(x), f32(y)) * texel;
+            visibility += textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, coords.z);
+        }
+    }
+    return visibility / 9.0;
+}
+
 // Fragment shader
 @group(0) @binding(0)
-var t_diffuse: texture_2d<f32>;
+var t_diffuse: texture_2d_array<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
@@ -58,7 +143,17 @@ var s_diffuse: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let object_color = color_blend(textureSample(t_diffuse, s_diffuse, in.tex_coords), in.color);
+    // frames continue down the texture, past its bottom edge into the next layers
+    let frame = floor(world.time * in.animation.y) % in.animation.x;
+    let frame_y = in.tile.y + frame * in.tile.w;
+    let tile = vec4(in.tile.x, fract(frame_y), in.tile.zw);
+    let layer = in.layer + u32(frame_y);
+
+    // wrap around the tile so merged quads repeat it, with gradients that don't jump at the seams
+    let tex_coords = tile.xy + fract(in.tex_coords) * tile.zw;
+    let ddx = dpdx(in.tex_coords) * tile.zw;
+    let ddy = dpdy(in.tex_coords) * tile.zw;
+    let object_color = color_blend(textureSampleGrad(t_diffuse, s_diffuse, tex_coords, layer, ddx, ddy), in.color);
 
     // let tangent_normal = normalize(2 * textureSample(t_normal, s_normal, in.tex_coords).xyz - 1);
     let tangent_normal = vec3(0.0, 0.0, 1.0);
@@ -76,12 +171,27 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let view_dir = TBN * normalize(camera.view_position - in.position);
     let half_dir = normalize(light_dir + view_dir);
 
-    let ambient_strength = 0.1;
+    let ambient_color = ambient_light(normal);
     let diffuse_stength = max(dot(tangent_normal, light_dir), 0.0);
     let specular_strength = pow(max(dot(tangent_normal, half_dir), 0.0), 32.0);
-
-    let result = min(ambient_strength + diffuse_stength + specular_strength, 1.0) * light.color * object_color.rgb;
-    return vec4(result, object_color.a);
+    let block_light = in.light.rgb;
+    let sky_light = in.light.a * light.daylight;
+
+    if world.light_debug == 1u {
+        return vec4(vec3(sky_light), 1.0);
+    } else if world.light_debug == 2u {
+        return vec4(block_light, 1.0);
+    }
+
+    // the sky's light only reaches as far as the sky light, leaving caves to the block light
+    let direct = sun_visibility(in.position, normal) * min(diffuse_stength + specular_strength, 1.0) * light.color;
+    let sky = max(sky_light, MIN_SKY_LIGHT) * (ambient_color + direct);
+    let ao = mix(0.5, 1.0, in.ao);
+    let result = ao * min(sky + block_light, vec3(1.0)) * object_color.rgb;
+
+    let distance = length(in.position.xz - camera.view_position.xz);
+    let fade = smoothstep(world.fade_start, world.fade_end, distance);
+    return vec4(mix(result, world.fog_color.rgb, fade), object_color.a);
 }
 
 fn color_blend(base: vec4<f32>, blend: vec4<f32>) -> vec4<f32> {
@@ -0,0 +1,290 @@
+use std::{f32::consts::TAU, time::Instant};
+
+use wgpu::util::DeviceExt;
+
+use super::BlockTexture;
+use crate::texture::Texture;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct HighlightUniform {
+    pub position: glam::Vec3,
+    pub expand: f32,
+    pub color: glam::Vec4,
+    pub expand_per_distance: f32,
+    /// Layer and rect of the registry's texture holding the cracks of the current mining stage.
+    pub crack_layer: u32,
+    pub _pad1: [u32; 2],
+    pub crack_tile: glam::Vec4,
+}
+
+/// How the outline of the targeted block looks.
+#[derive(Debug, Copy, Clone)]
+pub struct HighlightStyle {
+    pub color: glam::Vec4,
+    /// Pulses per second while not mining, 0 for a steady outline.
+    pub idle_pulse_rate: f32,
+    /// Pulses per second right before the block breaks.
+    pub mining_pulse_rate: f32,
+    /// How much the pulse dims the outline, from 0 to 1.
+    pub pulse_depth: f32,
+    /// Smallest distance in blocks the outline is pushed out of the block's faces, so it doesn't
+    /// z-fight with them up close.
+    pub expand: f32,
+    /// Extra push per block of distance from the camera. Depth precision and line rasterization
+    /// both get coarser further away, so a fixed push either floats up close or flickers at reach.
+    pub expand_per_distance: f32,
+}
+
+impl Default for HighlightStyle {
+    fn default() -> Self {
+        Self {
+            color: glam::vec4(0.0, 0.0, 0.0, 0.6),
+            idle_pulse_rate: 0.0,
+            mining_pulse_rate: 8.0,
+            pulse_depth: 0.6,
+            expand: 0.001,
+            expand_per_distance: 0.002,
+        }
+    }
+}
+
+/// Outline drawn around the block the camera is targeting, with cracks over its faces while
+/// it's being mined.
+pub struct BlockHighlight {
+    pub style: HighlightStyle,
+
+    target: Option<glam::IVec3>,
+    progress: f32,
+    cracked: bool,
+    phase: f32,
+    last_update: Instant,
+
+    uniform: HighlightUniform,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+    crack_render_pipeline: wgpu::RenderPipeline,
+}
+
+impl BlockHighlight {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        registry_bind_group_layout: &wgpu::BindGroupLayout,
+        sample_count: u32,
+    ) -> Self {
+        let uniform = HighlightUniform {
+            position: glam::Vec3::ZERO,
+            expand: 0.0,
+            color: glam::Vec4::ZERO,
+            expand_per_distance: 0.0,
+            crack_layer: 0,
+            _pad1: [0; 2],
+            crack_tile: glam::Vec4::ZERO,
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Highlight Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Highlight Uniform Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Highlight Uniform Bind Group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let module = device.create_shader_module(wgpu::include_wgsl!("highlight.wgsl"));
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Highlight Render Pipeline"),
+            layout: Some(
+                &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Highlight Render Pipeline Layout"),
+                    bind_group_layouts: &[camera_bind_group_layout, &uniform_bind_group_layout],
+                    push_constant_ranges: &[],
+                }),
+            ),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::GreaterEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        });
+        // the cracks are blended over the block's faces, like the outline without writing depth
+        let crack_render_pipeline =
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Crack Render Pipeline"),
+                layout: Some(
+                    &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: Some("Crack Render Pipeline Layout"),
+                        bind_group_layouts: &[
+                            camera_bind_group_layout,
+                            &uniform_bind_group_layout,
+                            registry_bind_group_layout,
+                        ],
+                        push_constant_ranges: &[],
+                    }),
+                ),
+                vertex: wgpu::VertexState {
+                    module: &module,
+                    entry_point: "vs_crack",
+                    buffers: &[],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &module,
+                    entry_point: "fs_crack",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: Texture::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::GreaterEqual,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    ..Default::default()
+                },
+                multiview: None,
+            });
+
+        Self {
+            style: HighlightStyle::default(),
+
+            target: None,
+            progress: 0.0,
+            cracked: false,
+            phase: 0.0,
+            last_update: Instant::now(),
+
+            uniform,
+            uniform_buffer,
+            uniform_bind_group,
+            render_pipeline,
+            crack_render_pipeline,
+        }
+    }
+
+    /// Updates the outlined block and how far along mining it is, from 0 to 1, with the cracks
+    /// to draw over it. The pulse restarts when the target changes or mining is released.
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        target: Option<glam::IVec3>,
+        progress: f32,
+        crack: Option<BlockTexture>,
+    ) {
+        let now = Instant::now();
+        let delta_time = now.duration_since(self.last_update).as_secs_f32();
+        self.last_update = now;
+
+        let progress = progress.clamp(0.0, 1.0);
+        if target != self.target || progress < self.progress {
+            self.phase = 0.0;
+        }
+        self.target = target;
+        self.progress = progress;
+        self.cracked = crack.is_some();
+
+        let Some(target) = target else {
+            return;
+        };
+
+        let rate = self.style.idle_pulse_rate
+            + (self.style.mining_pulse_rate - self.style.idle_pulse_rate) * progress;
+        self.phase = (self.phase + delta_time * rate).fract();
+        // starts at full brightness and dims by `pulse_depth` half way through each pulse
+        let pulse = 0.5 - 0.5 * (TAU * self.phase).cos();
+        let alpha = 1.0 - self.style.pulse_depth.clamp(0.0, 1.0) * pulse;
+
+        self.uniform.position = target.as_vec3();
+        self.uniform.color = self.style.color * glam::vec4(1.0, 1.0, 1.0, alpha);
+        self.uniform.expand = self.style.expand.max(0.0);
+        self.uniform.expand_per_distance = self.style.expand_per_distance.max(0.0);
+        if let Some(crack) = crack {
+            self.uniform.crack_layer = crack.layer;
+            self.uniform.crack_tile =
+                glam::vec4(crack.pos.x, crack.pos.y, crack.size.x, crack.size.y);
+        }
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform]),
+        );
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        registry_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if self.target.is_none() {
+            return;
+        }
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        render_pass.draw(0..24, 0..1);
+
+        if self.cracked {
+            render_pass.set_pipeline(&self.crack_render_pipeline);
+            render_pass.set_bind_group(2, registry_bind_group, &[]);
+            // two triangles on each of the 6 faces
+            render_pass.draw(0..36, 0..1);
+        }
+    }
+}
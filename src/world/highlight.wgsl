@@ -0,0 +1,114 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+};
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view_position: vec3<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct HighlightUniform {
+    position: vec3<f32>,
+    expand: f32,
+    color: vec4<f32>,
+    expand_per_distance: f32,
+    crack_layer: u32,
+    // position and size of the cracks in the texture
+    crack_tile: vec4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> highlight: HighlightUniform;
+
+// Pairs of unit cube corners forming its 12 edges
+const EDGES = array<vec3<f32>, 24>(
+    vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0),
+    vec3(0.0, 1.0, 0.0), vec3(1.0, 1.0, 0.0),
+    vec3(0.0, 0.0, 1.0), vec3(1.0, 0.0, 1.0),
+    vec3(0.0, 1.0, 1.0), vec3(1.0, 1.0, 1.0),
+    vec3(0.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0),
+    vec3(1.0, 0.0, 0.0), vec3(1.0, 1.0, 0.0),
+    vec3(0.0, 0.0, 1.0), vec3(0.0, 1.0, 1.0),
+    vec3(1.0, 0.0, 1.0), vec3(1.0, 1.0, 1.0),
+    vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0),
+    vec3(1.0, 0.0, 0.0), vec3(1.0, 0.0, 1.0),
+    vec3(0.0, 1.0, 0.0), vec3(0.0, 1.0, 1.0),
+    vec3(1.0, 1.0, 0.0), vec3(1.0, 1.0, 1.0),
+);
+
+// Pushes a corner of the unit cube at the highlighted block out of the block's faces, further
+// the more distant it is so the outline stays in front of them across the whole reach
+fn expand_corner(corner: vec3<f32>) -> vec3<f32> {
+    let position = highlight.position + corner;
+    let expand = highlight.expand + highlight.expand_per_distance * distance(position, camera.view_position);
+    return position + expand * (2.0 * corner - 1.0);
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var edges = EDGES;
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4(expand_corner(edges[vertex_index]), 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return highlight.color;
+}
+
+struct CrackOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+// Top left corner of each face of the unit cube, and the directions its texture goes right and
+// down in
+const FACE_ORIGINS = array<vec3<f32>, 6>(
+    vec3(1.0, 1.0, 1.0), vec3(0.0, 1.0, 0.0),
+    vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0),
+    vec3(1.0, 1.0, 0.0), vec3(0.0, 1.0, 1.0),
+);
+const FACE_RIGHT = array<vec3<f32>, 6>(
+    vec3(0.0, 0.0, -1.0), vec3(0.0, 0.0, 1.0),
+    vec3(1.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0),
+    vec3(-1.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0),
+);
+const FACE_DOWN = array<vec3<f32>, 6>(
+    vec3(0.0, -1.0, 0.0), vec3(0.0, -1.0, 0.0),
+    vec3(0.0, 0.0, 1.0), vec3(0.0, 0.0, -1.0),
+    vec3(0.0, -1.0, 0.0), vec3(0.0, -1.0, 0.0),
+);
+// Corners of the two triangles of a face
+const QUAD = array<vec2<f32>, 6>(
+    vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0),
+    vec2(0.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0),
+);
+
+@vertex
+fn vs_crack(@builtin(vertex_index) vertex_index: u32) -> CrackOutput {
+    var origins = FACE_ORIGINS;
+    var right = FACE_RIGHT;
+    var down = FACE_DOWN;
+    var quad = QUAD;
+    let face = vertex_index / 6u;
+    let uv = quad[vertex_index % 6u];
+    let corner = origins[face] + uv.x * right[face] + uv.y * down[face];
+
+    var out: CrackOutput;
+    out.clip_position = camera.view_proj * vec4(expand_corner(corner), 1.0);
+    out.tex_coords = highlight.crack_tile.xy + uv * highlight.crack_tile.zw;
+    return out;
+}
+
+@group(2) @binding(0)
+var t_blocks: texture_2d_array<f32>;
+@group(2) @binding(1)
+var s_blocks: sampler;
+
+@fragment
+fn fs_crack(in: CrackOutput) -> @location(0) vec4<f32> {
+    return textureSample(t_blocks, s_blocks, in.tex_coords, highlight.crack_layer);
+}
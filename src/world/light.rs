@@ -0,0 +1,207 @@
+use std::collections::VecDeque;
+
+use ahash::{HashMap, HashSet};
+
+use super::{chunk::Lighting, BlockFace, Chunk, World};
+
+/// The red, green and blue block light channels followed by the sky light.
+const CHANNELS: usize = 4;
+
+impl World {
+    /// Light level of one channel at `pos` relative to the origin, `None` outside the loaded
+    /// chunks.
+    fn light_at(&self, pos: glam::IVec3, channel: usize) -> Option<u8> {
+        let (chunk_i, block_i) = self.locate_block(pos)?;
+        Some(
+            self.loaded_chunks[chunk_i]
+                .blocks
+                .light(block_i)
+                .channel(channel),
+        )
+    }
+
+    fn set_light_at(&mut self, pos: glam::IVec3, channel: usize, level: u8) {
+        let Some((chunk_i, block_i)) = self.locate_block(pos) else {
+            return;
+        };
+        (self.loaded_chunks[chunk_i].blocks.light_mut(block_i)).set_channel(channel, level);
+    }
+
+    /// Whether light can pass through the block at `pos`.
+    fn lets_light_through(&self, pos: glam::IVec3) -> bool {
+        self.locate_block(pos).is_some_and(|(chunk_i, block_i)| {
+            !self.loaded_chunks[chunk_i].blocks[block_i]
+                .data(&self.registry)
+                .is_full()
+        })
+    }
+
+    /// Light the block at `pos` gives off by itself. Sky light comes from the top of the world,
+    /// into the blocks letting light through with no loaded chunk above them.
+    fn source_light(&self, pos: glam::IVec3, channel: usize) -> u8 {
+        let Some((chunk_i, block_i)) = self.locate_block(pos) else {
+            return 0;
+        };
+        let data = self.loaded_chunks[chunk_i].blocks[block_i].data(&self.registry);
+        match channel {
+            Lighting::SKY => {
+                match data.is_full() || self.locate_block(pos + glam::IVec3::Y).is_some() {
+                    true => 0,
+                    false => Lighting::MAX,
+                }
+            }
+            _ => data.emission[channel],
+        }
+    }
+
+    /// Level `level` turns into after moving across `face`. Full sky light pours straight down
+    /// without dimming, everything else dims by one level per block.
+    fn spread_level(channel: usize, level: u8, face: BlockFace) -> u8 {
+        match (channel, level, face) {
+            (Lighting::SKY, Lighting::MAX, BlockFace::Bottom) => Lighting::MAX,
+            _ => level.saturating_sub(1),
+        }
+    }
+
+    /// Spreads the light of `queue` outwards through blocks that let light pass. Every cell that
+    /// gets brighter is added to `changed`.
+    fn spread_light(
+        &mut self,
+        channel: usize,
+        mut queue: VecDeque<glam::IVec3>,
+        changed: &mut HashSet<glam::IVec3>,
+    ) {
+        while let Some(pos) = queue.pop_front() {
+            let level = self.light_at(pos, channel).unwrap_or(0);
+            if level <= 1 {
+                continue;
+            }
+            for face in BlockFace::iter() {
+                let next = pos + face.voffset();
+                if !self.lets_light_through(next) {
+                    continue;
+                }
+                let next_level = Self::spread_level(channel, level, face);
+                if self.light_at(next, channel).is_some_and(|l| l < next_level) {
+                    self.set_light_at(next, channel, next_level);
+                    changed.insert(next);
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    /// Updates the light around `pos` after the block there changed: the light that came from
+    /// or through it is removed, then the surrounding light fills the gap back in. Returns the
+    /// cells whose light changed.
+    pub(super) fn relight_block(&mut self, pos: glam::IVec3) -> HashSet<glam::IVec3> {
+        let mut changed = HashSet::default();
+        if self.locate_block(pos).is_none() {
+            return changed;
+        }
+        for channel in 0..CHANNELS {
+            let mut refill = VecDeque::new();
+            let mut removal = VecDeque::new();
+            removal.push_back((pos, self.light_at(pos, channel).unwrap_or(0)));
+            self.set_light_at(pos, channel, 0);
+            changed.insert(pos);
+
+            while let Some((pos, level)) = removal.pop_front() {
+                for face in BlockFace::iter() {
+                    let next = pos + face.voffset();
+                    let Some(next_level) = self.light_at(next, channel) else {
+                        continue;
+                    };
+                    if next_level == 0 {
+                        continue;
+                    } else if next_level > Self::spread_level(channel, level, face) {
+                        // lit by something else, which fills the gap back in
+                        refill.push_back(next);
+                        continue;
+                    }
+                    // sources keep their own light and refill around them
+                    let own = self.source_light(next, channel);
+                    if own < next_level {
+                        self.set_light_at(next, channel, own);
+                        changed.insert(next);
+                        removal.push_back((next, next_level));
+                    }
+                    if own != 0 {
+                        refill.push_back(next);
+                    }
+                }
+            }
+
+            let own = self.source_light(pos, channel);
+            if own != 0 {
+                self.set_light_at(pos, channel, own);
+            }
+            refill.push_back(pos);
+            refill.extend(BlockFace::iter().map(|face| pos + face.voffset()));
+            self.spread_light(channel, refill, &mut changed);
+        }
+        changed
+    }
+
+    /// Recomputes the light of the chunk at `chunk_pos` from its sources and the light coming in
+    /// from its loaded neighbours, spreading it back out into them. Light the chunk used to send
+    /// into its neighbours isn't removed, so this is meant for new chunks.
+    pub fn recompute_light(&mut self, chunk_pos: glam::IVec3) {
+        let Some(chunk_i) = self.chunk_index(chunk_pos) else {
+            return;
+        };
+        let base = 32 * (chunk_pos - self.origin);
+        let block_positions =
+            (0..32 * 32 * 32).map(|i| (i, base + Chunk::block_idx_to_pos(i).as_ivec3()));
+
+        let mut sources = [(); CHANNELS].map(|_| Vec::new());
+        for (i, pos) in block_positions.clone() {
+            let mut light = Lighting::default();
+            for (channel, sources) in sources.iter_mut().enumerate() {
+                light.set_channel(channel, self.source_light(pos, channel));
+                if light.channel(channel) != 0 {
+                    sources.push(pos);
+                }
+            }
+            *self.loaded_chunks[chunk_i].blocks.light_mut(i) = light;
+        }
+
+        let mut edges = Vec::new();
+        for (i, pos) in block_positions {
+            let across = BlockFace::iter().filter(|face| face.is_edge(i));
+            edges.extend(across.map(|face| pos + face.voffset()));
+        }
+
+        let mut changed = HashSet::default();
+        for (channel, sources) in sources.into_iter().enumerate() {
+            let seeds = sources.into_iter().chain(edges.iter().copied()).collect();
+            self.spread_light(channel, seeds, &mut changed);
+        }
+        // the chunk's own faces are meshed again anyway
+        changed.retain(|pos| pos.div_euclid(glam::IVec3::splat(32)) != chunk_pos - self.origin);
+        self.refresh_lit_faces(&changed);
+        if let Some(chunk_i) = self.chunk_index(chunk_pos) {
+            self.loaded_chunks[chunk_i].invalidate_mesh();
+        }
+    }
+
+    /// Regenerates the faces lit by the cells in `changed`: their own, and those of the full
+    /// blocks around them facing into them.
+    pub(super) fn refresh_lit_faces(&mut self, changed: &HashSet<glam::IVec3>) {
+        let mut by_chunk = HashMap::<usize, HashSet<usize>>::default();
+        for &pos in changed {
+            let around = BlockFace::iter().map(|face| pos + face.voffset());
+            for pos in std::iter::once(pos).chain(around) {
+                if let Some((chunk_i, block_i)) = self.locate_block(pos) {
+                    by_chunk.entry(chunk_i).or_default().insert(block_i);
+                }
+            }
+        }
+        for (chunk_i, idxs) in by_chunk {
+            self.with_neighbours(chunk_i, |chunk, reg, neighbours| {
+                chunk.refresh_faces(reg, idxs, neighbours)
+            });
+            self.invalidate_mesh_cache(chunk_i);
+        }
+    }
+}
@@ -1,13 +1,39 @@
-use ahash::HashMap;
+use std::{
+    cmp, fs, mem,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use ahash::{HashMap, HashSet};
 use anyhow::*;
+use rand::{rngs::SmallRng, SeedableRng};
 use wgpu::util::DeviceExt;
 use winit::event::*;
+use winit::keyboard::{KeyCode, PhysicalKey};
 
-use crate::{camera::Camera, texture::Texture};
+use crate::{
+    audio::{Audio, SoundId, SoundKind},
+    camera::{Camera, Frustum},
+    texture::{Mipmaps, Texture},
+};
 
+mod biome;
+mod blocks;
 mod chunk;
+mod highlight;
+mod light;
+mod particles;
+mod physics;
+mod state;
+mod structures;
+mod terrain;
 
-pub use chunk::{BlockFace, Chunk};
+pub use biome::{Biome, BiomeTint};
+pub use chunk::{BlockFace, Chunk, ChunkFlags, FacedData};
+pub use highlight::{BlockHighlight, HighlightStyle};
+pub use particles::{ParticleInstance, ParticleSettings, Particles};
+pub use state::{StateKind, StateProperty, StateSchema, StateTexture};
+pub use terrain::{FlatTerrain, NoiseTerrain, TerrainBlocks, TerrainGenerator};
 
 use chunk::ChunkInstance;
 
@@ -15,16 +41,35 @@ use chunk::ChunkInstance;
 pub struct BlockTexture {
     pub pos: glam::Vec2,
     pub size: glam::Vec2,
+    /// Layer of the registry's texture holding the rect, always 0 with
+    /// `BlockTextureBackend::Atlas`.
+    pub layer: u32,
+    /// Number of animation frames, stacked downwards from the first one in the atlas.
+    pub frames: u32,
+    /// Animation frames shown per second.
+    pub fps: f32,
 
     pub color: image::Rgba<u8>,
+    /// Biome color replacing `color` in the world, which is still used where there's no biome.
+    pub biome_tint: Option<BiomeTint>,
 }
 
 impl BlockTexture {
     pub fn new(pos: glam::Vec2, size: glam::Vec2) -> Self {
+        Self::animated(pos, size, 1, 0.0)
+    }
+
+    /// A texture cycling through `frames` frames of `size`, the first one at `pos` and each
+    /// following one right below the previous.
+    pub fn animated(pos: glam::Vec2, size: glam::Vec2, frames: u32, fps: f32) -> Self {
         Self {
             pos,
             size,
+            layer: 0,
+            frames,
+            fps,
             color: [0; 4].into(),
+            biome_tint: None,
         }
     }
 
@@ -33,6 +78,18 @@ impl BlockTexture {
         self
     }
 
+    /// Tints the texture with the biome's `tint`, and the default biome's outside the world.
+    pub fn with_biome_tint(mut self, tint: BiomeTint) -> Self {
+        self.color = Biome::default().tint(tint);
+        self.biome_tint = Some(tint);
+        self
+    }
+
+    /// Color of the texture in `biome`.
+    pub fn color_in(&self, biome: Biome) -> image::Rgba<u8> {
+        self.biome_tint.map_or(self.color, |tint| biome.tint(tint))
+    }
+
     pub fn get(&self, coords: glam::Vec2) -> glam::Vec2 {
         self.pos + coords * self.size
     }
@@ -42,6 +99,8 @@ impl BlockTexture {
 pub enum BlockMeshType {
     Transparent,
     SameSided(BlockTexture),
+    /// A see-through block like glass or ice, drawn after the opaque blocks.
+    Translucent(BlockTexture),
     Surrounded {
         top: BlockTexture,
         bottom: BlockTexture,
@@ -55,74 +114,176 @@ pub enum BlockMeshType {
         front: BlockTexture,
         back: BlockTexture,
     },
+    /// A post connecting to neighbouring fences and full blocks.
+    Fence(BlockTexture),
+}
+
+impl BlockMeshType {
+    fn textures(&self) -> Vec<&BlockTexture> {
+        match self {
+            Self::Transparent => vec![],
+            Self::SameSided(tex) | Self::Translucent(tex) | Self::Fence(tex) => vec![tex],
+            Self::Surrounded { top, bottom, sides } => vec![top, bottom, sides],
+            Self::Directional {
+                right,
+                left,
+                top,
+                bottom,
+                front,
+                back,
+            } => vec![right, left, top, bottom, front, back],
+        }
+    }
+
+    fn textures_mut(&mut self) -> Vec<&mut BlockTexture> {
+        match self {
+            Self::Transparent => vec![],
+            Self::SameSided(tex) | Self::Translucent(tex) | Self::Fence(tex) => vec![tex],
+            Self::Surrounded { top, bottom, sides } => vec![top, bottom, sides],
+            Self::Directional {
+                right,
+                left,
+                top,
+                bottom,
+                front,
+                back,
+            } => vec![right, left, top, bottom, front, back],
+        }
+    }
+}
+
+/// Group of sounds played when a block is broken, placed or stepped on.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SoundGroup {
+    #[default]
+    None,
+    Stone,
+    Gravel,
+    Grass,
 }
 
 #[derive(Debug)]
 pub struct BlockData {
     pub name: String,
     pub mesh_type: BlockMeshType,
+    /// Texture of the particles spawned when breaking the block, its main texture if `None`.
+    pub break_particle_texture: Option<BlockTexture>,
+    pub sound_group: SoundGroup,
+    /// Red, green and blue block light the block gives off, from 0 to 15.
+    pub emission: [u8; 3],
+    /// Seconds of mining it takes to break the block, 0 breaking it instantly.
+    pub hardness: f32,
+    /// Properties of the block's state, stored in the `data` of each block.
+    pub state: StateSchema,
+    /// Textures replacing the mesh type's for some states, the first match winning.
+    pub state_textures: Vec<StateTexture>,
 }
 
 impl BlockData {
     fn is_transparent(&self) -> bool {
         matches!(self.mesh_type, BlockMeshType::Transparent)
     }
+
+    fn is_translucent(&self) -> bool {
+        matches!(self.mesh_type, BlockMeshType::Translucent(_))
+    }
+
+    /// Whether the block has a front, facing the way it was placed.
+    fn is_directional(&self) -> bool {
+        matches!(self.mesh_type, BlockMeshType::Directional { .. })
+    }
+
+    /// Whether the block fills its whole cell, hiding the faces of full neighbours.
+    fn is_full(&self) -> bool {
+        !matches!(
+            self.mesh_type,
+            BlockMeshType::Transparent | BlockMeshType::Translucent(_) | BlockMeshType::Fence(_)
+        )
+    }
+
+    /// Atlas rect to texture break particles with, `None` for transparent blocks.
+    pub fn particle_texture(&self) -> Option<BlockTexture> {
+        self.break_particle_texture.or(self.main_texture())
+    }
+
+    /// Atlas rect the block is best recognized by, as on its icon. `None` for transparent
+    /// blocks.
+    pub fn main_texture(&self) -> Option<BlockTexture> {
+        match self.mesh_type {
+            BlockMeshType::Transparent => None,
+            BlockMeshType::SameSided(tex)
+            | BlockMeshType::Translucent(tex)
+            | BlockMeshType::Fence(tex) => Some(tex),
+            BlockMeshType::Surrounded { sides, .. } => Some(sides),
+            BlockMeshType::Directional { front, .. } => Some(front),
+        }
+    }
+}
+
+/// How the block textures are stored on the GPU.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum BlockTextureBackend {
+    /// The whole atlas as one image, faces sampling the rect of their `BlockTexture`.
+    #[default]
+    Atlas,
+    /// Every distinct rect of the atlas cut out into its own layer of a texture array, so
+    /// textures can't bleed into each other at any mip level. Animation frames go into
+    /// consecutive layers.
+    Array,
 }
 
 #[derive(Debug)]
 pub struct BlockRegistry {
-    pub texture: Texture,
-    pub bind_group: wgpu::BindGroup,
     pub blocks: Vec<BlockData>,
     pub block_map: HashMap<String, u32>,
+    /// Cracks drawn over a block as it's mined, from barely to almost broken.
+    pub crack_textures: Vec<BlockTexture>,
+    /// Image of each block's main texture for the GUI, indexed by id.
+    pub icons: Vec<Option<image::RgbaImage>>,
+    /// Ids of the blocks chunks are generated with.
+    pub terrain_blocks: TerrainBlocks,
 }
 
 impl BlockRegistry {
-    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Block Registry Bind Group Layout"),
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-            ],
-        })
+    /// Registry of `blocks`, whose ids are their indices. `icons` is indexed by id too.
+    pub fn new(
+        blocks: Vec<BlockData>,
+        crack_textures: Vec<BlockTexture>,
+        icons: Vec<Option<image::RgbaImage>>,
+    ) -> Self {
+        let block_map = (blocks.iter().enumerate())
+            .map(|(i, b)| (b.name.clone(), i as _))
+            .collect();
+        Self {
+            terrain_blocks: TerrainBlocks::new(&block_map),
+            block_map,
+            blocks,
+            crack_textures,
+            icons,
+        }
     }
-}
 
-pub struct World {
-    pub registry: BlockRegistry,
-    pub loaded_chunks: Vec<Chunk>,
-    pub instance_buffer: Option<wgpu::Buffer>,
-    pub render_pipeline: wgpu::RenderPipeline,
-}
+    /// The textures faces of the blocks are drawn with, including those of their states.
+    pub fn face_textures(&self) -> impl Iterator<Item = &BlockTexture> + '_ {
+        self.blocks.iter().flat_map(|block| {
+            let states = block.state_textures.iter().map(|s| &s.texture);
+            block.mesh_type.textures().into_iter().chain(states)
+        })
+    }
 
-impl World {
-    pub async fn new(
-        device: &wgpu::Device,
-        queue: &wgpu::Queue,
-        config: &wgpu::SurfaceConfiguration,
-        camera_bind_group_layout: &wgpu::BindGroupLayout,
-        light_bind_group_layout: &wgpu::BindGroupLayout,
-    ) -> Result<Self> {
+    /// The built-in blocks, with their textures in the block atlas.
+    pub fn default_blocks() -> Vec<BlockData> {
         let size = glam::vec2(16., 16.) / 1024.0;
-        let blocks = vec![
+        vec![
             BlockData {
                 name: "air".to_owned(),
                 mesh_type: BlockMeshType::Transparent,
+                break_particle_texture: None,
+                sound_group: SoundGroup::None,
+                emission: [0; 3],
+                hardness: 0.0,
+                state: StateSchema::default(),
+                state_textures: Vec::new(),
             },
             BlockData {
                 name: "cobblestone".to_owned(),
@@ -130,6 +291,12 @@ impl World {
                     glam::vec2(624.0, 272.0) / 1024.0,
                     size,
                 )),
+                break_particle_texture: None,
+                sound_group: SoundGroup::Stone,
+                emission: [0; 3],
+                hardness: 2.0,
+                state: StateSchema::default(),
+                state_textures: Vec::new(),
             },
             BlockData {
                 name: "dirt".to_owned(),
@@ -137,15 +304,30 @@ impl World {
                     glam::vec2(768.0, 304.0) / 1024.0,
                     size,
                 )),
+                break_particle_texture: None,
+                sound_group: SoundGroup::Gravel,
+                emission: [0; 3],
+                hardness: 0.75,
+                state: StateSchema::default(),
+                state_textures: Vec::new(),
             },
             BlockData {
                 name: "grass".to_owned(),
                 mesh_type: BlockMeshType::Surrounded {
                     top: BlockTexture::new(glam::vec2(880.0, 320.0) / 1024.0, size)
-                        .with_color(0x97c667_ffu32.to_be_bytes().into()),
+                        .with_biome_tint(BiomeTint::Grass),
                     bottom: BlockTexture::new(glam::vec2(768.0, 304.0) / 1024.0, size),
                     sides: BlockTexture::new(glam::vec2(832.0, 320.0) / 1024.0, size),
                 },
+                break_particle_texture: Some(BlockTexture::new(
+                    glam::vec2(768.0, 304.0) / 1024.0,
+                    size,
+                )),
+                sound_group: SoundGroup::Grass,
+                emission: [0; 3],
+                hardness: 0.9,
+                state: StateSchema::default(),
+                state_textures: Vec::new(),
             },
             BlockData {
                 name: "furnace".to_owned(),
@@ -157,6 +339,17 @@ impl World {
                     front: BlockTexture::new(glam::vec2(624.0, 320.0) / 1024.0, size),
                     back: BlockTexture::new(glam::vec2(656.0, 320.0) / 1024.0, size),
                 },
+                break_particle_texture: None,
+                sound_group: SoundGroup::Stone,
+                emission: [0; 3],
+                hardness: 3.5,
+                state: StateSchema::default().with("lit", StateKind::Bool, 0),
+                state_textures: vec![StateTexture {
+                    property: "lit",
+                    value: 1,
+                    face: BlockFace::Front,
+                    texture: BlockTexture::new(glam::vec2(640.0, 320.0) / 1024.0, size),
+                }],
             },
             BlockData {
                 name: "observer".to_owned(),
@@ -168,190 +361,1874 @@ impl World {
                     front: BlockTexture::new(glam::vec2(800.0, 368.0) / 1024.0, size),
                     back: BlockTexture::new(glam::vec2(768.0, 368.0) / 1024.0, size),
                 },
+                break_particle_texture: None,
+                sound_group: SoundGroup::Stone,
+                emission: [0; 3],
+                hardness: 3.0,
+                state: StateSchema::default(),
+                state_textures: Vec::new(),
             },
-        ];
-        let texture = Texture::load(
-            device,
-            queue,
-            "res/images/minecraft_textures_block_atlas.png",
-            false,
-            "Block Atlas",
-        )
-        .await?;
-        let registry_bind_group_layout = BlockRegistry::create_bind_group_layout(device);
-        let registry = BlockRegistry {
-            bind_group: device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &registry_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            BlockData {
+                name: "cobblestone_wall".to_owned(),
+                mesh_type: BlockMeshType::Fence(BlockTexture::new(
+                    glam::vec2(624.0, 272.0) / 1024.0,
+                    size,
+                )),
+                break_particle_texture: None,
+                sound_group: SoundGroup::Stone,
+                emission: [0; 3],
+                hardness: 2.0,
+                state: StateSchema::default().with("connections", StateKind::U8, 0),
+                state_textures: Vec::new(),
+            },
+            BlockData {
+                name: "lit_furnace".to_owned(),
+                mesh_type: BlockMeshType::Directional {
+                    right: BlockTexture::new(glam::vec2(656.0, 320.0) / 1024.0, size),
+                    left: BlockTexture::new(glam::vec2(656.0, 320.0) / 1024.0, size),
+                    top: BlockTexture::new(glam::vec2(672.0, 320.0) / 1024.0, size),
+                    bottom: BlockTexture::new(glam::vec2(672.0, 320.0) / 1024.0, size),
+                    front: BlockTexture::new(glam::vec2(640.0, 320.0) / 1024.0, size),
+                    back: BlockTexture::new(glam::vec2(656.0, 320.0) / 1024.0, size),
+                },
+                break_particle_texture: None,
+                sound_group: SoundGroup::Stone,
+                emission: [13, 11, 8],
+                hardness: 3.5,
+                state: StateSchema::default(),
+                state_textures: Vec::new(),
+            },
+            BlockData {
+                name: "glass".to_owned(),
+                mesh_type: BlockMeshType::Translucent(BlockTexture::new(
+                    glam::vec2(704.0, 320.0) / 1024.0,
+                    size,
+                )),
+                break_particle_texture: None,
+                sound_group: SoundGroup::Stone,
+                emission: [0; 3],
+                hardness: 0.45,
+                state: StateSchema::default(),
+                state_textures: Vec::new(),
+            },
+            BlockData {
+                name: "ice".to_owned(),
+                mesh_type: BlockMeshType::Translucent(BlockTexture::new(
+                    glam::vec2(608.0, 320.0) / 1024.0,
+                    size,
+                )),
+                break_particle_texture: None,
+                sound_group: SoundGroup::Stone,
+                emission: [0; 3],
+                hardness: 0.75,
+                state: StateSchema::default(),
+                state_textures: Vec::new(),
+            },
+            BlockData {
+                name: "water".to_owned(),
+                mesh_type: BlockMeshType::Translucent(
+                    BlockTexture::animated(glam::vec2(0.0, 896.0) / 1024.0, size, 8, 8.0)
+                        .with_color([0x3f, 0x76, 0xe4, 0xff].into()),
+                ),
+                break_particle_texture: None,
+                sound_group: SoundGroup::None,
+                emission: [0; 3],
+                hardness: 0.0,
+                state: StateSchema::default(),
+                state_textures: Vec::new(),
+            },
+            BlockData {
+                name: "oak_log".to_owned(),
+                mesh_type: BlockMeshType::Surrounded {
+                    top: BlockTexture::new(glam::vec2(688.0, 368.0) / 1024.0, size),
+                    bottom: BlockTexture::new(glam::vec2(688.0, 368.0) / 1024.0, size),
+                    sides: BlockTexture::new(glam::vec2(672.0, 368.0) / 1024.0, size),
+                },
+                break_particle_texture: None,
+                sound_group: SoundGroup::Stone,
+                emission: [0; 3],
+                hardness: 2.0,
+                state: StateSchema::default(),
+                state_textures: Vec::new(),
+            },
+            BlockData {
+                name: "oak_leaves".to_owned(),
+                // the texture has holes, which only the translucent pass leaves see-through
+                mesh_type: BlockMeshType::Translucent(
+                    BlockTexture::new(glam::vec2(656.0, 368.0) / 1024.0, size)
+                        .with_biome_tint(BiomeTint::Foliage),
+                ),
+                break_particle_texture: None,
+                sound_group: SoundGroup::Grass,
+                emission: [0; 3],
+                hardness: 0.2,
+                state: StateSchema::default(),
+                state_textures: Vec::new(),
+            },
+            BlockData {
+                name: "mossy_cobblestone".to_owned(),
+                mesh_type: BlockMeshType::SameSided(BlockTexture::new(
+                    glam::vec2(272.0, 368.0) / 1024.0,
+                    size,
+                )),
+                break_particle_texture: None,
+                sound_group: SoundGroup::Stone,
+                emission: [0; 3],
+                hardness: 2.0,
+                state: StateSchema::default(),
+                state_textures: Vec::new(),
+            },
+            BlockData {
+                name: "sand".to_owned(),
+                mesh_type: BlockMeshType::SameSided(BlockTexture::new(
+                    glam::vec2(752.0, 416.0) / 1024.0,
+                    size,
+                )),
+                break_particle_texture: None,
+                sound_group: SoundGroup::Gravel,
+                emission: [0; 3],
+                hardness: 0.5,
+                state: StateSchema::default(),
+                state_textures: Vec::new(),
+            },
+            BlockData {
+                name: "snow".to_owned(),
+                mesh_type: BlockMeshType::SameSided(BlockTexture::new(
+                    glam::vec2(784.0, 432.0) / 1024.0,
+                    size,
+                )),
+                break_particle_texture: None,
+                sound_group: SoundGroup::Gravel,
+                emission: [0; 3],
+                hardness: 0.2,
+                state: StateSchema::default(),
+                state_textures: Vec::new(),
+            },
+        ]
+    }
+
+    /// Ids of every block but air and water, the blocks the hotbar starts out with.
+    pub fn default_hotbar(&self) -> Vec<u32> {
+        (self.blocks.iter().zip(0..))
+            .filter(|(block, _)| !["air", "water"].contains(&block.name.as_str()))
+            .map(|(_, id)| id)
+            .collect()
+    }
+
+    /// Id of air, which breaking a block leaves behind.
+    pub fn air(&self) -> u32 {
+        self.block_map["air"]
+    }
+
+    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Block Registry Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        // the atlas is bound as an array of one layer
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
                     },
-                ],
-                label: Some("Block Bind Group"),
-            }),
-            texture,
-            block_map: (blocks.iter().enumerate())
-                .map(|(i, b)| (b.name.clone(), i as _))
-                .collect(),
-            blocks,
-        };
-        let loaded_chunks = vec![Chunk::generate(glam::ivec3(0, -1, 0))];
-        Ok(Self {
-            registry,
-            loaded_chunks,
-            instance_buffer: None,
-            render_pipeline: Chunk::create_render_pipeline(
-                &device,
-                &config,
-                &registry_bind_group_layout,
-                &camera_bind_group_layout,
-                &light_bind_group_layout,
-            ),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
         })
     }
 
-    pub fn raycast(
-        &self,
-        origin: glam::Vec3,
-        direction: glam::Vec3,
-        max_distance: f32,
-    ) -> Option<(usize, usize, BlockFace)> {
-        let mut pos = origin;
-        let mut ipos = pos.floor().as_ivec3();
-
-        fn dist_to_whole(pos: f32, dir: f32) -> f32 {
-            if dir == 0.0 {
-                f32::INFINITY
-            } else if 0.0 < dir {
-                (pos.floor() + 1.0 - pos) / dir + f32::EPSILON
-            } else {
-                (pos.ceil() - 1.0 - pos) / dir + f32::EPSILON
+    /// Loads the block textures from the atlas at `path`, stored as `backend` says. With
+    /// `BlockTextureBackend::Array` the textures of `blocks` and `extra` are pointed at their
+    /// layers.
+    async fn load_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+        blocks: &mut [BlockData],
+        extra: &mut [BlockTexture],
+        backend: BlockTextureBackend,
+        anisotropy: u16,
+    ) -> Result<Texture> {
+        let label = "Block Atlas";
+        let mut texture = match backend {
+            BlockTextureBackend::Atlas => {
+                let mut texture = Texture::load_with_mipmaps(
+                    device,
+                    queue,
+                    path,
+                    false,
+                    label,
+                    Mipmaps::Tiled(16),
+                )
+                .await?;
+                texture.view = texture.texture.create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(wgpu::TextureViewDimension::D2Array),
+                    ..Default::default()
+                });
+                texture
             }
-        }
+            BlockTextureBackend::Array => {
+                let atlas = image::load_from_memory(&tokio::fs::read(path).await?)?.to_rgba8();
+                let atlas_size = glam::uvec2(atlas.width(), atlas.height()).as_vec2();
 
-        loop {
-            // tracing::info!("Raycast: pos={pos}  ipos={ipos}  dir={direction}");
-            let dx = dist_to_whole(pos.x, direction.x);
-            let dy = dist_to_whole(pos.y, direction.y);
-            let dz = dist_to_whole(pos.z, direction.z);
-
-            let block_face;
-            if dx < dy && dx < dz {
-                // tracing::info!("Raycast[dx]:  dx={dx}  dy={dy}  dz={dz}");
-                pos += direction * dx;
-                ipos.x += direction.x.signum() as i32;
-                block_face = match 0.0 < direction.x {
-                    true => BlockFace::Left,
-                    false => BlockFace::Right,
-                };
-            } else if dy < dz {
-                // tracing::info!("Raycast[dy]:  dx={dx}  dy={dy}  dz={dz}");
-                pos += direction * dy;
-                ipos.y += direction.y.signum() as i32;
-                block_face = match 0.0 < direction.y {
-                    true => BlockFace::Bottom,
-                    false => BlockFace::Top,
-                };
-            } else {
-                // tracing::info!("Raycast[dz]:  dx={dx}  dy={dy}  dz={dz}");
-                pos += direction * dz;
-                ipos.z += direction.z.signum() as i32;
-                block_face = match 0.0 < direction.z {
-                    true => BlockFace::Front,
-                    false => BlockFace::Back,
-                };
+                let mut layers = Vec::new();
+                let mut rects = HashMap::<[u32; 4], u32>::default();
+                let textures = (blocks.iter_mut())
+                    .flat_map(|block| {
+                        let particles = block.break_particle_texture.as_mut();
+                        let states = block.state_textures.iter_mut().map(|s| &mut s.texture);
+                        (block.mesh_type.textures_mut().into_iter())
+                            .chain(particles)
+                            .chain(states)
+                    })
+                    .chain(extra);
+                for texture in textures {
+                    let pos = (texture.pos * atlas_size).round().as_uvec2();
+                    let size = (texture.size * atlas_size).round().as_uvec2();
+                    let rect = [pos.x, pos.y, size.x, size.y * texture.frames];
+                    texture.layer = *rects.entry(rect).or_insert_with(|| {
+                        let first = layers.len() as u32;
+                        for frame in 0..texture.frames {
+                            let y = pos.y + frame * size.y;
+                            let tile = image::imageops::crop_imm(&atlas, pos.x, y, size.x, size.y);
+                            layers.push(tile.to_image());
+                        }
+                        first
+                    });
+                    texture.pos = glam::Vec2::ZERO;
+                    texture.size = glam::Vec2::ONE;
+                }
+                Texture::from_layers(device, queue, &layers, false, label, Mipmaps::Full)?
             }
+        };
+        texture.set_anisotropy(device, label, anisotropy)?;
+        Ok(texture)
+    }
 
-            if max_distance * max_distance < origin.distance_squared(pos) {
-                // tracing::info!("Raycast[max distance reached at]:  origin={origin} pos={pos}");
-                break None;
-            }
+    /// Cuts the first frame of each block's main texture out of the atlas at `path`. Has to run
+    /// before `load_texture`, which may point the textures at layers instead.
+    async fn load_icons(
+        path: impl AsRef<Path>,
+        blocks: &[BlockData],
+    ) -> Result<Vec<Option<image::RgbaImage>>> {
+        let atlas = image::load_from_memory(&tokio::fs::read(path).await?)?.to_rgba8();
+        let atlas_size = glam::uvec2(atlas.width(), atlas.height()).as_vec2();
+        Ok((blocks.iter())
+            .map(|block| {
+                let texture = block.main_texture()?;
+                let pos = (texture.pos * atlas_size).round().as_uvec2();
+                let size = (texture.size * atlas_size).round().as_uvec2();
+                Some(image::imageops::crop_imm(&atlas, pos.x, pos.y, size.x, size.y).to_image())
+            })
+            .collect())
+    }
 
-            let chunk_pos = ipos.div_euclid(32 * glam::IVec3::ONE);
-            let Some((chunk_i, chunk)) = self
-                .loaded_chunks
-                .iter()
-                .enumerate()
-                .find(|(_, c)| c.pos == chunk_pos)
-            else {
-                continue;
-            };
-            let block_i = Chunk::block_pos_to_idx((ipos - 32 * chunk_pos).as_uvec3());
-            let block = &chunk.blocks[block_i];
+    /// Header of the block name table, with the version of its format.
+    const NAME_TABLE_HEADER: &'static str = "mclone block names 1";
 
-            if block.id != 0 {
-                // tracing::info!(
-                //     "Raycast[hit block]:  pos={pos}  ipos={ipos}  chunk_pos={chunk_pos}"
-                // );
-                break Some((chunk_i, block_i, block_face));
-            }
+    /// Writes the block names in id order, so saved ids can be mapped back to blocks after the
+    /// registry changes.
+    pub fn save_name_table(&self, path: &Path) -> std::io::Result<()> {
+        let mut table = format!("{}\n", Self::NAME_TABLE_HEADER);
+        for block in &self.blocks {
+            table += &block.name;
+            table.push('\n');
         }
+        fs::write(path, table)
     }
 
-    pub fn draw<'a>(
-        &'a mut self,
-        device: &wgpu::Device,
-        render_pass: &mut wgpu::RenderPass<'a>,
-        camera_bind_group: &'a wgpu::BindGroup,
-        light_bind_group: &'a wgpu::BindGroup,
-    ) {
-        let instance_buffer = self.instance_buffer.get_or_insert_with(|| {
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Chunk Instance Buffer"),
-                contents: bytemuck::cast_slice(&[ChunkInstance {
-                    offset: glam::vec3(0.0, -32.0, 0.0),
-                }]),
-                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    /// Reads a name table written by `save_name_table`, returning the current id of each saved
+    /// id. Blocks that no longer exist become air.
+    pub fn load_id_map(&self, path: &Path) -> Result<Vec<u32>> {
+        let table = fs::read_to_string(path)?;
+        let mut lines = table.lines();
+        if lines.next() != Some(Self::NAME_TABLE_HEADER) {
+            bail!("Unsupported block name table {}", path.display());
+        }
+        Ok(lines
+            .map(|name| {
+                self.block_map.get(name).copied().unwrap_or_else(|| {
+                    tracing::warn!("Saved block {name:?} no longer exists, replacing it with air");
+                    self.air()
+                })
             })
-        });
+            .collect())
+    }
+}
 
-        render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_bind_group(0, &self.registry.bind_group, &[]);
-        render_pass.set_bind_group(1, camera_bind_group, &[]);
-        render_pass.set_bind_group(2, light_bind_group, &[]);
-        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
-        for chunk in &mut self.loaded_chunks {
-            if chunk.vertex_buffer.is_none() {
-                chunk.gen_mesh(device, &self.registry);
-            }
-            render_pass.set_vertex_buffer(0, chunk.vertex_buffer.as_ref().unwrap().slice(..));
-            let index_buffer = chunk.index_buffer.as_ref().unwrap();
-            render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-            render_pass.draw_indexed(0..(6 * chunk.vertices.len()) as _, 0, 0..1);
+/// A block hit by a ray, positions being relative to the origin like the camera's.
+#[derive(Debug, Copy, Clone)]
+pub struct RaycastHit {
+    pub chunk_i: usize,
+    pub block_i: usize,
+    /// Face of the block the ray entered it through.
+    pub face: BlockFace,
+    /// Where the ray hit the block's surface.
+    pub position: glam::Vec3,
+    pub block_pos: glam::IVec3,
+}
+
+impl RaycastHit {
+    /// Normal of the face that was hit, pointing back out of the block.
+    pub fn normal(&self) -> glam::Vec3 {
+        self.face.voffset().as_vec3()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BlockAction {
+    Break,
+    Place,
+}
+
+/// Debug view that colors block faces by their light level instead of their texture.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum LightDebugView {
+    #[default]
+    Off,
+    Sky,
+    Block,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct WorldUniform {
+    pub light_debug: u32,
+    pub fade_start: f32,
+    pub fade_end: f32,
+    /// Seconds animated block textures have been playing for.
+    pub time: f32,
+    pub fog_color: glam::Vec4,
+}
+
+/// Weights scoring which chunks get meshed first, lower scores going first.
+#[derive(Debug, Copy, Clone)]
+pub struct MeshPriority {
+    /// Score per block of distance between the camera and the chunk's center.
+    pub distance_weight: f32,
+    /// Score taken off chunks straight ahead of the camera, scaled down towards its sides and
+    /// added to chunks behind it.
+    pub view_weight: f32,
+}
+
+impl Default for MeshPriority {
+    fn default() -> Self {
+        Self {
+            distance_weight: 1.0,
+            view_weight: 32.0,
         }
     }
+}
 
-    pub fn window_event(
-        &mut self,
+/// What the last `World::draw` drew.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct DrawStats {
+    /// Chunks with opaque faces drawn.
+    pub chunks: usize,
+    pub triangles: usize,
+}
+
+/// File in a save directory listing the block names the saved ids refer to.
+const BLOCK_NAMES_FILE: &str = "blocks.txt";
+
+/// Vertical position of the one layer of chunks the terrain is generated in.
+const TERRAIN_LAYER: i32 = -1;
+
+/// Number keys selecting the hotbar slots, in order.
+const DIGIT_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+pub struct World {
+    pub registry: BlockRegistry,
+    /// Textures of the blocks, stored as the `BlockTextureBackend` picked at startup.
+    pub block_texture: Texture,
+    pub block_bind_group: wgpu::BindGroup,
+    /// Seed of the terrain, the same seed always generates the same chunks.
+    pub seed: u64,
+    terrain: Box<dyn TerrainGenerator>,
+    /// Blocks of trees and boulders that reached into chunks which weren't loaded yet, by the
+    /// position of the chunk and then the block's index in it. See `decorate_chunk`.
+    pub pending_structure_edits: HashMap<glam::IVec3, Vec<(usize, u32)>>,
+    pub loaded_chunks: Vec<Chunk>,
+    /// Index of each loaded chunk in `loaded_chunks` by its position, rebuilt by `index_chunks`
+    /// whenever chunks are loaded.
+    chunk_indices: HashMap<glam::IVec3, usize>,
+    /// Offset of each loaded chunk from the origin, indexed by the chunk's position in
+    /// `loaded_chunks`. Recreated when the origin moves or the loaded chunks change.
+    pub instance_buffer: Option<wgpu::Buffer>,
+    /// Quad indices shared by the meshes of every chunk, see `reserve_indices`.
+    pub index_buffer: wgpu::Buffer,
+    /// Number of quads `index_buffer` has indices for.
+    index_capacity: usize,
+    pub render_pipeline: wgpu::RenderPipeline,
+    /// Draws the opaque faces after `draw_depth_prepass`, only shading the nearest ones.
+    pub prepassed_render_pipeline: wgpu::RenderPipeline,
+    /// Draws the opaque faces into the scene depth, see `draw_depth_prepass`.
+    pub depth_prepass_render_pipeline: wgpu::RenderPipeline,
+    /// Whether `draw_depth_prepass` is called before `draw`, which then draws the opaque faces
+    /// with `prepassed_render_pipeline`. Set through `set_depth_prepass`.
+    depth_prepass: bool,
+    /// Draws the faces of translucent blocks after the opaque ones.
+    pub translucent_render_pipeline: wgpu::RenderPipeline,
+    /// Draws the opaque faces into the shadow map.
+    pub shadow_render_pipeline: wgpu::RenderPipeline,
+    /// Color format and sample count of the scene pass, which chunk render bundles are
+    /// recorded for.
+    color_format: wgpu::TextureFormat,
+    sample_count: u32,
+    pub uniform: WorldUniform,
+    pub uniform_buffer: wgpu::Buffer,
+    pub uniform_bind_group: wgpu::BindGroup,
+    pub highlight: BlockHighlight,
+    pub particles: Particles,
+    pub audio: Audio,
+
+    light_debug: LightDebugView,
+    /// Fraction of the day that has passed since midnight, 0.5 being noon.
+    time_of_day: f32,
+    /// Horizontal distance from the camera at which chunks start fading into the sky.
+    pub fade_start: f32,
+    /// Horizontal distance from the camera at which chunks are fully faded into the sky.
+    pub fade_end: f32,
+
+    /// Chunk that rendering and the camera position are relative to, keeping coordinates small
+    /// enough for `f32` far away from the world's origin.
+    pub origin: glam::IVec3,
+    /// Distance from the origin along any axis the camera can go before the origin is moved.
+    pub rebase_distance: f32,
+
+    /// Minimum time between two actions of the same kind.
+    pub interaction_cooldown: Duration,
+    last_action: [Option<Instant>; 2],
+    /// Whether the left mouse button is held, mining the targeted block.
+    mining: bool,
+    /// Chunk and block index of the block being mined, and how far along breaking it is from
+    /// 0 to 1.
+    mined_block: Option<(usize, usize)>,
+    mining_progress: f32,
+
+    /// Directory to cache generated chunk meshes in, disabled when `None`.
+    pub mesh_cache_dir: Option<PathBuf>,
+    /// Directory F5 saves the world to and F9 reloads it from, disabled when `None`.
+    pub save_dir: Option<PathBuf>,
+    /// Whether block targeting passes through transparent blocks instead of stopping at them.
+    pub target_through_transparent: bool,
+    /// Whether rays pass through unloaded chunks instead of stopping at them as if they were
+    /// solid.
+    pub ray_through_unloaded: bool,
+    /// Edited meshes up to this many bytes are written directly instead of via the staging belt.
+    pub direct_upload_limit: u64,
+    /// Place blocks in the air at full reach when no block is targeted.
+    pub place_in_air: bool,
+    /// Block ids to pick what to place from, switched with the mouse wheel and number keys.
+    pub hotbar: Vec<u32>,
+    pub selected_slot: usize,
+    pub mesh_priority: MeshPriority,
+    /// Maximum number of chunks meshed each frame.
+    pub meshes_per_frame: usize,
+    /// Radius in chunks of the circle of chunks kept loaded around the camera, see
+    /// `update_loaded_chunks`.
+    pub render_distance: u32,
+    /// Maximum number of chunks generated each frame.
+    pub loads_per_frame: usize,
+    /// Mesh chunks with merged quads, see `Chunk::greedy`.
+    pub greedy_meshing: bool,
+    /// Skip drawing empty chunks and solid chunks buried between solid neighbours.
+    pub cull_hidden_chunks: bool,
+    pub draw_stats: DrawStats,
+}
+
+impl World {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn new(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        cam: &Camera,
-        event: &WindowEvent,
-    ) -> bool {
-        match event {
-            WindowEvent::MouseInput {
-                button: MouseButton::Right,
-                state: ElementState::Pressed,
+        config: &wgpu::SurfaceConfiguration,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        light_bind_group_layout: &wgpu::BindGroupLayout,
+        shadow_bind_group_layout: &wgpu::BindGroupLayout,
+        texture_backend: BlockTextureBackend,
+        anisotropy: u16,
+        sample_count: u32,
+        seed: u64,
+        terrain: Box<dyn TerrainGenerator>,
+    ) -> Result<Self> {
+        let size = glam::vec2(16., 16.) / 1024.0;
+        let mut blocks = BlockRegistry::default_blocks();
+        // ten stages side by side in the atlas
+        let mut crack_textures: Vec<_> = (0..10)
+            .map(|stage| {
+                BlockTexture::new(
+                    glam::vec2(528.0 + 16.0 * stage as f32, 304.0) / 1024.0,
+                    size,
+                )
+            })
+            .collect();
+        let atlas_path = "res/images/minecraft_textures_block_atlas.png";
+        let icons = BlockRegistry::load_icons(atlas_path, &blocks).await?;
+        let texture = BlockRegistry::load_texture(
+            device,
+            queue,
+            atlas_path,
+            &mut blocks,
+            &mut crack_textures,
+            texture_backend,
+            anisotropy,
+        )
+        .await?;
+        let registry_bind_group_layout = BlockRegistry::create_bind_group_layout(device);
+        let registry = BlockRegistry::new(blocks, crack_textures, icons);
+        let hotbar = registry.default_hotbar();
+        let block_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &registry_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some("Block Bind Group"),
+        });
+        let uniform = WorldUniform {
+            light_debug: LightDebugView::Off as _,
+            fade_start: 64.0,
+            fade_end: 96.0,
+            time: 0.0,
+            fog_color: glam::Vec4::ZERO,
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("World Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("World Uniform Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("World Uniform Bind Group"),
+            layout: &uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let mut world = Self {
+            registry,
+            block_texture: texture,
+            block_bind_group,
+            seed,
+            terrain,
+            pending_structure_edits: HashMap::default(),
+            loaded_chunks: Vec::new(),
+            chunk_indices: HashMap::default(),
+            instance_buffer: None,
+            index_buffer: Self::create_index_buffer(device, 1024),
+            index_capacity: 1024,
+            origin: glam::IVec3::ZERO,
+            rebase_distance: 4096.0,
+            uniform,
+            uniform_buffer,
+            uniform_bind_group,
+            highlight: BlockHighlight::new(
+                device,
+                config,
+                camera_bind_group_layout,
+                &registry_bind_group_layout,
+                sample_count,
+            ),
+            particles: Particles::new(
+                device,
+                config,
+                camera_bind_group_layout,
+                &registry_bind_group_layout,
+                sample_count,
+            ),
+            audio: Audio::new(Path::new("res/sounds")),
+            light_debug: LightDebugView::Off,
+            time_of_day: 0.5,
+            fade_start: uniform.fade_start,
+            fade_end: uniform.fade_end,
+            interaction_cooldown: Duration::from_millis(150),
+            last_action: [None; 2],
+            mining: false,
+            mined_block: None,
+            mining_progress: 0.0,
+            mesh_cache_dir: None,
+            save_dir: Some(PathBuf::from("saves/world")),
+            target_through_transparent: false,
+            ray_through_unloaded: false,
+            cull_hidden_chunks: true,
+            draw_stats: DrawStats::default(),
+            color_format: config.format,
+            sample_count,
+            place_in_air: false,
+            hotbar,
+            selected_slot: 0,
+            mesh_priority: MeshPriority::default(),
+            meshes_per_frame: 4,
+            render_distance: 3,
+            loads_per_frame: 2,
+            greedy_meshing: false,
+            direct_upload_limit: 4096,
+            render_pipeline: Chunk::create_render_pipeline(
+                &device,
+                &config,
+                &registry_bind_group_layout,
+                &camera_bind_group_layout,
+                &light_bind_group_layout,
+                &uniform_bind_group_layout,
+                sample_count,
+            ),
+            prepassed_render_pipeline: Chunk::create_prepassed_render_pipeline(
+                device,
+                config,
+                &registry_bind_group_layout,
+                camera_bind_group_layout,
+                light_bind_group_layout,
+                &uniform_bind_group_layout,
+                sample_count,
+            ),
+            depth_prepass_render_pipeline: Chunk::create_depth_prepass_render_pipeline(
+                device,
+                &registry_bind_group_layout,
+                camera_bind_group_layout,
+                light_bind_group_layout,
+                &uniform_bind_group_layout,
+                sample_count,
+            ),
+            depth_prepass: false,
+            translucent_render_pipeline: Chunk::create_translucent_render_pipeline(
+                device,
+                config,
+                &registry_bind_group_layout,
+                camera_bind_group_layout,
+                light_bind_group_layout,
+                &uniform_bind_group_layout,
+                sample_count,
+            ),
+            shadow_render_pipeline: Chunk::create_shadow_render_pipeline(
+                device,
+                shadow_bind_group_layout,
+            ),
+        };
+        // a 2x2 grid of chunks around the origin, each drawn with its own instance offset
+        for (x, z) in [(-1, -1), (0, -1), (-1, 0), (0, 0)] {
+            let chunk = world.generate_chunk(glam::ivec3(x, TERRAIN_LAYER, z));
+            world.loaded_chunks.push(chunk);
+        }
+        world.index_chunks();
+        let positions: Vec<_> = world.loaded_chunks.iter().map(|c| c.pos).collect();
+        for &pos in &positions {
+            world.decorate_chunk(pos);
+        }
+        for pos in positions {
+            world.recompute_light(pos);
+        }
+        Ok(world)
+    }
+
+    /// Seed for randomness tied to `pos`, mixed with the world's `seed`.
+    fn position_seed(seed: u64, pos: glam::IVec3) -> u64 {
+        let mut hash = seed;
+        for c in pos.to_array() {
+            hash = (hash ^ c as u32 as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        hash
+    }
+
+    /// Generates the chunk at `pos` with the world's terrain generator. The random details are
+    /// seeded from `seed` and `pos`, so the same chunk always comes out.
+    pub fn generate_chunk(&self, pos: glam::IVec3) -> Chunk {
+        let blocks = &self.registry.terrain_blocks;
+        Self::generate_seeded_chunk(&*self.terrain, blocks, self.seed, pos)
+    }
+
+    fn generate_seeded_chunk(
+        terrain: &dyn TerrainGenerator,
+        blocks: &TerrainBlocks,
+        seed: u64,
+        pos: glam::IVec3,
+    ) -> Chunk {
+        let mut rng = SmallRng::seed_from_u64(Self::position_seed(seed, pos));
+        Chunk::generate(pos, terrain, blocks, &mut rng)
+    }
+
+    /// Places trees and boulders on the ground of the loaded chunk at `pos`, each column rolling
+    /// for one with a seed from its position. Blocks landing in chunks that aren't loaded wait in
+    /// `pending_structure_edits` until those chunks are decorated, so the world comes out the
+    /// same whichever order its chunks are generated in. The structures of loaded neighbours
+    /// reaching into the chunk are placed again too, in case it was unloaded since they were
+    /// decorated. Call before the chunk is meshed and lit. Returns the blocks changed in other
+    /// chunks, relative to the origin.
+    pub fn decorate_chunk(&mut self, pos: glam::IVec3) -> Vec<glam::IVec3> {
+        let origin = self.origin;
+        (self.decorator().decorate(pos).into_iter())
+            .filter(|block_pos| block_pos.div_euclid(glam::IVec3::splat(32)) != pos)
+            .map(|block_pos| block_pos - 32 * origin)
+            .collect()
+    }
+
+    fn decorator(&mut self) -> Decorator<'_> {
+        Decorator {
+            terrain: &*self.terrain,
+            blocks: &self.registry.terrain_blocks,
+            seed: self.seed,
+            chunks: &mut self.loaded_chunks,
+            chunk_indices: &self.chunk_indices,
+            pending: &mut self.pending_structure_edits,
+        }
+    }
+
+    pub fn light_debug(&self) -> LightDebugView {
+        self.light_debug
+    }
+
+    pub fn set_light_debug(&mut self, queue: &wgpu::Queue, view: LightDebugView) {
+        self.light_debug = view;
+        self.uniform.light_debug = view as _;
+        self.write_uniform(queue);
+    }
+
+    pub fn time_of_day(&self) -> f32 {
+        self.time_of_day
+    }
+
+    /// Sets the fraction of the day that has passed since midnight, wrapping around into the
+    /// next day.
+    pub fn set_time_of_day(&mut self, t: f32) {
+        self.time_of_day = t.rem_euclid(1.0);
+    }
+
+    /// Advances animated block textures by `delta_time` seconds.
+    pub fn update_animations(&mut self, queue: &wgpu::Queue, delta_time: f32) {
+        // wrapped so the time keeps its precision, with a hitch in long animations once an hour
+        self.uniform.time = (self.uniform.time + delta_time) % 3600.0;
+        self.write_uniform(queue);
+    }
+
+    /// Updates the color distant chunks fade into, which should match the sky's horizon.
+    pub fn update_fog(&mut self, queue: &wgpu::Queue, fog_color: glam::Vec4) {
+        self.uniform.fog_color = fog_color;
+        self.write_uniform(queue);
+    }
+
+    fn write_uniform(&mut self, queue: &wgpu::Queue) {
+        self.uniform.fade_start = self.fade_start;
+        self.uniform.fade_end = self.fade_end;
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.uniform]),
+        );
+    }
+
+    /// Casts a ray that stops at the first non-air block.
+    pub fn raycast(
+        &self,
+        origin: glam::Vec3,
+        direction: glam::Vec3,
+        max_distance: f32,
+    ) -> Option<RaycastHit> {
+        let air = self.registry.air();
+        self.raycast_until(origin, direction, max_distance, |id| id != air)
+    }
+
+    /// Casts a ray that stops at the first block whose id satisfies `stops`, stepping through
+    /// the blocks along it one at a time. Unloaded chunks stop the ray unless
+    /// `ray_through_unloaded` is set.
+    pub fn raycast_until(
+        &self,
+        origin: glam::Vec3,
+        direction: glam::Vec3,
+        max_distance: f32,
+        stops: impl Fn(u32) -> bool,
+    ) -> Option<RaycastHit> {
+        let dir = direction.try_normalize()?;
+        let ((chunk_i, block_i), block_pos, face, dist) = Self::cast_ray(
+            origin,
+            dir,
+            max_distance,
+            self.ray_through_unloaded,
+            |pos| {
+                let (chunk_i, block_i) = self.locate_block(pos)?;
+                let block = &self.loaded_chunks[chunk_i].blocks[block_i];
+                Some(stops(block.id).then_some((chunk_i, block_i)))
+            },
+        )?;
+        Some(RaycastHit {
+            chunk_i,
+            block_i,
+            face,
+            position: origin + dist * dir,
+            block_pos,
+        })
+    }
+
+    /// Steps a ray with the normalized direction `dir` through the blocks along it, stopping at
+    /// the first one `hit` returns a value for. Returns that value with the block's position,
+    /// the face the ray entered it through, and the distance to it. Blocks `hit` returns `None`
+    /// for are missing, and stop the ray unless `through_missing` is set.
+    fn cast_ray<T>(
+        origin: glam::Vec3,
+        dir: glam::Vec3,
+        max_distance: f32,
+        through_missing: bool,
+        mut hit: impl FnMut(glam::IVec3) -> Option<Option<T>>,
+    ) -> Option<(T, glam::IVec3, BlockFace, f32)> {
+        let mut ipos = origin.floor().as_ivec3();
+        let step = dir.signum().as_ivec3();
+
+        // distance along the ray to the next block boundary on each axis, and between two of them
+        let boundary = |pos: f32, dir: f32| {
+            if 0.0 < dir {
+                (pos.floor() + 1.0 - pos) / dir
+            } else if dir < 0.0 {
+                (pos.floor() - pos) / dir
+            } else {
+                f32::INFINITY
+            }
+        };
+        let mut next = glam::vec3(
+            boundary(origin.x, dir.x),
+            boundary(origin.y, dir.y),
+            boundary(origin.z, dir.z),
+        );
+        let delta = dir.abs().recip();
+
+        loop {
+            let axis = if next.x < next.y && next.x < next.z {
+                0
+            } else if next.y < next.z {
+                1
+            } else {
+                2
+            };
+            let dist = next[axis];
+            next[axis] += delta[axis];
+            ipos[axis] += step[axis];
+            // the face the ray enters the block through
+            let block_face = match (axis, 0 < step[axis]) {
+                (0, true) => BlockFace::Left,
+                (0, false) => BlockFace::Right,
+                (1, true) => BlockFace::Bottom,
+                (1, false) => BlockFace::Top,
+                (_, true) => BlockFace::Front,
+                (_, false) => BlockFace::Back,
+            };
+
+            if max_distance < dist {
+                break None;
+            }
+
+            match hit(ipos) {
+                Some(Some(value)) => break Some((value, ipos, block_face, dist)),
+                Some(None) => {}
+                None if through_missing => {}
+                None => break None,
+            }
+        }
+    }
+
+    /// Id of the block in the selected hotbar slot, which right-clicking places.
+    pub fn selected_block(&self) -> u32 {
+        (self.hotbar.get(self.selected_slot).copied()).unwrap_or_else(|| self.registry.air())
+    }
+
+    /// Returns whether `action` is off cooldown, and if so starts its cooldown.
+    pub fn try_action(&mut self, action: BlockAction) -> bool {
+        let now = Instant::now();
+        let last = &mut self.last_action[action as usize];
+        if last.is_some_and(|t| now.duration_since(t) < self.interaction_cooldown) {
+            return false;
+        }
+        *last = Some(now);
+        true
+    }
+
+    /// Raycasts for the block the camera is targeting.
+    pub fn target(&self, cam: &Camera, reach: f32) -> Option<RaycastHit> {
+        let through = self.target_through_transparent;
+        self.raycast_until(cam.pos, cam.dir(), reach, |id| {
+            Self::stops_target(&self.registry, id, through)
+        })
+    }
+
+    /// Whether the ray targeting blocks stops at block `id`. It passes through air, and with
+    /// `through_transparent` through the blocks that can be seen through too.
+    fn stops_target(reg: &BlockRegistry, id: u32, through_transparent: bool) -> bool {
+        let block = &reg.blocks[id as usize];
+        !(block.is_transparent() || through_transparent && block.is_translucent())
+    }
+
+    /// Where a block placed in the air at `reach` in front of the camera would go, `None` if
+    /// that spot is taken, unloaded or overlaps the `player`'s box, or the camera without one.
+    pub fn air_placement(
+        &self,
+        cam: &Camera,
+        reach: f32,
+        player: Option<(glam::Vec3, glam::Vec3)>,
+    ) -> Option<(usize, usize, BlockFace)> {
+        let (pos, face) = Self::air_placement_cell(cam.pos, cam.dir(), reach, player)?;
+        let (chunk_i, block_i) = self.locate_block(pos)?;
+        if self.loaded_chunks[chunk_i].blocks[block_i].id != self.registry.air() {
+            return None;
+        }
+        Some((chunk_i, block_i, face))
+    }
+
+    /// The cell `reach` along `dir` from `eye` and the face of it turned towards the eye, `None`
+    /// if the cell overlaps the `player`'s box, or holds the eye without one.
+    fn air_placement_cell(
+        eye: glam::Vec3,
+        dir: glam::Vec3,
+        reach: f32,
+        player: Option<(glam::Vec3, glam::Vec3)>,
+    ) -> Option<(glam::IVec3, BlockFace)> {
+        let pos = (eye + reach * dir).floor().as_ivec3();
+        let blocked = match player {
+            Some(aabb) => Self::would_block_player(pos, aabb),
+            None => pos == eye.floor().as_ivec3(),
+        };
+        if blocked {
+            return None;
+        }
+
+        // face the camera along the ray's major axis, like a block placed against a face would
+        let abs = dir.abs();
+        let axis = match abs.max_element() {
+            m if m == abs.x => glam::Vec3::X * dir.x.signum(),
+            m if m == abs.y => glam::Vec3::Y * dir.y.signum(),
+            _ => glam::Vec3::Z * dir.z.signum(),
+        };
+        Some((pos, BlockFace::try_from_dir(-axis)?))
+    }
+
+    /// Moves the origin to the camera's chunk once the camera strays `rebase_distance` from it,
+    /// shifting the camera along so the world doesn't appear to move.
+    pub fn rebase_origin(&mut self, cam: &mut Camera) -> bool {
+        if cam.pos.abs().max_element() < self.rebase_distance {
+            return false;
+        }
+        let shift = (cam.pos / 32.0).floor().as_ivec3();
+        self.origin += shift;
+        cam.pos -= (32 * shift).as_vec3();
+        self.particles.shift(-(32 * shift).as_vec3());
+        // the offsets are relative to the old origin
+        self.instance_buffer = None;
+        tracing::info!("Rebased world origin to chunk {}", self.origin);
+        true
+    }
+
+    /// Loads the chunks within `render_distance` of the camera that aren't loaded yet, nearest
+    /// first and at most `loads_per_frame` of them, and unloads the ones more than a chunk beyond
+    /// it. New chunks are generated, decorated and lit, and the faces of meshed neighbours
+    /// against them refreshed. Returns whether the loaded chunks changed.
+    pub fn update_loaded_chunks(&mut self, cam: &Camera) -> bool {
+        let cam_chunk = cam.pos.div_euclid(glam::Vec3::splat(32.0)).as_ivec3() + self.origin;
+        let distance_squared = |pos: glam::IVec3| {
+            let offset = pos - cam_chunk;
+            offset.x * offset.x + offset.z * offset.z
+        };
+        let radius = self.render_distance as i32;
+
+        // the extra chunk keeps chunks at the edge from loading and unloading as the camera
+        // moves back and forth across a chunk border
+        let (kept, unloaded): (Vec<_>, Vec<_>) = (mem::take(&mut self.loaded_chunks).into_iter())
+            .partition(|chunk| distance_squared(chunk.pos) <= (radius + 1).pow(2));
+        self.loaded_chunks = kept;
+        if !unloaded.is_empty() {
+            self.save_unloaded(&unloaded);
+            self.index_chunks();
+            // the indices of the chunks moved
+            self.mined_block = None;
+            self.instance_buffer = None;
+
+            // the edge faces of the neighbours left behind were hidden by the unloaded chunks
+            for chunk in &unloaded {
+                for face in BlockFace::iter() {
+                    if let Some(neighbour_i) = self.chunk_index(chunk.pos + face.voffset()) {
+                        self.loaded_chunks[neighbour_i].invalidate_mesh();
+                        self.invalidate_mesh_cache(neighbour_i);
+                    }
+                }
+            }
+        }
+        let unloaded = !unloaded.is_empty();
+
+        let mut missing: Vec<_> = (-radius..=radius)
+            .flat_map(|x| (-radius..=radius).map(move |z| (x, z)))
+            .map(|(x, z)| glam::ivec3(cam_chunk.x + x, TERRAIN_LAYER, cam_chunk.z + z))
+            .filter(|&pos| distance_squared(pos) <= radius * radius)
+            .filter(|&pos| self.chunk_index(pos).is_none())
+            .collect();
+        if missing.is_empty() {
+            return unloaded;
+        }
+        missing.sort_by_key(|&pos| distance_squared(pos));
+        missing.truncate(self.loads_per_frame);
+        // chunks saved as they were unloaded already have their structures
+        let mut generated = Vec::new();
+        for &pos in &missing {
+            let chunk = self.load_saved_chunk(pos).unwrap_or_else(|| {
+                generated.push(pos);
+                self.generate_chunk(pos)
+            });
+            self.loaded_chunks.push(chunk);
+        }
+        self.index_chunks();
+        self.instance_buffer = None;
+
+        let mut edited = Vec::new();
+        for pos in generated {
+            edited.extend(self.decorate_chunk(pos));
+        }
+        for &pos in &missing {
+            self.recompute_light(pos);
+        }
+
+        // structures reaching from the new chunks into the meshed ones
+        let is_new = |pos: glam::IVec3| missing.contains(&pos);
+        let mut changed = HashSet::default();
+        for pos in edited {
+            if !is_new(pos.div_euclid(glam::IVec3::splat(32)) + self.origin) {
+                changed.extend(self.relight_block(pos));
+                changed.insert(pos);
+            }
+        }
+        self.refresh_lit_faces(&changed);
+
+        // the edge faces of the meshed neighbours were shown while the new chunks weren't loaded
+        for &pos in &missing {
+            for face in BlockFace::iter() {
+                let neighbour = pos + face.voffset();
+                let Some(neighbour_i) = self.chunk_index(neighbour).filter(|_| !is_new(neighbour))
+                else {
+                    continue;
+                };
+                let edge = (0..32 * 32 * 32).filter(|&i| face.flip().is_edge(i));
+                self.with_neighbours(neighbour_i, |chunk, reg, neighbours| {
+                    chunk.refresh_faces(reg, edge, neighbours)
+                });
+                self.invalidate_mesh_cache(neighbour_i);
+            }
+        }
+        true
+    }
+
+    /// Moves the particles by `delta_time` seconds, stopping the ones about to fall into solid
+    /// blocks.
+    pub fn update_particles(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        delta_time: f32,
+    ) {
+        let blocked: Vec<bool> = (self.particles.next_positions(delta_time))
+            .map(|pos| self.is_solid(pos.floor().as_ivec3()))
+            .collect();
+        (self.particles).update(device, queue, delta_time, &blocked);
+    }
+
+    /// Advances mining the targeted block while the left mouse button is held, breaking it once
+    /// its hardness has passed. Progress starts over when the target changes.
+    pub fn update_mining(&mut self, cam: &Camera, delta_time: f32, reach: f32) {
+        let Some(hit) = self.mining.then(|| self.target(cam, reach)).flatten() else {
+            self.mined_block = None;
+            self.mining_progress = 0.0;
+            return;
+        };
+        if self.mined_block != Some((hit.chunk_i, hit.block_i)) {
+            self.mined_block = Some((hit.chunk_i, hit.block_i));
+            self.mining_progress = 0.0;
+        }
+
+        let id = self.loaded_chunks[hit.chunk_i].blocks[hit.block_i].id;
+        let hardness = self.registry.blocks[id as usize].hardness;
+        self.mining_progress = match 0.0 < hardness {
+            true => (self.mining_progress + delta_time / hardness).min(1.0),
+            false => 1.0,
+        };
+        if self.mining_progress < 1.0 || !self.try_action(BlockAction::Break) {
+            return;
+        }
+        let block = &self.registry.blocks[id as usize];
+        if let Some(texture) = block.particle_texture() {
+            self.particles.spawn_burst(hit.block_pos, texture);
+        }
+        let sound = SoundId::new(block.sound_group, SoundKind::Break);
+        (self.audio).play_at(sound, 1.0, hit.block_pos.as_vec3() + 0.5);
+        let air = self.registry.air();
+        self.place_block(hit.chunk_i, hit.block_i, air, hit.face);
+        self.mined_block = None;
+        self.mining_progress = 0.0;
+    }
+
+    /// Moves the block highlight to the block the camera is targeting, cracked as far as it's
+    /// been mined.
+    pub fn update_highlight(&mut self, queue: &wgpu::Queue, cam: &Camera, reach: f32) {
+        let target = self.target(cam, reach).map(|hit| hit.block_pos);
+        let cracks = &self.registry.crack_textures;
+        let crack = (0.0 < self.mining_progress).then(|| {
+            let stage = (self.mining_progress * cracks.len() as f32) as usize;
+            cracks[stage.min(cracks.len() - 1)]
+        });
+        self.highlight
+            .update(queue, target, self.mining_progress, crack);
+    }
+
+    /// Whether `chunk` has nothing to draw or is fully enclosed by solid loaded chunks.
+    pub fn is_chunk_hidden(&self, chunk: &Chunk) -> bool {
+        if chunk.flags.contains(ChunkFlags::EMPTY) {
+            return true;
+        }
+        chunk.flags.contains(ChunkFlags::SOLID)
+            && BlockFace::iter().all(|face| {
+                (self.chunk_index(chunk.pos + face.voffset()))
+                    .is_some_and(|i| self.loaded_chunks[i].flags.contains(ChunkFlags::SOLID))
+            })
+    }
+
+    /// Runs `f` on chunk `chunk_i` alongside its loaded neighbours, looked up in
+    /// `chunk_indices` among the chunks on either side of it in `loaded_chunks`.
+    fn with_neighbours<R>(
+        &mut self,
+        chunk_i: usize,
+        f: impl FnOnce(&mut Chunk, &BlockRegistry, &FacedData<Option<&Chunk>>) -> R,
+    ) -> R {
+        let (before, rest) = self.loaded_chunks.split_at_mut(chunk_i);
+        let (chunk, after) = rest.split_first_mut().expect("chunk index out of bounds");
+        let pos = chunk.pos;
+        let neighbours = FacedData::from_fn(|face| {
+            let i = *self.chunk_indices.get(&(pos + face.voffset()))?;
+            match i.cmp(&chunk_i) {
+                cmp::Ordering::Less => Some(&before[i]),
+                cmp::Ordering::Equal => None,
+                cmp::Ordering::Greater => Some(&after[i - chunk_i - 1]),
+            }
+        });
+        f(chunk, &self.registry, &neighbours)
+    }
+
+    /// Position of a block relative to the origin, in the same space as the camera.
+    pub fn block_pos(&self, chunk_i: usize, block_i: usize) -> glam::IVec3 {
+        32 * (self.loaded_chunks[chunk_i].pos - self.origin)
+            + Chunk::block_idx_to_pos(block_i).as_ivec3()
+    }
+
+    /// Rebuilds the lookup of loaded chunks by position, after `loaded_chunks` was changed.
+    fn index_chunks(&mut self) {
+        self.chunk_indices = (self.loaded_chunks.iter().enumerate())
+            .map(|(i, chunk)| (chunk.pos, i))
+            .collect();
+    }
+
+    /// Index in `loaded_chunks` of the chunk at `pos`, if it's loaded.
+    pub fn chunk_index(&self, pos: glam::IVec3) -> Option<usize> {
+        self.chunk_indices.get(&pos).copied()
+    }
+
+    /// The loaded chunk and block index of the block at `pos` relative to the origin.
+    pub fn locate_block(&self, pos: glam::IVec3) -> Option<(usize, usize)> {
+        let chunk_i = self.chunk_index(pos.div_euclid(glam::IVec3::splat(32)) + self.origin)?;
+        let block_pos = pos.rem_euclid(glam::IVec3::splat(32)).as_uvec3();
+        Some((chunk_i, Chunk::block_pos_to_idx(block_pos)))
+    }
+
+    /// The block next to `block_i` across `face`, which may be in a neighbouring chunk.
+    fn adjacent_block(
+        &self,
+        chunk_i: usize,
+        block_i: usize,
+        face: BlockFace,
+    ) -> Option<(usize, usize, BlockFace)> {
+        let (chunk_i, block_i) =
+            self.locate_block(self.block_pos(chunk_i, block_i) + face.voffset())?;
+        Some((chunk_i, block_i, face))
+    }
+
+    /// Places a block, updating the faces of neighbouring chunks along the seam when it's on the
+    /// chunk's edge, and the block light around it.
+    fn place_block(&mut self, chunk_i: usize, block_i: usize, id: u32, dir: BlockFace) {
+        self.with_neighbours(chunk_i, |chunk, reg, neighbours| {
+            chunk.place_block(reg, block_i, id, dir, neighbours)
+        });
+        self.invalidate_mesh_cache(chunk_i);
+
+        let pos = self.loaded_chunks[chunk_i].pos;
+        for face in BlockFace::iter().filter(|face| face.is_edge(block_i)) {
+            let Some(neighbour_i) = self.chunk_index(pos + face.voffset()) else {
+                continue;
+            };
+            let j = Chunk::edge_neighbour_idx(block_i, face);
+            self.with_neighbours(neighbour_i, |neighbour, reg, neighbours| {
+                neighbour.refresh_face(reg, j, face.flip(), neighbours)
+            });
+            self.invalidate_mesh_cache(neighbour_i);
+        }
+
+        let changed = self.relight_block(self.block_pos(chunk_i, block_i));
+        self.refresh_lit_faces(&changed);
+    }
+
+    /// Writes every loaded chunk to `dir`, one file per chunk, along with the block name table
+    /// their ids refer to.
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(BLOCK_NAMES_FILE);
+        (self.registry.save_name_table(&path))
+            .with_context(|| format!("Failed to save {}", path.display()))?;
+        for chunk in &self.loaded_chunks {
+            let path = dir.join(Chunk::file_name(chunk.pos));
+            (chunk.save(&path)).with_context(|| format!("Failed to save {}", path.display()))?;
+        }
+        tracing::info!(
+            "Saved {} chunks to {}",
+            self.loaded_chunks.len(),
+            dir.display()
+        );
+        Ok(())
+    }
+
+    /// Writes the modified ones of the just unloaded `chunks` to `save_dir`, so they're loaded
+    /// back instead of generated again when they come back into range.
+    fn save_unloaded(&self, chunks: &[Chunk]) {
+        let Some(dir) = &self.save_dir else {
+            return;
+        };
+        let mut modified = chunks.iter().filter(|chunk| chunk.modified).peekable();
+        if modified.peek().is_none() {
+            return;
+        }
+        let result = fs::create_dir_all(dir).map_err(Error::from).and_then(|()| {
+            let path = dir.join(BLOCK_NAMES_FILE);
+            (self.registry.save_name_table(&path))
+                .with_context(|| format!("Failed to save {}", path.display()))?;
+            for chunk in modified {
+                let path = dir.join(Chunk::file_name(chunk.pos));
+                (chunk.save(&path))
+                    .with_context(|| format!("Failed to save {}", path.display()))?;
+            }
+            Ok(())
+        });
+        if let Err(err) = result {
+            tracing::error!("{err:#}");
+        }
+    }
+
+    /// The chunk at `pos` saved to `save_dir` when it was unloaded, if there is one. Its block
+    /// ids are remapped to the current registry like `load` does.
+    fn load_saved_chunk(&self, pos: glam::IVec3) -> Option<Chunk> {
+        let dir = self.save_dir.as_ref()?;
+        let path = dir.join(Chunk::file_name(pos));
+        if !path.exists() {
+            return None;
+        }
+        let names = dir.join(BLOCK_NAMES_FILE);
+        let result = (Chunk::load(&path))
+            .with_context(|| format!("Failed to load {}", path.display()))
+            .and_then(|mut chunk| {
+                if names.exists() {
+                    chunk.remap_ids(&self.registry, &self.registry.load_id_map(&names)?);
+                }
+                chunk.load_states(&self.registry);
+                Ok(chunk)
+            });
+        if let Err(err) = &result {
+            tracing::error!("{err:#}, generating the chunk instead");
+        }
+        result.ok()
+    }
+
+    /// Replaces the loaded chunks with the ones saved in `dir`, remapping their block ids to the
+    /// current registry. They're meshed again as they're drawn.
+    pub fn load(&mut self, dir: &Path) -> Result<()> {
+        let path = dir.join(BLOCK_NAMES_FILE);
+        let id_map = if path.exists() {
+            Some(self.registry.load_id_map(&path)?)
+        } else {
+            tracing::warn!(
+                "{} is missing, assuming the current block ids",
+                path.display()
+            );
+            None
+        };
+
+        let mut chunks = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "chunk") {
+                let mut chunk = (Chunk::load(&path))
+                    .with_context(|| format!("Failed to load {}", path.display()))?;
+                if let Some(id_map) = &id_map {
+                    chunk.remap_ids(&self.registry, id_map);
+                }
+                chunk.load_states(&self.registry);
+                chunks.push(chunk);
+            }
+        }
+        tracing::info!("Loaded {} chunks from {}", chunks.len(), dir.display());
+        self.loaded_chunks = chunks;
+        self.index_chunks();
+        // saved chunks already have their structures
+        self.pending_structure_edits.clear();
+        self.instance_buffer = None;
+        for pos in self.loaded_chunks.iter().map(|c| c.pos).collect::<Vec<_>>() {
+            self.recompute_light(pos);
+        }
+        Ok(())
+    }
+
+    /// Drops the cached mesh of an edited chunk.
+    fn invalidate_mesh_cache(&self, chunk_i: usize) {
+        let Some(dir) = &self.mesh_cache_dir else {
+            return;
+        };
+        let path = dir.join(Chunk::mesh_cache_file_name(self.loaded_chunks[chunk_i].pos));
+        if let Err(err) = fs::remove_file(&path) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("Failed to remove mesh cache {}: {err}", path.display());
+            }
+        }
+    }
+
+    /// Indices of the chunks waiting to be meshed, in the order they should be meshed. The
+    /// camera's own chunk always comes first.
+    pub fn mesh_queue(&self, cam: &Camera) -> Vec<usize> {
+        let cam_chunk = cam.pos.div_euclid(glam::Vec3::splat(32.0)).as_ivec3() + self.origin;
+        let dir = cam.dir();
+        let mut queue: Vec<_> = (self.loaded_chunks.iter().enumerate())
+            .filter(|(_, chunk)| chunk.needs_mesh())
+            .map(|(i, chunk)| {
+                if chunk.pos == cam_chunk {
+                    return (f32::NEG_INFINITY, i);
+                }
+                let center = (32 * (chunk.pos - self.origin)).as_vec3() + 16.0;
+                let to_chunk = center - cam.pos;
+                let score = self.mesh_priority.distance_weight * to_chunk.length()
+                    - self.mesh_priority.view_weight * dir.dot(to_chunk.normalize_or_zero());
+                (score, i)
+            })
+            .collect();
+        queue.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        queue.into_iter().map(|(_, i)| i).collect()
+    }
+
+    /// Advances the world by `delta_time` seconds around `cam`: loads and unloads chunks, meshes
+    /// the next chunks in the mesh queue, prepares the buffers `draw` binds, animates the block
+    /// textures and moves the particles. Call once a frame before `upload_meshes` and `draw`,
+    /// which only upload and record what it prepared.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        delta_time: f32,
+        cam: &Camera,
+    ) {
+        self.update_loaded_chunks(cam);
+        self.update_meshes(device, cam);
+        self.update_instances(device);
+        self.update_animations(queue, delta_time);
+        self.update_particles(device, queue, delta_time);
+    }
+
+    /// Meshes the next chunks in the mesh queue, at most `meshes_per_frame` of them, and grows
+    /// the shared index buffer to fit them.
+    fn update_meshes(&mut self, device: &wgpu::Device, cam: &Camera) {
+        for i in (self.mesh_queue(cam).into_iter()).take(self.meshes_per_frame) {
+            let cache_path = (self.mesh_cache_dir.as_ref())
+                .map(|dir| dir.join(Chunk::mesh_cache_file_name(self.loaded_chunks[i].pos)));
+            let greedy = self.greedy_meshing;
+            self.with_neighbours(i, |chunk, reg, neighbours| {
+                chunk.greedy = greedy;
+                chunk.update_flags(reg);
+                chunk.resolve_states(reg);
+                if (cache_path.as_ref())
+                    .is_some_and(|path| chunk.load_mesh_cache(reg, neighbours, path))
+                {
+                    return;
+                }
+                chunk.gen_mesh(reg, neighbours);
+                if let Some(path) = &cache_path {
+                    if let Err(err) = chunk.save_mesh_cache(reg, neighbours, path) {
+                        tracing::warn!("Failed to cache mesh of chunk {}: {err}", chunk.pos);
+                    }
+                }
+            });
+        }
+
+        self.reserve_indices(device);
+    }
+
+    /// Recreates the instance buffer with the offset of each loaded chunk from the origin, if
+    /// the loaded chunks or the origin changed since it was created.
+    fn update_instances(&mut self, device: &wgpu::Device) {
+        let instance_size = (mem::size_of::<ChunkInstance>() * self.loaded_chunks.len()) as u64;
+        if (self.instance_buffer.as_ref()).is_some_and(|b| b.size() == instance_size) {
+            return;
+        }
+        let instances: Vec<_> = (self.loaded_chunks.iter())
+            .map(|chunk| ChunkInstance {
+                offset: (32 * (chunk.pos - self.origin)).as_vec3(),
+            })
+            .collect();
+        self.instance_buffer = Some(
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Chunk Instance Buffer"),
+                contents: bytemuck::cast_slice(&instances),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            }),
+        );
+        // the bundles bind the old buffer, with the chunks at their old indices
+        self.invalidate_render_bundles();
+    }
+
+    /// Uploads the edited parts of chunk meshes and the translucent faces sorted away from
+    /// `cam`. Call after `update` and before the render pass drawing the world, the larger
+    /// uploads go through `staging_belt` so they have to be submitted with `encoder`.
+    pub fn upload_meshes(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut wgpu::util::StagingBelt,
+        cam: &Camera,
+    ) {
+        for chunk in &mut self.loaded_chunks {
+            chunk.upload_mesh(
+                device,
+                queue,
+                encoder,
+                staging_belt,
+                self.direct_upload_limit,
+            );
+            let cam_pos = cam.pos - (32 * (chunk.pos - self.origin)).as_vec3();
+            chunk.upload_translucent(device, queue, cam_pos);
+        }
+    }
+
+    pub fn draw<'a>(
+        &'a mut self,
+        device: &wgpu::Device,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        cam: &Camera,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    ) {
+        // nothing is ready to draw before the first `update`
+        if self.instance_buffer.is_none() {
+            return;
+        }
+
+        let frustum = cam.frustum();
+        let in_view = |chunk: &Chunk| {
+            let min = (32 * (chunk.pos - self.origin)).as_vec3();
+            frustum.intersects_aabb(min, min + 32.0)
+        };
+        let visible = self.visible_chunks(cam);
+        for &i in &visible {
+            let faces = self.loaded_chunks[i].vertices.len();
+            if (self.loaded_chunks[i].render_bundle)
+                .as_ref()
+                .is_none_or(|&(bundle_faces, _)| bundle_faces != faces)
+            {
+                let bundle =
+                    self.record_render_bundle(device, i, camera_bind_group, light_bind_group);
+                self.loaded_chunks[i].render_bundle = Some((faces, bundle));
+            }
+        }
+        render_pass.execute_bundles(
+            (visible.iter()).map(|&i| &self.loaded_chunks[i].render_bundle.as_ref().unwrap().1),
+        );
+        let faces: usize = (visible.iter())
+            .map(|&i| self.loaded_chunks[i].vertices.len())
+            .sum();
+        self.draw_stats = DrawStats {
+            chunks: visible.len(),
+            triangles: 2 * faces,
+        };
+
+        // executing bundles clears the pass's bindings
+        render_pass.set_bind_group(0, &self.block_bind_group, &[]);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, light_bind_group, &[]);
+        render_pass.set_bind_group(3, &self.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.as_ref().unwrap().slice(..));
+
+        // translucent faces are blended over everything behind them, farthest chunks first
+        let mut translucent: Vec<_> = (self.loaded_chunks.iter().enumerate())
+            .filter(|(_, chunk)| chunk.translucent_buffer.is_some())
+            .filter(|(_, chunk)| !chunk.translucent_vertices.is_empty() && in_view(chunk))
+            .map(|(i, chunk)| {
+                let center = (32 * (chunk.pos - self.origin)).as_vec3() + 16.0;
+                (center.distance_squared(cam.pos), i)
+            })
+            .collect();
+        translucent.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        render_pass.set_pipeline(&self.translucent_render_pipeline);
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for (_, i) in translucent {
+            let chunk = &self.loaded_chunks[i];
+            self.draw_stats.triangles += 2 * chunk.translucent_vertices.len();
+            render_pass.set_vertex_buffer(0, chunk.translucent_buffer.as_ref().unwrap().slice(..));
+            let instance = i as u32;
+            render_pass.draw_indexed(
+                0..(6 * chunk.translucent_vertices.len()) as _,
+                0,
+                instance..instance + 1,
+            );
+        }
+
+        (self.highlight).draw(render_pass, camera_bind_group, &self.block_bind_group);
+        (self.particles).draw(render_pass, camera_bind_group, &self.block_bind_group);
+    }
+
+    /// Indices of the loaded chunks with opaque meshes in the camera's view.
+    fn visible_chunks(&self, cam: &Camera) -> Vec<usize> {
+        let frustum = cam.frustum();
+        (0..self.loaded_chunks.len())
+            .filter(|&i| {
+                let chunk = &self.loaded_chunks[i];
+                let min = (32 * (chunk.pos - self.origin)).as_vec3();
+                chunk.vertex_buffer.is_some()
+                    && frustum.intersects_aabb(min, min + 32.0)
+                    && !(self.cull_hidden_chunks && self.is_chunk_hidden(chunk))
+            })
+            .collect()
+    }
+
+    /// Switches `draw` between shading every opaque face it draws and only the ones
+    /// `draw_depth_prepass` left in the scene depth.
+    pub fn set_depth_prepass(&mut self, enabled: bool) {
+        if self.depth_prepass != enabled {
+            self.depth_prepass = enabled;
+            // the bundles are recorded with the pipeline
+            self.invalidate_render_bundles();
+        }
+    }
+
+    /// Draws the opaque faces `draw` will draw into the scene depth, without shading them. Only
+    /// worth it when the scene pass is bound by its fragments.
+    pub fn draw_depth_prepass<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        cam: &Camera,
+        camera_bind_group: &'a wgpu::BindGroup,
+        light_bind_group: &'a wgpu::BindGroup,
+    ) {
+        let Some(instance_buffer) = &self.instance_buffer else {
+            return;
+        };
+        render_pass.set_pipeline(&self.depth_prepass_render_pipeline);
+        render_pass.set_bind_group(0, &self.block_bind_group, &[]);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, light_bind_group, &[]);
+        render_pass.set_bind_group(3, &self.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for i in self.visible_chunks(cam) {
+            let chunk = &self.loaded_chunks[i];
+            render_pass.set_vertex_buffer(0, chunk.vertex_buffer.as_ref().unwrap().slice(..));
+            let instance = i as u32;
+            render_pass.draw_indexed(
+                0..(6 * chunk.vertices.len()) as _,
+                0,
+                instance..instance + 1,
+            );
+        }
+    }
+
+    /// Draws the opaque faces of the chunks in `view_proj` into the shadow map, using the
+    /// instance buffer of the last `update`.
+    pub fn draw_shadows<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        shadow_bind_group: &'a wgpu::BindGroup,
+        view_proj: glam::Mat4,
+    ) {
+        let Some(instance_buffer) = &self.instance_buffer else {
+            return;
+        };
+        let frustum = Frustum::from_view_proj(view_proj);
+        render_pass.set_pipeline(&self.shadow_render_pipeline);
+        render_pass.set_bind_group(0, shadow_bind_group, &[]);
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for (i, chunk) in self.loaded_chunks.iter().enumerate() {
+            let min = (32 * (chunk.pos - self.origin)).as_vec3();
+            let Some(vertex_buffer) = &chunk.vertex_buffer else {
+                continue;
+            };
+            if chunk.vertices.is_empty() || !frustum.intersects_aabb(min, min + 32.0) {
+                continue;
+            }
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            let instance = i as u32;
+            render_pass.draw_indexed(
+                0..(6 * chunk.vertices.len()) as _,
+                0,
+                instance..instance + 1,
+            );
+        }
+    }
+
+    /// Bytes of GPU memory taken by the vertex and index buffers of the loaded chunks.
+    pub fn mesh_buffer_size(&self) -> u64 {
+        let vertex_buffers: u64 = (self.loaded_chunks.iter())
+            .flat_map(|chunk| [&chunk.vertex_buffer, &chunk.translucent_buffer])
+            .flatten()
+            .map(|buffer| buffer.size())
+            .sum();
+        vertex_buffers + self.index_buffer.size()
+    }
+
+    /// Bytes of memory taken by the blocks of the loaded chunks, see `ChunkBlocks`.
+    pub fn block_memory_size(&self) -> usize {
+        (self.loaded_chunks.iter())
+            .map(|chunk| chunk.blocks.memory_size())
+            .sum()
+    }
+
+    fn create_index_buffer(device: &wgpu::Device, face_capacity: usize) -> wgpu::Buffer {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Chunk Index Buffer"),
+            contents: bytemuck::cast_slice(
+                &(0..face_capacity as u32)
+                    .flat_map(|f| [0, 1, 2, 2, 3, 0].map(|i| 4 * f + i))
+                    .collect::<Vec<u32>>(),
+            ),
+            usage: wgpu::BufferUsages::INDEX,
+        })
+    }
+
+    /// Grows the shared index buffer to cover the largest opaque or translucent mesh of the
+    /// loaded chunks. It never shrinks, so the chunks drawn in a frame can't outgrow it as long
+    /// as this is called after they're meshed.
+    fn reserve_indices(&mut self, device: &wgpu::Device) {
+        let faces = (self.loaded_chunks.iter())
+            .map(|chunk| chunk.vertices.len().max(chunk.translucent_vertices.len()))
+            .max()
+            .unwrap_or(0);
+        if faces <= self.index_capacity {
+            return;
+        }
+        self.index_capacity = faces * 5 / 4;
+        tracing::info!(
+            "Growing the chunk index buffer to {} faces",
+            self.index_capacity
+        );
+        self.index_buffer = Self::create_index_buffer(device, self.index_capacity);
+        // the bundles bind the old buffer
+        self.invalidate_render_bundles();
+    }
+
+    /// Drops the recorded chunk render bundles, so they're recorded again with the current
+    /// buffers and bind groups the next time they're drawn.
+    pub fn invalidate_render_bundles(&mut self) {
+        for chunk in &mut self.loaded_chunks {
+            chunk.render_bundle = None;
+        }
+    }
+
+    /// Records drawing the opaque faces of the chunk at `chunk_i`, with the bind groups it's
+    /// drawn with. They're recorded once, so `invalidate_render_bundles` has to be called when
+    /// the bind groups are recreated.
+    fn record_render_bundle(
+        &self,
+        device: &wgpu::Device,
+        chunk_i: usize,
+        camera_bind_group: &wgpu::BindGroup,
+        light_bind_group: &wgpu::BindGroup,
+    ) -> wgpu::RenderBundle {
+        let chunk = &self.loaded_chunks[chunk_i];
+        let label = format!("Chunk {} Render Bundle", chunk.pos);
+        let mut encoder =
+            device.create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
+                label: Some(&label),
+                color_formats: &[Some(self.color_format)],
+                depth_stencil: Some(wgpu::RenderBundleDepthStencil {
+                    format: Texture::DEPTH_FORMAT,
+                    depth_read_only: false,
+                    stencil_read_only: true,
+                }),
+                sample_count: self.sample_count,
+                multiview: None,
+            });
+        encoder.set_pipeline(match self.depth_prepass {
+            true => &self.prepassed_render_pipeline,
+            false => &self.render_pipeline,
+        });
+        encoder.set_bind_group(0, &self.block_bind_group, &[]);
+        encoder.set_bind_group(1, camera_bind_group, &[]);
+        encoder.set_bind_group(2, light_bind_group, &[]);
+        encoder.set_bind_group(3, &self.uniform_bind_group, &[]);
+        encoder.set_vertex_buffer(0, chunk.vertex_buffer.as_ref().unwrap().slice(..));
+        encoder.set_vertex_buffer(1, self.instance_buffer.as_ref().unwrap().slice(..));
+        encoder.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        let instance = chunk_i as u32;
+        encoder.draw_indexed(
+            0..(6 * chunk.vertices.len()) as _,
+            0,
+            instance..instance + 1,
+        );
+        encoder.finish(&wgpu::RenderBundleDescriptor {
+            label: Some(&label),
+        })
+    }
+
+    pub fn window_event(
+        &mut self,
+        queue: &wgpu::Queue,
+        cam: &Camera,
+        player: Option<(glam::Vec3, glam::Vec3)>,
+        event: &WindowEvent,
+    ) -> bool {
+        match event {
+            WindowEvent::Focused(false) => {
+                // the button release goes to whichever window has focus now
+                self.mining = false;
+                false
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key @ (KeyCode::F7 | KeyCode::F8)),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                // F7 toggles the light debug view, F8 switches between sky and block light
+                let view = match (key, self.light_debug) {
+                    (KeyCode::F7, LightDebugView::Off) => LightDebugView::Sky,
+                    (KeyCode::F7, _) => LightDebugView::Off,
+                    (_, LightDebugView::Sky) => LightDebugView::Block,
+                    (_, LightDebugView::Block) => LightDebugView::Sky,
+                    (_, LightDebugView::Off) => return false,
+                };
+                self.set_light_debug(queue, view);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key @ (KeyCode::F5 | KeyCode::F9)),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let Some(dir) = self.save_dir.clone() else {
+                    return false;
+                };
+                let result = match key {
+                    KeyCode::F5 => self.save(&dir),
+                    _ => self.load(&dir),
+                };
+                if let Err(err) = result {
+                    tracing::error!("{err:#}");
+                }
+                true
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if DIGIT_KEYS.contains(key) => {
+                let slot = DIGIT_KEYS.iter().position(|k| k == key).unwrap();
+                if slot < self.hotbar.len() {
+                    self.selected_slot = slot;
+                }
+                true
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let scroll = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(pos) => pos.y as f32,
+                };
+                if scroll == 0.0 || self.hotbar.is_empty() {
+                    return false;
+                }
+                // scrolling down moves right, wrapping around at the ends
+                let step = match scroll < 0.0 {
+                    true => 1,
+                    false => self.hotbar.len() - 1,
+                };
+                self.selected_slot = (self.selected_slot + step) % self.hotbar.len();
+                true
+            }
+            WindowEvent::MouseInput {
+                button: MouseButton::Right,
+                state: ElementState::Pressed,
                 ..
             } => {
                 // tracing::info!("Button event: {:?}", event);
-                if let Some((chunk_i, block_i, face)) = self.raycast(cam.pos, cam.dir(), 6.0) {
-                    let Some(block_i) = block_i.checked_add_signed(face.ioffset()) else {
+                if !self.try_action(BlockAction::Place) {
+                    return true;
+                }
+                let placement = match self.target(cam, 6.0) {
+                    Some(hit) => self.adjacent_block(hit.chunk_i, hit.block_i, hit.face),
+                    None if self.place_in_air => self.air_placement(cam, 6.0, player),
+                    None => None,
+                };
+                if let Some((chunk_i, block_i, _)) = placement {
+                    let block_pos = self.block_pos(chunk_i, block_i);
+                    // the player would be stuck inside it
+                    if player.is_some_and(|aabb| Self::would_block_player(block_pos, aabb)) {
                         return true;
+                    }
+                    let id = self.selected_block();
+                    // directional blocks face the player, the rest aren't turned
+                    let dir = match self.registry.blocks[id as usize].is_directional() {
+                        true => cam.facing().flip(),
+                        false => BlockFace::Front,
                     };
-                    self.loaded_chunks[chunk_i].place_block(
-                        device,
-                        queue,
-                        &self.registry,
-                        block_i,
-                        1,
-                        face,
+                    self.place_block(chunk_i, block_i, id, dir);
+                    let sound = SoundId::new(
+                        self.registry.blocks[id as usize].sound_group,
+                        SoundKind::Place,
                     );
+                    (self.audio).play_at(sound, 1.0, block_pos.as_vec3() + 0.5);
                 }
                 true
             }
@@ -361,16 +2238,8 @@ impl World {
                 ..
             } => {
                 // tracing::info!("Button event: {:?}", event);
-                if let Some((chunk_i, block_i, face)) = self.raycast(cam.pos, cam.dir(), 6.0) {
-                    self.loaded_chunks[chunk_i].place_block(
-                        device,
-                        queue,
-                        &self.registry,
-                        block_i,
-                        0,
-                        face,
-                    );
-                }
+                // the block breaks in `update_mining` once it's been mined long enough
+                self.mining = true;
                 true
 
                 // tracing::info!(
@@ -416,7 +2285,377 @@ impl World {
                 //     bytemuck::cast_slice(&vertices),
                 // );
             }
+            WindowEvent::MouseInput {
+                button: MouseButton::Left,
+                state: ElementState::Released,
+                ..
+            } => {
+                self.mining = false;
+                true
+            }
             _ => false,
         }
     }
 }
+
+/// The parts of a `World` placing structures works on, borrowed apart from the rest of it.
+/// See `World::decorate_chunk`.
+struct Decorator<'a> {
+    terrain: &'a dyn TerrainGenerator,
+    blocks: &'a TerrainBlocks,
+    seed: u64,
+    chunks: &'a mut [Chunk],
+    chunk_indices: &'a HashMap<glam::IVec3, usize>,
+    pending: &'a mut HashMap<glam::IVec3, Vec<(usize, u32)>>,
+}
+
+impl Decorator<'_> {
+    /// Places the structures of the chunk at `pos` and of its neighbours reaching into it,
+    /// returns the blocks that changed by their position in the world.
+    fn decorate(&mut self, pos: glam::IVec3) -> Vec<glam::IVec3> {
+        let mut edits = self.structure_edits(pos);
+        for (x, z) in (-1..=1).flat_map(|x| (-1..=1).map(move |z| (x, z))) {
+            let neighbour = pos + glam::ivec3(x, 0, z);
+            if neighbour == pos {
+                continue;
+            }
+            let into_chunk = (self.structure_edits(neighbour).into_iter())
+                .filter(|(block_pos, _)| block_pos.div_euclid(glam::IVec3::splat(32)) == pos);
+            edits.extend(into_chunk);
+        }
+
+        for (block_pos, id) in edits {
+            let chunk_pos = block_pos.div_euclid(glam::IVec3::splat(32));
+            let block_i =
+                Chunk::block_pos_to_idx(block_pos.rem_euclid(glam::IVec3::splat(32)).as_uvec3());
+            (self.pending.entry(chunk_pos).or_default()).push((block_i, id));
+        }
+        self.apply_pending()
+    }
+
+    /// Blocks of the structures growing on the ground of the loaded chunk at `pos`, by their
+    /// position in the world.
+    fn structure_edits(&self, pos: glam::IVec3) -> Vec<(glam::IVec3, u32)> {
+        let Some(&chunk_i) = self.chunk_indices.get(&pos) else {
+            return Vec::new();
+        };
+        let chunk = &self.chunks[chunk_i];
+        let air = self.blocks.air;
+        let mut edits = Vec::new();
+        for (x, z) in (0..32).flat_map(|x| (0..32).map(move |z| (x, z))) {
+            // the top of the terrain, looking through structures already reaching into the column
+            let ground = (0..32).rev().find_map(|y| {
+                let block_pos = glam::uvec3(x, y, z);
+                let id = chunk.blocks[Chunk::block_pos_to_idx(block_pos)].id;
+                let is_ground = id != air && !structures::is_structure_block(self.blocks, id);
+                is_ground.then_some((block_pos, id))
+            });
+            let Some((ground, id)) = ground else {
+                continue;
+            };
+            let ground = 32 * pos + ground.as_ivec3();
+            // nothing grows under water, overhangs, or the chunk above when the column continues
+            // into it
+            if self.terrain.sample(self.blocks, ground + glam::IVec3::Y) != air {
+                continue;
+            }
+            let mut rng = SmallRng::seed_from_u64(World::position_seed(self.seed, ground));
+            let structure = structures::column_structure(self.blocks, ground, id, &mut rng);
+            edits.extend(structure);
+        }
+        edits
+    }
+
+    /// Writes the pending structure edits of every loaded chunk into its blocks. Returns the
+    /// blocks that changed, by their position in the world.
+    fn apply_pending(&mut self) -> Vec<glam::IVec3> {
+        let mut changed = Vec::new();
+        for (pos, edits) in mem::take(self.pending) {
+            let Some(&chunk_i) = self.chunk_indices.get(&pos) else {
+                self.pending.insert(pos, edits);
+                continue;
+            };
+            let chunk_blocks = &mut self.chunks[chunk_i].blocks;
+            for (block_i, id) in edits {
+                if structures::overrides(self.blocks, id, chunk_blocks[block_i].id) {
+                    chunk_blocks.modify(block_i, |block| block.id = id);
+                    changed.push(32 * pos + Chunk::block_idx_to_pos(block_i).as_ivec3());
+                }
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Physics;
+
+    #[test]
+    fn same_seed_and_position_generate_the_same_chunk() {
+        let seed = 0x1234_5678_9abc_def0;
+        let terrain = NoiseTerrain::new(seed);
+        let blocks = BlockRegistry::new(BlockRegistry::default_blocks(), Vec::new(), Vec::new())
+            .terrain_blocks;
+        let pos = glam::ivec3(3, 1, -2);
+        let chunk = World::generate_seeded_chunk(&terrain, &blocks, seed, pos);
+        let again = World::generate_seeded_chunk(&NoiseTerrain::new(seed), &blocks, seed, pos);
+        assert!(chunk.blocks.iter().eq(again.blocks.iter()));
+        assert_eq!(chunk.biomes, again.biomes);
+
+        let dirs = |chunk: &Chunk| chunk.blocks.iter().map(|b| b.dir).collect::<Vec<_>>();
+        let elsewhere = World::generate_seeded_chunk(&terrain, &blocks, seed, pos + glam::IVec3::X);
+        assert_ne!(dirs(&chunk), dirs(&elsewhere));
+
+        // the terrain and the block directions change with the seed, including its high bits
+        let heights =
+            |terrain: &NoiseTerrain| (0..64).map(|x| terrain.height(x, 0)).collect::<Vec<_>>();
+        for reseed in [seed + 1, seed ^ 1 << 40] {
+            let reseeded = World::generate_seeded_chunk(&terrain, &blocks, reseed, pos);
+            assert_ne!(dirs(&chunk), dirs(&reseeded), "{reseed:x}");
+            assert_ne!(
+                heights(&terrain),
+                heights(&NoiseTerrain::new(reseed)),
+                "{reseed:x}"
+            );
+        }
+    }
+
+    #[test]
+    fn desert_columns_are_topped_with_sand() {
+        let terrain = NoiseTerrain::new(42);
+        let registry = BlockRegistry::new(BlockRegistry::default_blocks(), Vec::new(), Vec::new());
+        let blocks = &registry.terrain_blocks;
+        let name = |pos| {
+            registry.blocks[terrain.sample(blocks, pos) as usize]
+                .name
+                .as_str()
+        };
+        // the first dry desert column along the x axis whose surface isn't a cave mouth
+        let surface = (0..1 << 16)
+            .step_by(8)
+            .filter(|&x| terrain.biome(x, 0) == Biome::Desert)
+            .map(|x| glam::ivec3(x, terrain.height(x, 0), 0))
+            .find(|&pos| name(pos) != "air" && name(pos + glam::IVec3::Y) == "air")
+            .expect("no desert along the x axis");
+        assert_eq!(name(surface), "sand");
+        assert_eq!(name(surface - glam::IVec3::Y), "sand");
+
+        // and so is the generated chunk
+        let chunk_pos = surface.div_euclid(glam::IVec3::splat(32));
+        let chunk = World::generate_seeded_chunk(&terrain, blocks, 42, chunk_pos);
+        let idx = Chunk::block_pos_to_idx(surface.rem_euclid(glam::IVec3::splat(32)).as_uvec3());
+        assert_eq!(chunk.blocks[idx].data(&registry).name, "sand");
+    }
+
+    #[test]
+    fn air_placement_rejects_cells_in_the_player() {
+        let eye = glam::vec3(0.5, 10.62, 0.5);
+        let player = Some(Physics::default().aabb(eye));
+        let down = glam::Vec3::NEG_Y;
+
+        // the feet and the body below the eye
+        assert_eq!(World::air_placement_cell(eye, down, 1.0, player), None);
+        assert_eq!(World::air_placement_cell(eye, down, 1.5, player), None);
+        assert_eq!(
+            World::air_placement_cell(eye, down, 2.0, player),
+            Some((glam::ivec3(0, 8, 0), BlockFace::Top)),
+        );
+        assert_eq!(
+            World::air_placement_cell(eye, glam::Vec3::X, 1.0, player),
+            Some((glam::ivec3(1, 10, 0), BlockFace::Left)),
+        );
+
+        // flying without a box only the camera's own cell is taken
+        assert_eq!(World::air_placement_cell(eye, down, 0.5, None), None);
+        assert!(World::air_placement_cell(eye, down, 1.0, None).is_some());
+    }
+
+    #[test]
+    fn rays_hit_blocks_across_chunk_boundaries() {
+        let terrain = FlatTerrain { surface: 10 };
+        let reg = BlockRegistry::new(BlockRegistry::default_blocks(), Vec::new(), Vec::new());
+        let blocks = &reg.terrain_blocks;
+        let chunks: HashMap<_, _> = [-1, 0, 1]
+            .into_iter()
+            .map(|x| {
+                let pos = glam::ivec3(x, 0, 0);
+                let chunk = Chunk::generate(pos, &terrain, blocks, &mut SmallRng::seed_from_u64(0));
+                (pos, chunk)
+            })
+            .collect();
+        let cast = |origin: glam::Vec3, dir: glam::Vec3, through_missing| {
+            World::cast_ray(origin, dir.normalize(), 32.0, through_missing, |pos| {
+                let chunk = chunks.get(&pos.div_euclid(glam::IVec3::splat(32)))?;
+                let block_pos = pos.rem_euclid(glam::IVec3::splat(32)).as_uvec3();
+                let id = chunk.blocks[Chunk::block_pos_to_idx(block_pos)].id;
+                Some((id != blocks.air).then_some(()))
+            })
+            .map(|(_, pos, face, _)| (pos, face))
+        };
+
+        // down onto the surface just past the seam, both ways
+        assert_eq!(
+            cast(
+                glam::vec3(30.5, 11.5, 0.5),
+                glam::vec3(1.0, -0.25, 0.0),
+                false
+            ),
+            Some((glam::ivec3(32, 10, 0), BlockFace::Top)),
+        );
+        assert_eq!(
+            cast(
+                glam::vec3(1.5, 11.5, 0.5),
+                glam::vec3(-1.0, -0.25, 0.0),
+                false
+            ),
+            Some((glam::ivec3(-1, 10, 0), BlockFace::Top)),
+        );
+
+        // from inside the ground the first block stepped into is over the seam
+        assert_eq!(
+            cast(glam::vec3(-0.5, 8.5, 0.5), glam::Vec3::X, false),
+            Some((glam::ivec3(0, 8, 0), BlockFace::Left)),
+        );
+
+        // missing chunks stop the ray unless it may pass through them
+        let from_above = glam::vec3(0.5, 33.5, 0.5);
+        let down = glam::vec3(0.0, -1.0, 0.0);
+        assert_eq!(cast(from_above, down, false), None);
+        assert_eq!(
+            cast(from_above, down, true),
+            Some((glam::ivec3(0, 10, 0), BlockFace::Top)),
+        );
+    }
+
+    #[test]
+    fn generation_follows_the_block_names_in_a_reordered_registry() {
+        let seed = 7;
+        let terrain = NoiseTerrain::new(seed);
+        let default = BlockRegistry::new(BlockRegistry::default_blocks(), Vec::new(), Vec::new());
+        let mut blocks = BlockRegistry::default_blocks();
+        blocks.reverse();
+        let reordered = BlockRegistry::new(blocks, Vec::new(), Vec::new());
+        assert_ne!(default.terrain_blocks, reordered.terrain_blocks);
+
+        // two neighbouring chunks, with their trees and boulders
+        let (a, b) = (
+            glam::ivec3(0, TERRAIN_LAYER, 0),
+            glam::ivec3(1, TERRAIN_LAYER, 0),
+        );
+        let names = |reg: &BlockRegistry| {
+            let blocks = &reg.terrain_blocks;
+            let mut chunks = vec![
+                World::generate_seeded_chunk(&terrain, blocks, seed, a),
+                World::generate_seeded_chunk(&terrain, blocks, seed, b),
+            ];
+            let chunk_indices = [(a, 0), (b, 1)].into_iter().collect();
+            let mut decorator = Decorator {
+                terrain: &terrain,
+                blocks,
+                seed,
+                chunks: &mut chunks,
+                chunk_indices: &chunk_indices,
+                pending: &mut HashMap::default(),
+            };
+            decorator.decorate(a);
+            decorator.decorate(b);
+            let blocks = chunks.iter().flat_map(|chunk| chunk.blocks.iter());
+            blocks
+                .map(|block| block.data(reg).name.clone())
+                .collect::<Vec<_>>()
+        };
+        let names_by_default = names(&default);
+        assert!(names_by_default.iter().any(|name| name == "oak_leaves"));
+        assert!(names_by_default == names(&reordered));
+
+        // the hotbar holds the same blocks
+        let hotbar_names = |reg: &BlockRegistry| {
+            let mut names: Vec<_> = (reg.default_hotbar().into_iter())
+                .map(|id| reg.blocks[id as usize].name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(hotbar_names(&default), hotbar_names(&reordered));
+        assert_eq!(default.default_hotbar().len(), default.blocks.len() - 2);
+    }
+
+    #[test]
+    fn targeting_through_transparent_blocks_passes_glass_and_water() {
+        let reg = BlockRegistry::new(BlockRegistry::default_blocks(), Vec::new(), Vec::new());
+        let terrain = FlatTerrain { surface: -1 };
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut chunk = Chunk::generate(glam::IVec3::ZERO, &terrain, &reg.terrain_blocks, &mut rng);
+        for (z, name) in [(4, "glass"), (3, "water"), (1, "cobblestone")] {
+            let idx = Chunk::block_pos_to_idx(glam::uvec3(5, 5, z));
+            (chunk.blocks).modify(idx, |block| block.id = reg.block_map[name]);
+        }
+        let target = |through_transparent| {
+            let origin = glam::vec3(5.5, 5.5, 10.5);
+            World::cast_ray(origin, glam::Vec3::NEG_Z, 16.0, false, |pos| {
+                let in_chunk =
+                    pos.cmpge(glam::IVec3::ZERO).all() && pos.cmplt(glam::IVec3::splat(32)).all();
+                in_chunk.then(|| {
+                    let id = chunk.blocks[Chunk::block_pos_to_idx(pos.as_uvec3())].id;
+                    World::stops_target(&reg, id, through_transparent).then_some(id)
+                })
+            })
+            .map(|(id, pos, _, _)| (id, pos))
+        };
+
+        assert_eq!(
+            target(false),
+            Some((reg.block_map["glass"], glam::ivec3(5, 5, 4)))
+        );
+        assert_eq!(
+            target(true),
+            Some((reg.block_map["cobblestone"], glam::ivec3(5, 5, 1)))
+        );
+    }
+
+    #[test]
+    fn neighbouring_chunks_decorate_the_same_in_either_order() {
+        let seed = 7;
+        let terrain = NoiseTerrain::new(seed);
+        let registry = BlockRegistry::new(BlockRegistry::default_blocks(), Vec::new(), Vec::new());
+        // generates and decorates the chunks at `order` one by one, like they load in
+        let decorate_in = |order: [glam::IVec3; 2]| {
+            let mut chunks = Vec::new();
+            let mut chunk_indices = HashMap::default();
+            let mut pending = HashMap::default();
+            let mut reached_next = false;
+            for pos in order {
+                chunk_indices.insert(pos, chunks.len());
+                let blocks = &registry.terrain_blocks;
+                chunks.push(World::generate_seeded_chunk(&terrain, blocks, seed, pos));
+                let mut decorator = Decorator {
+                    terrain: &terrain,
+                    blocks,
+                    seed,
+                    chunks: &mut chunks,
+                    chunk_indices: &chunk_indices,
+                    pending: &mut pending,
+                };
+                decorator.decorate(pos);
+                reached_next |= pending.contains_key(&order[1]);
+            }
+            assert!(!pending.contains_key(&order[0]));
+            let blocks = |pos| chunks[chunk_indices[&pos]].blocks.iter().cloned().collect();
+            let blocks: [Vec<_>; 2] = order.map(blocks);
+            (blocks, reached_next)
+        };
+
+        // a structure on the edge of the first chunk reaches into the second
+        let (a, b) = (
+            glam::ivec3(0, TERRAIN_LAYER, 0),
+            glam::ivec3(1, TERRAIN_LAYER, 0),
+        );
+        let ([a_first, b_second], reached) = decorate_in([a, b]);
+        let ([b_first, a_second], _) = decorate_in([b, a]);
+        assert!(reached);
+        assert!(a_first == a_second);
+        assert!(b_first == b_second);
+    }
+}
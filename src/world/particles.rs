@@ -0,0 +1,305 @@
+use std::{collections::VecDeque, mem};
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use wgpu::util::DeviceExt;
+
+use super::BlockTexture;
+use crate::{graphics::VertexBuffer, gui, texture::Texture};
+
+/// A particle as the shader draws it, a square turned towards the camera.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ParticleInstance {
+    pub position: glam::Vec3,
+    /// Width and height in blocks.
+    pub size: f32,
+    /// Rect of the registry's texture the particle shows.
+    pub tile: glam::Vec4,
+    /// Tint multiplied into the texture, blended in by its alpha.
+    pub color: [u8; 4],
+    pub layer: u32,
+    pub alpha: f32,
+    pub _pad1: u32,
+}
+
+impl VertexBuffer for ParticleInstance {
+    type Raw = Self;
+    fn to_raw(&self) -> Self {
+        *self
+    }
+    const DESC: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+        array_stride: mem::size_of::<Self>() as _,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &wgpu::vertex_attr_array![
+            3 => Float32x3, // position
+            4 => Float32, // size
+            5 => Float32x4, // tile
+            6 => Unorm8x4, // color
+            7 => Uint32, // layer
+            8 => Float32, // alpha
+        ],
+    };
+}
+
+#[derive(Debug, Clone)]
+struct Particle {
+    instance: ParticleInstance,
+    velocity: glam::Vec3,
+    /// Seconds since the particle was spawned and until it disappears.
+    age: f32,
+    lifetime: f32,
+}
+
+/// How break particles move and look.
+#[derive(Debug, Copy, Clone)]
+pub struct ParticleSettings {
+    /// Most particles alive at once, the oldest ones disappearing to make room for new ones.
+    pub max_count: usize,
+    /// Particles in the burst of a broken block.
+    pub burst_count: usize,
+    /// Downward acceleration in blocks per second squared.
+    pub gravity: f32,
+    /// Seconds the particles fade out over at the end of their lives.
+    pub fade_time: f32,
+}
+
+impl Default for ParticleSettings {
+    fn default() -> Self {
+        Self {
+            max_count: 1024,
+            burst_count: 24,
+            gravity: 16.0,
+            fade_time: 0.25,
+        }
+    }
+}
+
+/// Small textured squares simulated on the CPU, like the chips flying off a broken block. They
+/// fall, land on solid blocks and fade out.
+pub struct Particles {
+    pub settings: ParticleSettings,
+    /// Alive particles, oldest first.
+    particles: VecDeque<Particle>,
+    rng: SmallRng,
+
+    square_vertices: wgpu::Buffer,
+    square_indices: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+    /// Number of instances `instance_buffer` has room for.
+    instance_capacity: usize,
+    /// Number of instances written by the last `update`.
+    instance_count: usize,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl Particles {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        registry_bind_group_layout: &wgpu::BindGroupLayout,
+        sample_count: u32,
+    ) -> Self {
+        // the GUI's square, centered on the particle and scaled to its size in the shader
+        let corner = |x: f32, y: f32| gui::Vertex {
+            position: glam::vec2(x, y),
+            tex_coords: glam::vec2(0.5 + 0.5 * x, 0.5 - 0.5 * y),
+            color: [255; 4],
+        };
+        let square_vertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Particle Square Buffer"),
+            contents: bytemuck::cast_slice(&[
+                corner(-1.0, -1.0),
+                corner(1.0, -1.0),
+                corner(1.0, 1.0),
+                corner(-1.0, 1.0),
+            ]),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let square_indices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Particle Square Index Buffer"),
+            contents: bytemuck::cast_slice::<u16, _>(&[0, 1, 2, 0, 2, 3]),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let settings = ParticleSettings::default();
+
+        let module = device.create_shader_module(wgpu::include_wgsl!("particles.wgsl"));
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Particle Render Pipeline"),
+            layout: Some(
+                &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Particle Render Pipeline Layout"),
+                    bind_group_layouts: &[camera_bind_group_layout, registry_bind_group_layout],
+                    push_constant_ranges: &[],
+                }),
+            ),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[gui::Vertex::DESC, ParticleInstance::DESC],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            // turned towards the camera, so there's no back to cull
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::GreaterEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        });
+
+        Self {
+            settings,
+            particles: VecDeque::new(),
+            rng: SmallRng::from_entropy(),
+
+            square_vertices,
+            square_indices,
+            instance_buffer: Self::create_instance_buffer(device, settings.max_count),
+            instance_capacity: settings.max_count,
+            instance_count: 0,
+            render_pipeline,
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle Instance Buffer"),
+            size: (capacity.max(1) * mem::size_of::<ParticleInstance>()) as _,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    /// Throws a burst of chips of `texture` out of the block at `block_pos`, relative to the
+    /// origin.
+    pub fn spawn_burst(&mut self, block_pos: glam::IVec3, texture: BlockTexture) {
+        let center = block_pos.as_vec3() + 0.5;
+        for _ in 0..self.settings.burst_count {
+            let offset = glam::vec3(
+                self.rng.gen_range(-0.4..0.4),
+                self.rng.gen_range(-0.4..0.4),
+                self.rng.gen_range(-0.4..0.4),
+            );
+            // a random quarter of the texture, so the chips don't all look the same
+            let corner = glam::vec2(self.rng.gen_range(0.0..0.75), self.rng.gen_range(0.0..0.75));
+            let pos = texture.get(corner);
+            let size = 0.25 * texture.size;
+            let particle = Particle {
+                instance: ParticleInstance {
+                    position: center + offset,
+                    size: self.rng.gen_range(0.08..0.15),
+                    tile: glam::vec4(pos.x, pos.y, size.x, size.y),
+                    color: texture.color.0,
+                    layer: texture.layer,
+                    alpha: 1.0,
+                    _pad1: 0,
+                },
+                velocity: 3.0 * offset + glam::vec3(0.0, self.rng.gen_range(1.0..3.0), 0.0),
+                age: 0.0,
+                lifetime: self.rng.gen_range(0.5..1.2),
+            };
+            self.spawn(particle);
+        }
+    }
+
+    fn spawn(&mut self, particle: Particle) {
+        while self.settings.max_count <= self.particles.len() {
+            self.particles.pop_front();
+        }
+        if 0 < self.settings.max_count {
+            self.particles.push_back(particle);
+        }
+    }
+
+    /// Where each particle would move in the next `delta_time` seconds, in order, for finding
+    /// the ones about to land.
+    pub fn next_positions(&self, delta_time: f32) -> impl Iterator<Item = glam::Vec3> + '_ {
+        (self.particles.iter()).map(move |p| p.instance.position + delta_time * p.velocity)
+    }
+
+    /// Moves the particles and ages them, dropping the dead ones, and uploads them for drawing.
+    /// `blocked` says for each particle whether its next position is inside a solid block, where
+    /// it stops.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        delta_time: f32,
+        blocked: &[bool],
+    ) {
+        let settings = self.settings;
+        for (particle, &blocked) in self.particles.iter_mut().zip(blocked) {
+            if blocked {
+                particle.velocity = glam::Vec3::ZERO;
+            } else {
+                particle.instance.position += delta_time * particle.velocity;
+                particle.velocity.y -= delta_time * settings.gravity;
+            }
+            particle.age += delta_time;
+            let left = particle.lifetime - particle.age;
+            particle.instance.alpha = (left / settings.fade_time.max(1e-3)).clamp(0.0, 1.0);
+        }
+        self.particles.retain(|p| p.age < p.lifetime);
+
+        if self.instance_capacity < self.particles.len() {
+            self.instance_capacity = self.particles.len().next_power_of_two();
+            self.instance_buffer = Self::create_instance_buffer(device, self.instance_capacity);
+        }
+        let instances: Vec<_> = self.particles.iter().map(|p| p.instance).collect();
+        if !instances.is_empty() {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+        }
+        self.instance_count = instances.len();
+    }
+
+    /// Moves the particles along with the origin, by `offset` blocks.
+    pub fn shift(&mut self, offset: glam::Vec3) {
+        for particle in &mut self.particles {
+            particle.instance.position += offset;
+        }
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        registry_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if self.instance_count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, registry_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.square_vertices.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_index_buffer(self.square_indices.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..6, 0, 0..self.instance_count as u32);
+    }
+}
@@ -0,0 +1,70 @@
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) color: vec4<f32>,
+};
+
+struct InstanceInput {
+    @location(3) position: vec3<f32>,
+    @location(4) size: f32,
+    // position and size of the particle's rect in the texture
+    @location(5) tile: vec4<f32>,
+    @location(6) color: vec4<f32>,
+    @location(7) layer: u32,
+    @location(8) alpha: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) layer: u32,
+    @location(3) alpha: f32,
+};
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    view_position: vec3<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    // turn the square towards the camera, keeping it upright unless it's seen from right above
+    // or below
+    let to_camera = normalize(camera.view_position - instance.position);
+    var right = cross(vec3(0.0, 1.0, 0.0), to_camera);
+    if length(right) < 1e-3 {
+        right = vec3(1.0, 0.0, 0.0);
+    }
+    right = normalize(right);
+    let up = cross(to_camera, right);
+    let corner = 0.5 * instance.size * (model.position.x * right + model.position.y * up);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4(instance.position + corner, 1.0);
+    out.tex_coords = instance.tile.xy + model.tex_coords * instance.tile.zw;
+    out.color = model.color * vec4(mix(vec3(1.0), instance.color.rgb, instance.color.a), 1.0);
+    out.layer = instance.layer;
+    out.alpha = instance.alpha;
+    return out;
+}
+
+@group(1) @binding(0)
+var t_blocks: texture_2d_array<f32>;
+@group(1) @binding(1)
+var s_blocks: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = in.color * textureSample(t_blocks, s_blocks, in.tex_coords, in.layer);
+    // cut out the see-through texels instead of blending them, the chips are small enough
+    if color.a < 0.5 {
+        discard;
+    }
+    return vec4(color.rgb, in.alpha);
+}
@@ -0,0 +1,98 @@
+use super::World;
+use crate::audio::{SoundId, SoundKind};
+
+/// Slack when deciding which blocks a box overlaps, so a box resting exactly on a block boundary
+/// doesn't count as overlapping the block past it.
+const EPSILON: f32 = 1e-4;
+
+impl World {
+    /// Whether the block at `pos` relative to the origin stops moving boxes. Blocks in chunks
+    /// that aren't loaded don't.
+    pub fn is_solid(&self, pos: glam::IVec3) -> bool {
+        self.locate_block(pos).is_some_and(|(chunk_i, block_i)| {
+            !self.loaded_chunks[chunk_i].blocks[block_i]
+                .data(&self.registry)
+                .is_transparent()
+        })
+    }
+
+    /// Whether a solid block at `block_pos` relative to the origin would overlap the player's box,
+    /// given by its lowest and highest corners. A box touching the block's cell without going
+    /// into it, like when standing on it, doesn't count.
+    pub fn would_block_player(
+        block_pos: glam::IVec3,
+        (min, max): (glam::Vec3, glam::Vec3),
+    ) -> bool {
+        let cell_min = block_pos.as_vec3();
+        let cell_max = cell_min + glam::Vec3::ONE;
+        (min + EPSILON).cmplt(cell_max).all() && cell_min.cmplt(max - EPSILON).all()
+    }
+
+    /// Plays the step sound of the block under `feet`, the middle of the bottom of a box standing
+    /// on it.
+    pub fn play_step(&self, feet: glam::Vec3) {
+        let below = (feet - EPSILON * glam::Vec3::Y).floor().as_ivec3();
+        let Some((chunk_i, block_i)) = self.locate_block(below) else {
+            return;
+        };
+        let block = self.loaded_chunks[chunk_i].blocks[block_i].data(&self.registry);
+        let sound = SoundId::new(block.sound_group, SoundKind::Step);
+        self.audio.play_at(sound, 0.5, feet);
+    }
+
+    /// Moves a box of `size` standing at `pos`, the middle of its bottom face, by `velocity`,
+    /// the distance to move this frame. It's swept along one axis at a time, starting with the
+    /// vertical one, and stopped flush against the first solid block in its way. Returns the
+    /// resolved position.
+    ///
+    /// Only blocks the box moves into are considered, so a box already stuck in blocks can
+    /// still move out of them.
+    pub fn move_aabb(&self, pos: glam::Vec3, size: glam::Vec3, velocity: glam::Vec3) -> glam::Vec3 {
+        let mut pos = pos;
+        for axis in [1, 0, 2] {
+            let distance = velocity[axis];
+            if distance == 0.0 {
+                continue;
+            }
+            let min = pos - glam::vec3(size.x / 2.0, 0.0, size.z / 2.0);
+            let max = min + size;
+            // the blocks the box covers across the other two axes
+            let cover_min = (min + EPSILON).floor().as_ivec3();
+            let cover_max = (max - EPSILON).floor().as_ivec3();
+
+            // the layers of blocks the leading face passes into, nearest first
+            let layers: Vec<i32> = if 0.0 < distance {
+                let first = (max[axis] - EPSILON).floor() as i32 + 1;
+                let last = (max[axis] + distance - EPSILON).floor() as i32;
+                (first..=last).collect()
+            } else {
+                let first = (min[axis] + EPSILON).floor() as i32 - 1;
+                let last = (min[axis] + distance + EPSILON).floor() as i32;
+                (last..=first).rev().collect()
+            };
+
+            let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+            let mut moved = distance;
+            for layer in layers {
+                let blocked = (cover_min[u_axis]..=cover_max[u_axis]).any(|u| {
+                    (cover_min[v_axis]..=cover_max[v_axis]).any(|v| {
+                        let mut block = glam::IVec3::ZERO;
+                        block[axis] = layer;
+                        block[u_axis] = u;
+                        block[v_axis] = v;
+                        self.is_solid(block)
+                    })
+                });
+                if blocked {
+                    moved = match 0.0 < distance {
+                        true => layer as f32 - max[axis],
+                        false => layer as f32 + 1.0 - min[axis],
+                    };
+                    break;
+                }
+            }
+            pos[axis] += moved;
+        }
+        pos
+    }
+}
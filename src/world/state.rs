@@ -0,0 +1,141 @@
+use super::{BlockFace, BlockTexture};
+
+/// Type of the value of a block state property.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StateKind {
+    Bool,
+    U8,
+    /// Wide enough for ids, like the item a container holds.
+    U32,
+}
+
+impl StateKind {
+    /// Bytes the value takes in a block's data.
+    fn len(self) -> usize {
+        match self {
+            Self::Bool | Self::U8 => 1,
+            Self::U32 => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StateProperty {
+    pub name: &'static str,
+    pub kind: StateKind,
+    pub default: u32,
+}
+
+/// Properties a block's state is made of, packed one after another in its `ChunkBlock::data`.
+/// Blocks without data have every property at its default, so only blocks that were placed or
+/// changed need to store it.
+#[derive(Debug, Default, Clone)]
+pub struct StateSchema {
+    properties: Vec<StateProperty>,
+}
+
+impl StateSchema {
+    /// Adds a property after the existing ones.
+    pub fn with(mut self, name: &'static str, kind: StateKind, default: u32) -> Self {
+        debug_assert!(self.property(name).is_none(), "duplicate property {name}");
+        self.properties.push(StateProperty {
+            name,
+            kind,
+            default,
+        });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+
+    /// Bytes of data a block's state takes.
+    pub fn len(&self) -> usize {
+        self.properties.iter().map(|p| p.kind.len()).sum()
+    }
+
+    /// Offset of the property in the data, along with the property.
+    fn property(&self, name: &str) -> Option<(usize, &StateProperty)> {
+        let mut offset = 0;
+        for property in &self.properties {
+            if property.name == name {
+                return Some((offset, property));
+            }
+            offset += property.kind.len();
+        }
+        None
+    }
+
+    /// Data of a block with every property at its default, `None` for stateless blocks.
+    pub fn default_data(&self) -> Option<Box<[u8]>> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut data = vec![0; self.len()].into_boxed_slice();
+        for property in &self.properties {
+            self.write(&mut data, property.name, property.default);
+        }
+        Some(data)
+    }
+
+    /// Value of the property `name` in `data`, its default when the block has no data.
+    pub fn get(&self, data: Option<&[u8]>, name: &str) -> Option<u32> {
+        let (offset, property) = self.property(name)?;
+        let Some(data) = data.filter(|data| data.len() == self.len()) else {
+            return Some(property.default);
+        };
+        let bytes = &data[offset..offset + property.kind.len()];
+        Some(match property.kind {
+            StateKind::Bool | StateKind::U8 => bytes[0] as u32,
+            StateKind::U32 => u32::from_le_bytes(bytes.try_into().unwrap()),
+        })
+    }
+
+    /// Sets the property `name` in `data`, filling in the defaults first if the block has no
+    /// data. Returns whether the data changed.
+    pub fn set(&self, data: &mut Option<Box<[u8]>>, name: &str, value: u32) -> bool {
+        if self.property(name).is_none() {
+            debug_assert!(false, "unknown property {name}");
+            return false;
+        }
+        let mut new = (data.clone())
+            .filter(|data| data.len() == self.len())
+            .or_else(|| self.default_data())
+            .unwrap();
+        self.write(&mut new, name, value);
+        let changed = data.as_ref() != Some(&new);
+        *data = Some(new);
+        changed
+    }
+
+    fn write(&self, data: &mut [u8], name: &str, value: u32) {
+        let (offset, property) = self.property(name).unwrap();
+        let bytes = &mut data[offset..offset + property.kind.len()];
+        match property.kind {
+            StateKind::Bool => bytes[0] = (value != 0) as u8,
+            StateKind::U8 => bytes[0] = value as u8,
+            StateKind::U32 => bytes.copy_from_slice(&value.to_le_bytes()),
+        }
+    }
+
+    /// Checks data read from a save against the schema, resetting it to the defaults when it
+    /// doesn't fit, as when the block's properties changed since it was saved.
+    pub fn load(&self, data: Option<Box<[u8]>>) -> Option<Box<[u8]>> {
+        match data {
+            Some(data) if data.len() == self.len() => Some(data),
+            None if self.is_empty() => None,
+            _ => self.default_data(),
+        }
+    }
+}
+
+/// Texture replacing a face's when a property of the block's state has a value.
+#[derive(Debug, Clone)]
+pub struct StateTexture {
+    pub property: &'static str,
+    pub value: u32,
+    /// Face of the block, before it's turned to face its direction.
+    pub face: BlockFace,
+    pub texture: BlockTexture,
+}
@@ -0,0 +1,101 @@
+use rand::Rng;
+
+use super::TerrainBlocks;
+
+/// Chance of a grass or snow column growing a tree.
+const TREE_CHANCE: f64 = 0.015;
+/// Chance of a grass or dirt column getting a boulder, checked after trees.
+const BOULDER_CHANCE: f64 = 0.002;
+
+/// Blocks of the tree or boulder sitting on the ground block at `ground`, if its column gets
+/// one. `ground_id` is the id of the ground block, and `rng` must be seeded from the column so
+/// it always gets the same structure.
+pub fn column_structure(
+    blocks: &TerrainBlocks,
+    ground: glam::IVec3,
+    ground_id: u32,
+    rng: &mut impl Rng,
+) -> Vec<(glam::IVec3, u32)> {
+    let roll = rng.gen::<f64>();
+    let (grass, snow, dirt) = (blocks.grass, blocks.snow, blocks.dirt);
+    match ground_id {
+        id if (id == grass || id == snow) && roll < TREE_CHANCE => tree(blocks, ground, rng),
+        id if (id == grass || id == dirt) && roll < TREE_CHANCE + BOULDER_CHANCE => {
+            boulder(blocks, ground, rng)
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// A trunk of logs with a blob of leaves around its top, leaving out some of the blob's corners.
+fn tree(
+    blocks: &TerrainBlocks,
+    ground: glam::IVec3,
+    rng: &mut impl Rng,
+) -> Vec<(glam::IVec3, u32)> {
+    let height = rng.gen_range(4..=6);
+    let mut structure: Vec<_> = (1..=height)
+        .map(|y| (ground + glam::IVec3::Y * y, blocks.oak_log))
+        .collect();
+    for y in height - 2..=height + 1 {
+        let radius: i32 = if y < height { 2 } else { 1 };
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                let corner = x.abs() == radius && z.abs() == radius;
+                if corner && (y == height + 1 || rng.gen_bool(0.5)) {
+                    continue;
+                }
+                structure.push((ground + glam::ivec3(x, y, z), blocks.oak_leaves));
+            }
+        }
+    }
+    structure
+}
+
+/// A rough ball of mossy cobblestone half sunk into the ground.
+fn boulder(
+    blocks: &TerrainBlocks,
+    ground: glam::IVec3,
+    rng: &mut impl Rng,
+) -> Vec<(glam::IVec3, u32)> {
+    let radius: f32 = rng.gen_range(1.2..2.2);
+    let r = radius.ceil() as i32;
+    let mut structure = Vec::new();
+    for x in -r..=r {
+        for y in 0..=r {
+            for z in -r..=r {
+                let offset = glam::ivec3(x, y, z);
+                if offset.as_vec3().length() <= radius + rng.gen_range(-0.3..0.3) {
+                    structure.push((ground + offset, blocks.mossy_cobblestone));
+                }
+            }
+        }
+    }
+    structure
+}
+
+/// Rank of the blocks structures can replace, `None` for terrain, which they never do.
+fn rank(blocks: &TerrainBlocks, id: u32) -> Option<u8> {
+    // lowest first
+    let ranked = [
+        blocks.air,
+        blocks.oak_leaves,
+        blocks.oak_log,
+        blocks.mossy_cobblestone,
+    ];
+    ranked
+        .iter()
+        .position(|&ranked| ranked == id)
+        .map(|rank| rank as u8)
+}
+
+/// Whether a structure's `new` block replaces the `old` one. Overlapping structures keep the
+/// highest ranked block whichever is placed first, so chunks come out the same in any order.
+pub fn overrides(blocks: &TerrainBlocks, new: u32, old: u32) -> bool {
+    rank(blocks, old).is_some_and(|old| rank(blocks, new).is_some_and(|new| old < new))
+}
+
+/// Whether `id` is only placed by structures, and isn't part of the terrain under them.
+pub fn is_structure_block(blocks: &TerrainBlocks, id: u32) -> bool {
+    rank(blocks, id).is_some_and(|rank| rank > 0)
+}
@@ -0,0 +1,172 @@
+use ahash::HashMap;
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin, ScaleBias};
+
+use super::Biome;
+
+/// Highest block generated terrain fills with water where it dips below.
+const SEA_LEVEL: i32 = -20;
+
+/// Ids of the blocks terrain and the structures on it are made of, looked up by name once when
+/// the block registry is built, so generation doesn't depend on the order of the blocks.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TerrainBlocks {
+    pub air: u32,
+    pub water: u32,
+    pub cobblestone: u32,
+    pub dirt: u32,
+    pub grass: u32,
+    pub sand: u32,
+    pub snow: u32,
+    pub oak_log: u32,
+    pub oak_leaves: u32,
+    pub mossy_cobblestone: u32,
+}
+
+impl TerrainBlocks {
+    /// Looks the blocks up in `block_map`, panicking if one of them is missing.
+    pub fn new(block_map: &HashMap<String, u32>) -> Self {
+        let id = |name: &str| match block_map.get(name) {
+            Some(&id) => id,
+            None => panic!("No {name} block to generate terrain with"),
+        };
+        Self {
+            air: id("air"),
+            water: id("water"),
+            cobblestone: id("cobblestone"),
+            dirt: id("dirt"),
+            grass: id("grass"),
+            sand: id("sand"),
+            snow: id("snow"),
+            oak_log: id("oak_log"),
+            oak_leaves: id("oak_leaves"),
+            mossy_cobblestone: id("mossy_cobblestone"),
+        }
+    }
+}
+
+/// Picks the blocks of newly generated chunks, so the shape of the world can be swapped without
+/// touching the chunk code.
+pub trait TerrainGenerator {
+    /// Id of the block at `world_pos`, in blocks from the world's origin, one of `blocks`.
+    fn sample(&self, blocks: &TerrainBlocks, world_pos: glam::IVec3) -> u32;
+
+    /// Biome of the column at `x`, `z`, tinting its grass and leaves.
+    fn biome(&self, _x: i32, _z: i32) -> Biome {
+        Biome::Plains
+    }
+
+    /// Ids of the blocks of the chunk whose lowest corner is at `origin`, in the chunk's block
+    /// order. Override to share work between the blocks of a chunk.
+    fn fill_chunk(&self, blocks: &TerrainBlocks, origin: glam::IVec3) -> Vec<u32> {
+        (0..1 << 15)
+            .map(|i| origin + glam::ivec3(i & 31, i >> 5 & 31, i >> 10))
+            .map(|pos| self.sample(blocks, pos))
+            .collect()
+    }
+}
+
+/// Continents and rolling hills on top of them, cut through by caves, with the lowlands under
+/// water.
+pub struct NoiseTerrain {
+    /// Broad height differences between regions, in blocks.
+    continents: ScaleBias<f64, Fbm<Perlin>, 2>,
+    /// Local hills on top of the continents, in blocks.
+    hills: ScaleBias<f64, Fbm<Perlin>, 2>,
+    caves: Fbm<Perlin>,
+    /// Temperature of the columns, picking their biome.
+    climate: Perlin,
+    /// Cave noise above which blocks are carved out, higher making fewer and thinner caves.
+    pub cave_threshold: f64,
+}
+
+impl NoiseTerrain {
+    pub fn new(seed: u64) -> Self {
+        // the noise takes 32-bit seeds, folded from the world's
+        let seed = (seed ^ seed >> 32) as u32;
+        let continents = Fbm::<Perlin>::new(seed.wrapping_add(1))
+            .set_octaves(2)
+            .set_frequency(1.0 / 512.0);
+        let hills = Fbm::<Perlin>::new(seed)
+            .set_octaves(4)
+            .set_frequency(1.0 / 64.0);
+        let caves = Fbm::<Perlin>::new(seed.wrapping_add(2))
+            .set_octaves(2)
+            .set_frequency(1.0 / 24.0);
+        Self {
+            continents: ScaleBias::new(continents).set_scale(8.0).set_bias(-16.0),
+            hills: ScaleBias::new(hills).set_scale(6.0),
+            caves,
+            climate: Perlin::new(seed.wrapping_add(3)),
+            cave_threshold: 0.45,
+        }
+    }
+
+    /// Height of the top block of the column at `x`, `z`.
+    pub fn height(&self, x: i32, z: i32) -> i32 {
+        let column = [x as f64, z as f64];
+        (self.continents.get(column) + self.hills.get(column)).floor() as i32
+    }
+
+    fn block(&self, blocks: &TerrainBlocks, pos: glam::IVec3, surface: i32, biome: Biome) -> u32 {
+        let is_cave = || self.cave_threshold < self.caves.get(pos.as_dvec3().to_array());
+        match (pos.y, biome) {
+            (y, _) if surface < y && y <= SEA_LEVEL => blocks.water,
+            (y, _) if surface < y => blocks.air,
+            // caves stay closed under the sea, so they aren't dry holes in the seabed
+            _ if (SEA_LEVEL < surface || pos.y < surface - 4) && is_cave() => blocks.air,
+            (y, Biome::Desert) if surface - 3 <= y => blocks.sand,
+            (y, Biome::Snow) if surface == y => blocks.snow,
+            (y, _) if surface == y => blocks.grass,
+            (y, _) if surface - 3 <= y => blocks.dirt,
+            _ => blocks.cobblestone,
+        }
+    }
+}
+
+impl TerrainGenerator for NoiseTerrain {
+    fn sample(&self, blocks: &TerrainBlocks, world_pos: glam::IVec3) -> u32 {
+        let (x, z) = (world_pos.x, world_pos.z);
+        self.block(blocks, world_pos, self.height(x, z), self.biome(x, z))
+    }
+
+    fn biome(&self, x: i32, z: i32) -> Biome {
+        let temperature = self.climate.get([x as f64 / 384.0, z as f64 / 384.0]);
+        match temperature {
+            t if t < -0.3 => Biome::Snow,
+            t if 0.3 < t => Biome::Desert,
+            _ => Biome::Plains,
+        }
+    }
+
+    fn fill_chunk(&self, blocks: &TerrainBlocks, origin: glam::IVec3) -> Vec<u32> {
+        let columns: Vec<_> = (0..32 * 32)
+            .map(|i| {
+                let (x, z) = (origin.x + (i & 31), origin.z + (i >> 5));
+                (self.height(x, z), self.biome(x, z))
+            })
+            .collect();
+        (0..1 << 15)
+            .map(|i| {
+                let offset = glam::ivec3(i & 31, i >> 5 & 31, i >> 10);
+                let (surface, biome) = columns[(offset.x + 32 * offset.z) as usize];
+                self.block(blocks, origin + offset, surface, biome)
+            })
+            .collect()
+    }
+}
+
+/// The same layers everywhere: grass at `surface` on a few blocks of dirt, cobblestone below.
+pub struct FlatTerrain {
+    pub surface: i32,
+}
+
+impl TerrainGenerator for FlatTerrain {
+    fn sample(&self, blocks: &TerrainBlocks, world_pos: glam::IVec3) -> u32 {
+        match world_pos.y {
+            y if self.surface < y => blocks.air,
+            y if self.surface == y => blocks.grass,
+            y if self.surface - 3 <= y => blocks.dirt,
+            _ => blocks.cobblestone,
+        }
+    }
+}